@@ -7,6 +7,7 @@ pub mod parametric_curve;
 pub mod parametric_surface;
 pub mod point_types;
 pub mod power_basis_curve;
+pub mod random;
 pub mod surface;
 pub mod utility;
 pub mod vector_3;
\ No newline at end of file
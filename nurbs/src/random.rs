@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+
+// Seed every thread starts with, so programs that never seed are still reproducible
+const DEFAULT_SEED: u64 = 0x5EED_5EED_5EED;
+
+// xoshiro256** seeded through SplitMix64. Small, fast and, unlike the thread RNG from the
+// rand crate, produces the same sequence on every run and platform.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut mix = seed;
+        let mut next = || {
+            mix = mix.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            split_mix(mix)
+        };
+        Rng {
+            state: [next(), next(), next(), next()],
+        }
+    }
+
+    // An independent generator for one of many streams of `seed`, e.g. one per pixel
+    pub fn for_stream(seed: u64, stream: u64) -> Rng {
+        Rng::new(split_mix(
+            seed ^ split_mix(stream.wrapping_add(0xD1B5_4A32_D192_ED03)),
+        ))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+fn split_mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+thread_local! {
+    static THREAD_RNG: RefCell<Rng> = RefCell::new(Rng::new(DEFAULT_SEED));
+}
+
+// Restarts the calling thread's generator, which backs `random_f64` and the `Vec3::random*`
+// functions
+pub fn seed(seed: u64) {
+    THREAD_RNG.with(|rng| *rng.borrow_mut() = Rng::new(seed));
+}
+
+// Restarts the calling thread's generator on one stream of `seed`
+pub fn seed_stream(seed: u64, stream: u64) {
+    THREAD_RNG.with(|rng| *rng.borrow_mut() = Rng::for_stream(seed, stream));
+}
+
+// Uniform in [0, 1) from the calling thread's generator
+pub fn random_f64() -> f64 {
+    THREAD_RNG.with(|rng| rng.borrow_mut().next_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::random::{random_f64, seed, seed_stream, Rng};
+
+    #[test]
+    fn test_reproducible() {
+        seed(42);
+        let first: Vec<f64> = (0..16).map(|_| random_f64()).collect();
+        seed(42);
+        let second: Vec<f64> = (0..16).map(|_| random_f64()).collect();
+        assert_eq!(first, second);
+
+        seed_stream(42, 7);
+        let stream: Vec<f64> = (0..16).map(|_| random_f64()).collect();
+        assert_ne!(first, stream);
+        let mut rng = Rng::for_stream(42, 7);
+        assert!(stream.iter().all(|&value| value == rng.next_f64()));
+    }
+
+    #[test]
+    fn test_range() {
+        let mut rng = Rng::new(1);
+        let mut sum = 0.0;
+        for _ in 0..10000 {
+            let value = rng.next_f64();
+            assert!((0.0..1.0).contains(&value));
+            sum += value;
+        }
+        assert!((sum / 10000.0 - 0.5).abs() < 0.02);
+    }
+}
@@ -1,11 +1,11 @@
-use rand;
+use crate::random::random_f64;
 
 pub fn degree_to_radians(degree: f64) -> f64 {
     degree * std::f64::consts::PI / 180.0
 }
 
 pub fn random_f64_range(min: f64, max: f64) -> f64 {
-    ((max - min) * rand::random::<f64>()) + min
+    ((max - min) * random_f64()) + min
 }
 
 pub fn random_u32_range(min: u32, max: u32) -> u32 {
@@ -18,4 +18,4 @@ pub fn f64_equal(a: f64, b: f64) -> bool {
 
 pub fn f64_near(a: f64, b: f64, epsilon: f64) -> bool {
     (a - b).abs() < epsilon
-}
\ No newline at end of file
+}
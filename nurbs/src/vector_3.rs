@@ -1,4 +1,5 @@
 use core::ops;
+use crate::random::random_f64;
 use crate::utility::random_f64_range;
 use std::f64::consts::PI;
 
@@ -78,9 +79,9 @@ impl Vec3 {
 
     pub fn random() -> Vec3 {
         Vec3 {
-            x: rand::random::<f64>(),
-            y: rand::random::<f64>(),
-            z: rand::random::<f64>(),
+            x: random_f64(),
+            y: random_f64(),
+            z: random_f64(),
         }
     }
 
@@ -123,8 +124,14 @@ impl Vec3 {
     }
 
     pub fn random_cosine_direction() -> Vec3 {
-        let r1 = rand::random::<f64>();
-        let r2 = rand::random::<f64>();
+        Vec3::cosine_direction(random_f64(), random_f64())
+    }
+
+    // The functions below warp a pair of uniform numbers in [0, 1) onto a domain, so the
+    // stratification of low discrepancy samples carries over
+
+    // Cosine weighted direction on the hemisphere around +z
+    pub fn cosine_direction(r1: f64, r2: f64) -> Vec3 {
         let sqrt_r2 = r2.sqrt();
 
         let phi = 2.0 * PI * r1;
@@ -135,6 +142,30 @@ impl Vec3 {
         Vec3::new(x, y, z)
     }
 
+    // Uniformly distributed direction on the unit sphere
+    pub fn on_unit_sphere(r1: f64, r2: f64) -> Vec3 {
+        let z = 1.0 - 2.0 * r2;
+        let radius = f64::sqrt(f64::max(0.0, 1.0 - z * z));
+        let phi = 2.0 * PI * r1;
+        Vec3::new(radius * phi.cos(), radius * phi.sin(), z)
+    }
+
+    // Shirley and Chiu's concentric mapping of the square onto the unit disk in the xy plane,
+    // which keeps neighbouring samples close together
+    pub fn in_unit_disk(r1: f64, r2: f64) -> Vec3 {
+        let a = 2.0 * r1 - 1.0;
+        let b = 2.0 * r2 - 1.0;
+        if a == 0.0 && b == 0.0 {
+            return Vec3::empty();
+        }
+        let (radius, phi) = if a.abs() > b.abs() {
+            (a, 0.25 * PI * (b / a))
+        } else {
+            (b, 0.5 * PI - 0.25 * PI * (a / b))
+        };
+        Vec3::new(radius * phi.cos(), radius * phi.sin(), 0.0)
+    }
+
     pub fn reflect(&self, normal: &Vec3) -> Vec3 {
         self.clone() - (2.0 * self.dot(normal) * normal)
     }
@@ -519,4 +550,23 @@ mod tests {
         assert_eq!(vec.y, 2.0);
         assert_eq!(vec.z, 3.0);
     }
+
+    #[test]
+    fn test_sample_warps() {
+        for i in 0..8 {
+            for j in 0..8 {
+                let r1 = (i as f64 + 0.5) / 8.0;
+                let r2 = (j as f64 + 0.5) / 8.0;
+                let cosine = Vec3::cosine_direction(r1, r2);
+                assert!((cosine.length() - 1.0).abs() < 1e-12);
+                assert!(cosine.z >= 0.0);
+                assert!((Vec3::on_unit_sphere(r1, r2).length() - 1.0).abs() < 1e-12);
+                let disk = Vec3::in_unit_disk(r1, r2);
+                assert!(disk.length() <= 1.0 && disk.z == 0.0);
+            }
+        }
+        // The corners of the square map onto the rim of the disk
+        assert!((Vec3::in_unit_disk(1.0, 1.0).length() - 1.0).abs() < 1e-12);
+        assert!((Vec3::in_unit_disk(0.0, 0.5).x + 1.0).abs() < 1e-12);
+    }
 }
@@ -71,6 +71,36 @@ impl AABB {
         hit
     }
 
+    pub fn centroid(&self) -> Vec3 {
+        Vec3::new(
+            0.5 * (self.x.min() + self.x.max()),
+            0.5 * (self.y.min() + self.y.max()),
+            0.5 * (self.z.min() + self.z.max()),
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size().max(0.0);
+        let dy = self.y.size().max(0.0);
+        let dz = self.z.size().max(0.0);
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    // Index of the axis with the largest extent
+    pub fn longest_axis(&self) -> u32 {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
     pub fn pad(&self) -> AABB {
         let delta = 0.0001;
         let x = if self.x.size() < delta {
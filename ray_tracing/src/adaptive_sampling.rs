@@ -0,0 +1,216 @@
+use crate::color::luminance;
+use nurbs::vector_3::Vec3;
+
+// Pixels darker than this are compared against it instead of their own brightness, so the
+// relative error of near black pixels doesn't keep them sampling forever
+const DARK_LUMINANCE: f64 = 0.01;
+
+// Running estimate of one pixel: the sum of its radiance samples and Welford's running mean
+// and variance of their luminance
+#[derive(Debug, Clone, Copy)]
+pub struct PixelEstimate {
+    sum: Vec3,
+    count: u32,
+    mean: f64,
+    m2: f64,
+}
+
+impl PixelEstimate {
+    pub fn new() -> PixelEstimate {
+        PixelEstimate {
+            sum: Vec3::empty(),
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, color: Vec3) {
+        self.sum += color;
+        self.count += 1;
+        let l = luminance(color);
+        let l = if l.is_finite() { l } else { 0.0 };
+        let delta = l - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (l - self.mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    // Mean radiance of the samples so far
+    pub fn color(&self) -> Vec3 {
+        if self.count == 0 {
+            Vec3::empty()
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    // Standard deviation of a single sample's luminance relative to the mean
+    pub fn relative_deviation(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        variance.sqrt() / self.mean.max(DARK_LUMINANCE)
+    }
+
+    // Variance of the mean luminance, infinite until there are two samples
+    pub fn mean_variance(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        self.m2 / (self.count - 1) as f64 / self.count as f64
+    }
+
+    // Standard error of the mean luminance relative to the luminance itself
+    pub fn relative_error(&self) -> f64 {
+        self.relative_deviation() / (self.count as f64).sqrt()
+    }
+}
+
+impl Default for PixelEstimate {
+    fn default() -> Self {
+        PixelEstimate::new()
+    }
+}
+
+// Settings for spending the sample budget where the image is noisy. Every pixel first takes
+// `min_samples`, then the pixels above the error threshold keep sampling in passes until they
+// converge, reach `max_factor` times samples_per_pixel or the budget of samples_per_pixel
+// samples per pixel on average is used up. Each pass hands out half of the remaining budget in
+// proportion to the pixels' relative standard deviation, which is the split that minimises the
+// summed relative variance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    // Relative standard error of a pixel's luminance at which it stops
+    pub threshold: f64,
+    // Samples taken before a pixel's error estimate is trusted
+    pub min_samples: u32,
+    // Cap on a single pixel in multiples of samples_per_pixel
+    pub max_factor: u32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(threshold: f64) -> AdaptiveSampling {
+        AdaptiveSampling {
+            threshold,
+            min_samples: 16,
+            max_factor: 8,
+        }
+    }
+
+    // Samples every pixel takes in the first pass
+    pub fn first_batch(&self, samples_per_pixel: u32) -> u32 {
+        self.min_samples.clamp(1, samples_per_pixel.max(1))
+    }
+
+    // Samples for each pixel in the next pass, all zero once the image has converged or the
+    // budget is spent
+    pub fn next_batches(&self, estimates: &[PixelEstimate], samples_per_pixel: u32) -> Vec<u32> {
+        let mut batches = vec![0; estimates.len()];
+        let max_samples = samples_per_pixel.max(1) * self.max_factor.max(1);
+        let noisy: Vec<usize> = (0..estimates.len())
+            .filter(|&index| {
+                let estimate = &estimates[index];
+                estimate.count() < max_samples && estimate.relative_error() > self.threshold
+            })
+            .collect();
+        if noisy.is_empty() {
+            return batches;
+        }
+
+        let budget = estimates.len() as u64 * samples_per_pixel as u64;
+        let used: u64 = estimates
+            .iter()
+            .map(|estimate| estimate.count() as u64)
+            .sum();
+        let remaining = budget.saturating_sub(used);
+        if remaining < noisy.len() as u64 {
+            return batches;
+        }
+        // Keep half back while there is plenty left, so it goes out with fresher estimates
+        let pass_budget = if remaining > 4 * noisy.len() as u64 {
+            remaining / 2
+        } else {
+            remaining
+        };
+
+        // A pixel with a single sample has no deviation yet, it is weighted like a typical one
+        let weight = |index: usize| {
+            let deviation = estimates[index].relative_deviation();
+            if deviation.is_finite() {
+                deviation
+            } else {
+                1.0
+            }
+        };
+        let total_weight: f64 = noisy.iter().map(|&index| weight(index)).sum();
+        for index in noisy {
+            let share = (pass_budget as f64 * weight(index) / total_weight) as u64;
+            let left = (max_samples - estimates[index].count()) as u64;
+            batches[index] = share.min(left) as u32;
+        }
+        batches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(values: &[f64]) -> PixelEstimate {
+        let mut estimate = PixelEstimate::new();
+        for &value in values {
+            estimate.add(Vec3::new(value, value, value));
+        }
+        estimate
+    }
+
+    #[test]
+    fn running_variance() {
+        let values = [0.2, 0.9, 0.4, 0.4, 1.6, 0.1];
+        let pixel = estimate(&values);
+        let mean = values.iter().sum::<f64>() / 6.0;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / 5.0;
+        assert!((pixel.color().x - mean).abs() < 1e-12);
+        assert!((pixel.relative_error() - (variance / 6.0).sqrt() / mean).abs() < 1e-12);
+        assert_eq!(estimate(&[0.5; 8]).relative_error(), 0.0);
+        assert_eq!(estimate(&[0.5]).relative_error(), f64::INFINITY);
+    }
+
+    #[test]
+    fn budget_moves_to_noisy_pixels() {
+        let adaptive = AdaptiveSampling::new(0.05);
+        let flat = estimate(&[0.5; 16]);
+        let noisy = estimate(&[0.0, 2.0].repeat(8));
+        let mut estimates = vec![flat; 6];
+        estimates.push(noisy);
+        estimates.push(noisy);
+
+        // 8 pixels with a budget of 32 each, 8 * 16 left of which half goes to the noisy ones
+        let batches = adaptive.next_batches(&estimates, 32);
+        assert_eq!(batches, [0, 0, 0, 0, 0, 0, 32, 32]);
+
+        // The noisier pixel gets the larger share
+        estimates[7] = estimate(&[0.8, 1.2].repeat(8));
+        let batches = adaptive.next_batches(&estimates, 32);
+        assert!(batches[6] > 3 * batches[7] && batches[7] > 0);
+
+        // With a cap of 64 samples the first noisy pixel only has 4 to go
+        let capped = AdaptiveSampling {
+            max_factor: 2,
+            ..adaptive
+        };
+        estimates[6] = estimate(&[0.0, 2.0].repeat(30));
+        assert_eq!(capped.next_batches(&estimates, 32)[6], 4);
+
+        let converged = vec![flat; 8];
+        assert!(adaptive
+            .next_batches(&converged, 32)
+            .iter()
+            .all(|&batch| batch == 0));
+    }
+}
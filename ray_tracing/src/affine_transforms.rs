@@ -1,8 +1,10 @@
 use crate::aabb::AABB;
+use crate::bvh_node::BVHStats;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::mat4::Mat4;
 use crate::ray::Ray3;
-use nurbs::{utility, vector_3::Vec3};
+use nurbs::vector_3::Vec3;
 use std::f64::INFINITY;
 use std::sync::Arc;
 
@@ -13,10 +15,16 @@ pub struct Translate {
 }
 
 pub struct RotateY {
+    transform: Transform,
+}
+
+// An arbitrary invertible affine transform of an object. The object is intersected in its
+// own space and the results are mapped back with `matrix`.
+pub struct Transform {
     object: Arc<dyn Hittable + Sync + Send>,
     bbox: AABB,
-    sin_theta: f64,
-    cos_theta: f64,
+    matrix: Mat4,
+    inverse: Mat4,
 }
 
 impl Hittable for Translate {
@@ -29,6 +37,7 @@ impl Hittable for Translate {
 
         if self.object.hit(&offset_ray, time, hit_record) {
             hit_record.point += self.offset;
+            hit_record.object = HitRecord::object_id(self);
             true
         } else {
             false
@@ -38,33 +47,61 @@ impl Hittable for Translate {
     fn bounding_box(&self) -> AABB {
         self.bbox.copy()
     }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(&(*origin - self.offset), direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.object.random(&(*origin - self.offset))
+    }
+
+    fn bvh_stats(&self) -> Option<BVHStats> {
+        self.object.bvh_stats()
+    }
 }
 
 impl Hittable for RotateY {
     fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
-        let mut origin = ray_in.origin();
-        let mut direction = ray_in.direction();
-
-        origin.x = self.cos_theta * ray_in.origin().x - self.sin_theta * ray_in.origin().z;
-        origin.z = self.sin_theta * ray_in.origin().x + self.cos_theta * ray_in.origin().z;
+        self.transform.hit(ray_in, time, hit_record)
+    }
 
-        direction.x = self.cos_theta * ray_in.direction().x - self.sin_theta * ray_in.direction().z;
-        direction.z = self.sin_theta * ray_in.direction().x + self.cos_theta * ray_in.direction().z;
+    fn bounding_box(&self) -> AABB {
+        self.transform.bounding_box()
+    }
 
-        let rotated_ray = Ray3::new(origin, direction, ray_in.time());
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.transform.pdf_value(origin, direction)
+    }
 
-        if self.object.hit(&rotated_ray, time, hit_record) {
-            let mut point = hit_record.point.clone();
-            point.x = self.cos_theta * hit_record.point.x + self.sin_theta * hit_record.point.z;
-            point.z = -self.sin_theta * hit_record.point.x + self.cos_theta * hit_record.point.z;
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.transform.random(origin)
+    }
 
-            let mut normal = hit_record.normal.clone();
-            normal.x = self.cos_theta * hit_record.normal.x + self.sin_theta * hit_record.normal.z;
-            normal.z = -self.sin_theta * hit_record.normal.x + self.cos_theta * hit_record.normal.z;
+    fn bvh_stats(&self) -> Option<BVHStats> {
+        self.transform.bvh_stats()
+    }
+}
 
-            hit_record.point = point;
-            hit_record.normal = normal;
+impl Hittable for Transform {
+    fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        // The direction is not renormalized so hit times are the same in both spaces
+        let local_ray = Ray3::new(
+            self.inverse.transform_point(ray_in.origin()),
+            self.inverse.transform_vector(ray_in.direction()),
+            ray_in.time(),
+        );
 
+        if self.object.hit(&local_ray, time, hit_record) {
+            // n . d is preserved by the inverse transpose, so front_face stays valid
+            hit_record.point = self.matrix.transform_point(hit_record.point);
+            hit_record.normal = self
+                .inverse
+                .transform_normal_by_inverse(hit_record.normal)
+                .unit_vector();
+            hit_record.dpdu = self.matrix.transform_vector(hit_record.dpdu);
+            hit_record.dpdv = self.matrix.transform_vector(hit_record.dpdv);
+            hit_record.object = HitRecord::object_id(self);
             true
         } else {
             false
@@ -74,6 +111,27 @@ impl Hittable for RotateY {
     fn bounding_box(&self) -> AABB {
         self.bbox.copy()
     }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let local_origin = self.inverse.transform_point(*origin);
+        let local_direction = self.inverse.transform_vector(*direction);
+        let local_pdf = self.object.pdf_value(&local_origin, &local_direction);
+
+        // Change of variables between world and object space solid angles. For a unit
+        // direction w mapped by the linear part A, dw_local / dw = |det A| / |A w|^3
+        let stretch = local_direction.length() / direction.length();
+        local_pdf * self.inverse.determinant_3x3().abs() / (stretch * stretch * stretch)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        let local_origin = self.inverse.transform_point(*origin);
+        self.matrix
+            .transform_vector(self.object.random(&local_origin))
+    }
+
+    fn bvh_stats(&self) -> Option<BVHStats> {
+        self.object.bvh_stats()
+    }
 }
 
 impl Translate {
@@ -89,9 +147,17 @@ impl Translate {
 
 impl RotateY {
     pub fn new(object: Arc<dyn Hittable + Sync + Send>, angle: f64) -> RotateY {
-        let radians = utility::degree_to_radians(angle);
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
+        RotateY {
+            transform: Transform::new(object, Mat4::rotate_y(angle))
+                .expect("rotations are invertible"),
+        }
+    }
+}
+
+impl Transform {
+    // None if `matrix` is not invertible
+    pub fn new(object: Arc<dyn Hittable + Sync + Send>, matrix: Mat4) -> Option<Transform> {
+        let inverse = matrix.inverse()?;
         let bbox = object.bounding_box();
 
         let mut min = Vec3::new(INFINITY, INFINITY, INFINITY);
@@ -100,18 +166,23 @@ impl RotateY {
         for i in 0..2 {
             for j in 0..2 {
                 for k in 0..2 {
-                    let i_f = i as f64;
-                    let j_f = j as f64;
-                    let k_f = k as f64;
-
-                    let x = i_f * bbox.axis(0).max() + (1.0 - i_f) * bbox.axis(0).min();
-                    let y = j_f * bbox.axis(1).max() + (1.0 - j_f) * bbox.axis(1).min();
-                    let z = k_f * bbox.axis(2).max() + (1.0 - k_f) * bbox.axis(2).min();
-
-                    let new_x = cos_theta * x + sin_theta * z;
-                    let new_z = -sin_theta * x + cos_theta * z;
+                    let x = if i == 0 {
+                        bbox.axis(0).min()
+                    } else {
+                        bbox.axis(0).max()
+                    };
+                    let y = if j == 0 {
+                        bbox.axis(1).min()
+                    } else {
+                        bbox.axis(1).max()
+                    };
+                    let z = if k == 0 {
+                        bbox.axis(2).min()
+                    } else {
+                        bbox.axis(2).max()
+                    };
 
-                    let tester = Vec3::new(new_x, y, new_z);
+                    let tester = matrix.transform_point(Vec3::new(x, y, z));
 
                     for index in 0..3 {
                         min.set(index, min.at(index).min(tester.at(index)));
@@ -123,11 +194,123 @@ impl RotateY {
 
         let bbox = AABB::from_vec3s(min, max);
 
-        RotateY {
+        Some(Transform {
             object,
             bbox,
-            sin_theta,
-            cos_theta,
+            matrix,
+            inverse,
+        })
+    }
+
+    // None for a zero axis
+    pub fn rotate(
+        object: Arc<dyn Hittable + Sync + Send>,
+        axis: Vec3,
+        angle: f64,
+    ) -> Option<Transform> {
+        if axis.near_zero() {
+            return None;
         }
+        Transform::new(object, Mat4::rotation(axis, angle))
+    }
+
+    // None if any factor is zero
+    pub fn scale(object: Arc<dyn Hittable + Sync + Send>, factors: Vec3) -> Option<Transform> {
+        Transform::new(object, Mat4::scale(factors))
+    }
+
+    // Moves an object modelled around the origin to `from`, with its +z axis facing `to`. None
+    // if `from` and `to` are the same point or `up` is parallel to the way from one to the other.
+    pub fn look_at(
+        object: Arc<dyn Hittable + Sync + Send>,
+        from: Vec3,
+        to: Vec3,
+        up: Vec3,
+    ) -> Option<Transform> {
+        let forward = to - from;
+        if forward.near_zero() || up.cross(&forward.unit_vector()).near_zero() {
+            return None;
+        }
+        Transform::new(object, Mat4::look_at(from, to, up))
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::sphere::Sphere;
+
+    fn unit_sphere() -> Arc<dyn Hittable + Sync + Send> {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, mat))
+    }
+
+    #[test]
+    fn scaled_sphere_hit_and_normal() {
+        let ellipsoid = Transform::new(
+            unit_sphere(),
+            Mat4::translation(Vec3::new(0.0, 0.0, -5.0)) * Mat4::scale(Vec3::new(2.0, 1.0, 1.0)),
+        )
+        .unwrap();
+        let ray = Ray3::new(Vec3::new(10.0, 0.0, -5.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(ellipsoid.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.time - 8.0).abs() < 1e-9);
+        assert!((rec.point - Vec3::new(2.0, 0.0, -5.0)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!(rec.front_face);
+
+        let bbox = ellipsoid.bounding_box();
+        assert!((bbox.axis(0).min() + 2.0).abs() < 1e-9);
+        assert!((bbox.axis(2).max() + 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rotate_y_matches_matrix() {
+        let offset = Arc::new(Translate::new(unit_sphere(), Vec3::new(3.0, 0.0, 0.0)));
+        let rotated = RotateY::new(offset, 90.0);
+        let ray = Ray3::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(rotated.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.point - Vec3::new(0.0, 0.0, -4.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn degenerate_transforms() {
+        assert!(Transform::new(unit_sphere(), Mat4::scale(Vec3::new(1.0, 0.0, 1.0))).is_none());
+        assert!(Transform::scale(unit_sphere(), Vec3::new(0.0, 2.0, 2.0)).is_none());
+        assert!(Transform::rotate(unit_sphere(), Vec3::empty(), 30.0).is_none());
+        let (origin, up) = (Vec3::empty(), Vec3::new(0.0, 1.0, 0.0));
+        assert!(Transform::look_at(unit_sphere(), origin, origin, up).is_none());
+        assert!(Transform::look_at(unit_sphere(), origin, 2.0 * up, up).is_none());
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        assert!(Transform::look_at(unit_sphere(), origin, z, up).is_some());
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        // Monte Carlo estimate of the integral of the pdf over the sphere of directions
+        let ellipsoid = Transform::new(
+            unit_sphere(),
+            Mat4::rotate_z(30.0) * Mat4::scale(Vec3::new(3.0, 1.0, 0.5)),
+        )
+        .unwrap();
+        let origin = Vec3::new(0.0, 0.0, 4.0);
+        let samples = 200_000;
+        let mut total = 0.0;
+        for _ in 0..samples {
+            let direction = Vec3::random_unit_vector();
+            total += ellipsoid.pdf_value(&origin, &direction);
+        }
+        let integral = total * 4.0 * std::f64::consts::PI / samples as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+
+        let direction = ellipsoid.random(&origin);
+        assert!(ellipsoid.pdf_value(&origin, &direction) > 0.0);
     }
 }
@@ -0,0 +1,301 @@
+use crate::film::Film;
+use nurbs::vector_3::Vec3;
+use std::collections::HashMap;
+
+// Arbitrary output variables, extra images made from the first surface each camera sample
+// hits, for compositing and denoising
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aov {
+    // World space normal of the surface, facing the camera
+    Normal,
+    // The material's attenuation, or for lights the emitted color, clamped to 1
+    Albedo,
+    // Distance from the camera along its viewing direction, infinite where nothing was hit
+    Depth,
+    // World space position of the hit
+    Position,
+    // 1 for the first material seen scanning the image top to bottom, 2 for the next and so
+    // on, 0 where nothing was hit
+    MaterialId,
+    // Numbered like the materials, but by object, so objects sharing a material still differ.
+    // An instance is one object, however many shapes it is made of.
+    ObjectId,
+    // Light reaching the camera after at most one bounce: what the first hit emits and what it
+    // reflects straight from a light or the background
+    Direct,
+    // All of the light that took more bounces, direct and indirect add up to the image
+    Indirect,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 8] = [
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Depth,
+        Aov::Position,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::Direct,
+        Aov::Indirect,
+    ];
+
+    // The name used in scene files, on the command line and in file names
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+}
+
+// What the first hit of one camera sample saw
+#[derive(Debug, Clone, Copy)]
+pub struct FirstHit {
+    // False if the ray left the scene, only albedo and the lighting are set then
+    pub hit: bool,
+    pub normal: Vec3,
+    pub albedo: Vec3,
+    pub position: Vec3,
+    pub depth: f64,
+    // Identify the material and the object for the duration of a render, 0 for none
+    pub material: usize,
+    pub object: usize,
+    pub direct: Vec3,
+    pub indirect: Vec3,
+}
+
+impl FirstHit {
+    pub fn new() -> FirstHit {
+        FirstHit {
+            hit: false,
+            normal: Vec3::empty(),
+            albedo: Vec3::empty(),
+            position: Vec3::empty(),
+            depth: 0.0,
+            material: 0,
+            object: 0,
+            direct: Vec3::empty(),
+            indirect: Vec3::empty(),
+        }
+    }
+}
+
+impl Default for FirstHit {
+    fn default() -> Self {
+        FirstHit::new()
+    }
+}
+
+// Running sums of the first hits of one pixel's samples. Normal, albedo and the lighting are
+// averaged over every sample, depth and position over the samples that hit something and the
+// material and object are the ones the first of those hit.
+#[derive(Debug, Clone, Copy)]
+pub struct AovEstimate {
+    samples: u32,
+    hits: u32,
+    normal: Vec3,
+    albedo: Vec3,
+    position: Vec3,
+    depth: f64,
+    material: usize,
+    object: usize,
+    direct: Vec3,
+    indirect: Vec3,
+}
+
+impl AovEstimate {
+    pub fn new() -> AovEstimate {
+        AovEstimate {
+            samples: 0,
+            hits: 0,
+            normal: Vec3::empty(),
+            albedo: Vec3::empty(),
+            position: Vec3::empty(),
+            depth: 0.0,
+            material: 0,
+            object: 0,
+            direct: Vec3::empty(),
+            indirect: Vec3::empty(),
+        }
+    }
+
+    pub fn add(&mut self, first_hit: &FirstHit) {
+        self.samples += 1;
+        self.normal += first_hit.normal;
+        self.albedo += first_hit.albedo;
+        self.direct += first_hit.direct;
+        self.indirect += first_hit.indirect;
+        if first_hit.hit {
+            self.hits += 1;
+            self.position += first_hit.position;
+            self.depth += first_hit.depth;
+            if self.material == 0 {
+                self.material = first_hit.material;
+                self.object = first_hit.object;
+            }
+        }
+    }
+
+    fn value(&self, aov: Aov, ids: &Ids) -> Vec3 {
+        let mean = |sum: Vec3, count: u32| {
+            if count == 0 {
+                Vec3::empty()
+            } else {
+                sum / count as f64
+            }
+        };
+        match aov {
+            Aov::Normal => mean(self.normal, self.samples),
+            Aov::Albedo => mean(self.albedo, self.samples),
+            Aov::Depth => {
+                let depth = if self.hits == 0 {
+                    f64::INFINITY
+                } else {
+                    self.depth / self.hits as f64
+                };
+                Vec3::new(depth, depth, depth)
+            }
+            Aov::Position => mean(self.position, self.hits),
+            Aov::MaterialId => {
+                let id = ids.materials.get(&self.material).copied().unwrap_or(0) as f64;
+                Vec3::new(id, id, id)
+            }
+            Aov::ObjectId => {
+                let id = ids.objects.get(&self.object).copied().unwrap_or(0) as f64;
+                Vec3::new(id, id, id)
+            }
+            Aov::Direct => mean(self.direct, self.samples),
+            Aov::Indirect => mean(self.indirect, self.samples),
+        }
+    }
+}
+
+impl Default for AovEstimate {
+    fn default() -> Self {
+        AovEstimate::new()
+    }
+}
+
+// The numbers written to the id AOVs, keyed by the ids of the first hits
+struct Ids {
+    materials: HashMap<usize, u32>,
+    objects: HashMap<usize, u32>,
+}
+
+// Numbers the ids from 1 in the order they first appear, skipping 0
+fn number_ids(ids: impl Iterator<Item = usize>) -> HashMap<usize, u32> {
+    let mut numbers = HashMap::new();
+    for id in ids.filter(|&id| id != 0) {
+        let next = numbers.len() as u32 + 1;
+        numbers.entry(id).or_insert(next);
+    }
+    numbers
+}
+
+// Turns the estimates of a `width` wide image, in row by row order, into one film per AOV
+pub fn aov_films(aovs: &[Aov], estimates: &[AovEstimate], width: u32) -> Vec<(Aov, Film)> {
+    let height = estimates.len() as u32 / width.max(1);
+    let ids = Ids {
+        materials: number_ids(estimates.iter().map(|estimate| estimate.material)),
+        objects: number_ids(estimates.iter().map(|estimate| estimate.object)),
+    };
+
+    aovs.iter()
+        .map(|&aov| {
+            let mut film = Film::new(width, height);
+            for (index, estimate) in estimates.iter().enumerate() {
+                let (x, y) = (index as u32 % width, index as u32 / width);
+                film.set_pixel(x, y, estimate.value(aov, &ids));
+                film.set_sample_count(x, y, estimate.samples);
+            }
+            (aov, film)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(material: usize, depth: f64) -> FirstHit {
+        on(material, material, depth)
+    }
+
+    fn on(object: usize, material: usize, depth: f64) -> FirstHit {
+        FirstHit {
+            hit: true,
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            albedo: Vec3::new(0.5, 0.5, 0.5),
+            position: Vec3::new(depth, 0.0, 0.0),
+            depth,
+            material,
+            object,
+            direct: Vec3::new(1.0, 1.0, 1.0),
+            indirect: Vec3::new(0.25, 0.25, 0.25),
+        }
+    }
+
+    #[test]
+    fn averages_and_ids() {
+        let mut sky = AovEstimate::new();
+        sky.add(&FirstHit::new());
+        let mut edge = AovEstimate::new();
+        edge.add(&hit(0xbeef, 2.0));
+        edge.add(&FirstHit::new());
+        edge.add(&hit(0xcafe, 4.0));
+        let mut wall = AovEstimate::new();
+        wall.add(&hit(0xcafe, 1.0));
+
+        let films = aov_films(&Aov::ALL, &[sky, wall, edge, wall], 2);
+        let film = |aov: Aov| &films.iter().find(|(kind, _)| *kind == aov).unwrap().1;
+        assert_eq!(film(Aov::Depth).pixel(0, 0).x, f64::INFINITY);
+        assert_eq!(film(Aov::Depth).pixel(0, 1).x, 3.0);
+        assert_eq!(film(Aov::Position).pixel(0, 1).x, 3.0);
+        assert!((film(Aov::Normal).pixel(0, 1).y - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(film(Aov::Indirect).pixel(1, 0).x, 0.25);
+
+        // Numbered in the order they first appear, the edge keeps its first material
+        let ids = film(Aov::MaterialId);
+        assert_eq!(
+            [
+                ids.pixel(0, 0).x,
+                ids.pixel(1, 0).x,
+                ids.pixel(0, 1).x,
+                ids.pixel(1, 1).x
+            ],
+            [0.0, 1.0, 2.0, 1.0]
+        );
+        assert_eq!(Aov::from_name("material_id"), Some(Aov::MaterialId));
+    }
+
+    #[test]
+    fn objects_sharing_a_material() {
+        let estimate = |first_hit: FirstHit| {
+            let mut estimate = AovEstimate::new();
+            estimate.add(&first_hit);
+            estimate
+        };
+        let estimates = [
+            estimate(FirstHit::new()),
+            estimate(on(0x10, 0xcafe, 1.0)),
+            estimate(on(0x20, 0xcafe, 1.0)),
+            estimate(on(0x10, 0xcafe, 2.0)),
+        ];
+
+        let films = aov_films(&[Aov::MaterialId, Aov::ObjectId], &estimates, 4);
+        let row = |film: &Film| (0..4).map(|x| film.pixel(x, 0).x).collect::<Vec<_>>();
+        assert_eq!(row(&films[0].1), [0.0, 1.0, 1.0, 1.0]);
+        assert_eq!(row(&films[1].1), [0.0, 1.0, 2.0, 1.0]);
+        assert_eq!(Aov::from_name("object_id"), Some(Aov::ObjectId));
+    }
+}
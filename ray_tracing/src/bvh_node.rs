@@ -5,14 +5,103 @@ use crate::hittable_list::HittableList;
 use crate::interval::Interval;
 use crate::ray::Ray3;
 use nurbs::utility::random_u32_range;
+use nurbs::vector_3::Vec3;
 
 use std::cmp::Ordering;
+use std::fmt;
 use std::sync::Arc;
 
+// Number of buckets the centroids are binned into when evaluating the SAH
+const SAH_BINS: usize = 12;
+// Nodes with at most this many primitives become leaves when splitting doesn't pay off
+const MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting a node relative to intersecting a primitive
+const TRAVERSAL_COST: f64 = 0.125;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SplitMethod {
+    // Median split along a random axis, one primitive per leaf
+    Median,
+    // Binned surface area heuristic with small multi-primitive leaves
+    #[default]
+    SurfaceAreaHeuristic,
+}
+
+// Shape of a tree, for comparing builders. Depth counts interior nodes on the longest path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BVHStats {
+    pub depth: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    pub primitives: usize,
+    pub max_leaf_size: usize,
+}
+
+impl BVHStats {
+    fn leaf(size: usize) -> BVHStats {
+        BVHStats {
+            depth: 0,
+            nodes: 0,
+            leaves: 1,
+            primitives: size,
+            max_leaf_size: size,
+        }
+    }
+
+    // A node whose children are the same primitive
+    fn single() -> BVHStats {
+        BVHStats {
+            depth: 1,
+            nodes: 1,
+            leaves: 1,
+            primitives: 1,
+            max_leaf_size: 1,
+        }
+    }
+
+    fn node(left: &BVHStats, right: &BVHStats) -> BVHStats {
+        let mut stats = left.merge(right);
+        stats.depth += 1;
+        stats.nodes += 1;
+        stats
+    }
+
+    // Combines the stats of two independent trees
+    pub fn merge(&self, other: &BVHStats) -> BVHStats {
+        BVHStats {
+            depth: self.depth.max(other.depth),
+            nodes: self.nodes + other.nodes,
+            leaves: self.leaves + other.leaves,
+            primitives: self.primitives + other.primitives,
+            max_leaf_size: self.max_leaf_size.max(other.max_leaf_size),
+        }
+    }
+
+    pub fn mean_leaf_size(&self) -> f64 {
+        self.primitives as f64 / self.leaves.max(1) as f64
+    }
+}
+
+impl fmt::Display for BVHStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "depth {}, {} nodes, {} leaves, {} primitives, leaf size mean {:.2} max {}",
+            self.depth,
+            self.nodes,
+            self.leaves,
+            self.primitives,
+            self.mean_leaf_size(),
+            self.max_leaf_size
+        )
+    }
+}
+
 pub struct BVHNode {
     left: Arc<dyn Hittable + Sync + Send>,
     right: Arc<dyn Hittable + Sync + Send>,
     bbox: AABB,
+    stats: BVHStats,
 }
 
 impl Hittable for BVHNode {
@@ -41,6 +130,32 @@ impl Hittable for BVHNode {
     fn bounding_box(&self) -> AABB {
         AABB::copy(&self.bbox)
     }
+
+    fn bvh_stats(&self) -> Option<BVHStats> {
+        Some(self.stats)
+    }
+}
+
+// A primitive with its bounds cached for the SAH builders
+pub(crate) struct Primitive {
+    pub(crate) object: Arc<dyn Hittable + Sync + Send>,
+    pub(crate) bbox: AABB,
+    centroid: Vec3,
+    // Where the object is in the list the BVH is built from
+    pub(crate) index: usize,
+}
+
+impl Primitive {
+    pub(crate) fn new(index: usize, object: &Arc<dyn Hittable + Sync + Send>) -> Primitive {
+        let bbox = object.bounding_box();
+        let centroid = bbox.centroid();
+        Primitive {
+            object: Arc::clone(object),
+            bbox,
+            centroid,
+            index,
+        }
+    }
 }
 
 impl BVHNode {
@@ -61,13 +176,17 @@ impl BVHNode {
         let span = end - start;
         let mut left = Arc::clone(&objects[start]);
         let mut right = Arc::clone(&objects[start]);
-        if span == 2 {
+        let stats;
+        if span == 1 {
+            stats = BVHStats::single();
+        } else if span == 2 {
             if comparator(&objects[start], &objects[start + 1]) {
                 right = Arc::clone(&objects[start + 1]);
             } else {
                 left = Arc::clone(&objects[start + 1]);
             }
-        } else if span != 1 {
+            stats = BVHStats::node(&BVHStats::leaf(1), &BVHStats::leaf(1));
+        } else {
             let mut slice = objects[start..end].to_vec();
             slice.sort_by(|a, b| {
                 if comparator(a, b) {
@@ -81,15 +200,180 @@ impl BVHNode {
             let end = slice.len();
             let mid = start + (span / 2);
 
-            left = Arc::new(BVHNode::from_vec(&slice, start, mid));
-            right = Arc::new(BVHNode::from_vec(&slice, mid, end));
+            let left_node = BVHNode::from_vec(&slice, start, mid);
+            let right_node = BVHNode::from_vec(&slice, mid, end);
+            stats = BVHStats::node(&left_node.stats, &right_node.stats);
+            left = Arc::new(left_node);
+            right = Arc::new(right_node);
         }
         let bbox = AABB::from_aabbs(&left.bounding_box().copy(), &right.bounding_box().copy());
-        BVHNode { left, right, bbox }
+        BVHNode {
+            left,
+            right,
+            bbox,
+            stats,
+        }
     }
 
+    // Built with the default split method, the SAH
     pub fn from_list(list: &HittableList) -> BVHNode {
-        BVHNode::from_vec(&list.objects, 0, list.objects.len())
+        BVHNode::from_list_with(list, SplitMethod::default())
+    }
+
+    pub fn from_list_with(list: &HittableList, method: SplitMethod) -> BVHNode {
+        match method {
+            SplitMethod::Median => BVHNode::from_vec(&list.objects, 0, list.objects.len()),
+            SplitMethod::SurfaceAreaHeuristic => BVHNode::from_vec_sah(&list.objects),
+        }
+    }
+
+    // Builds the tree top down with the binned surface area heuristic. Unlike `from_vec`
+    // the result only depends on the input order.
+    pub fn from_vec_sah(objects: &[Arc<dyn Hittable + Sync + Send>]) -> BVHNode {
+        let mut primitives: Vec<Primitive> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| Primitive::new(index, object))
+            .collect();
+
+        if primitives.len() == 1 {
+            let object = Arc::clone(&primitives[0].object);
+            return BVHNode {
+                left: Arc::clone(&object),
+                right: object,
+                bbox: primitives[0].bbox.copy(),
+                stats: BVHStats::single(),
+            };
+        }
+        BVHNode::split_sah(&mut primitives)
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        self.stats
+    }
+
+    fn split_sah(primitives: &mut [Primitive]) -> BVHNode {
+        let (mid, _) = BVHNode::partition_sah(primitives, true).unwrap();
+        let (left_primitives, right_primitives) = primitives.split_at_mut(mid);
+        let (left, left_stats) = BVHNode::subtree_sah(left_primitives);
+        let (right, right_stats) = BVHNode::subtree_sah(right_primitives);
+        let bbox = AABB::from_aabbs(&left.bounding_box(), &right.bounding_box());
+        BVHNode {
+            left,
+            right,
+            bbox,
+            stats: BVHStats::node(&left_stats, &right_stats),
+        }
+    }
+
+    fn subtree_sah(primitives: &mut [Primitive]) -> (Arc<dyn Hittable + Sync + Send>, BVHStats) {
+        if primitives.len() == 1 {
+            return (Arc::clone(&primitives[0].object), BVHStats::leaf(1));
+        }
+        if BVHNode::partition_sah(primitives, false).is_none() {
+            let mut leaf = HittableList::new();
+            for primitive in primitives.iter() {
+                leaf.add(Arc::clone(&primitive.object));
+            }
+            return (Arc::new(leaf), BVHStats::leaf(primitives.len()));
+        }
+        let node = BVHNode::split_sah(primitives);
+        let stats = node.stats;
+        (Arc::new(node), stats)
+    }
+
+    // Reorders `primitives` so the two children are `..mid` and `mid..` and returns `mid` with
+    // the split axis, or None if the primitives are better off in a single leaf. The split is
+    // always made when `force` is set or there are too many primitives for a leaf.
+    pub(crate) fn partition_sah(primitives: &mut [Primitive], force: bool) -> Option<(usize, u32)> {
+        let count = primitives.len();
+        let mut bounds = AABB::empty();
+        let mut centroid_bounds = AABB::empty();
+        for primitive in primitives.iter() {
+            bounds = AABB::from_aabbs(&bounds, &primitive.bbox);
+            centroid_bounds = AABB::from_aabbs(
+                &centroid_bounds,
+                &AABB::from_vec3s(primitive.centroid, primitive.centroid),
+            );
+        }
+        let must_split = force || count > MAX_LEAF_SIZE;
+
+        // Best (cost, axis, last bin of the left child) over all axes
+        let mut best: Option<(f64, u32, usize)> = None;
+        for axis in 0..3 {
+            let extent = centroid_bounds.axis(axis);
+            if extent.size() <= 0.0 {
+                continue;
+            }
+            let bin_of = |centroid: &Vec3| {
+                let offset = (centroid.at(axis) - extent.min()) / extent.size();
+                ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+            };
+
+            let mut counts = [0usize; SAH_BINS];
+            let mut boxes: Vec<AABB> = (0..SAH_BINS).map(|_| AABB::empty()).collect();
+            for primitive in primitives.iter() {
+                let bin = bin_of(&primitive.centroid);
+                counts[bin] += 1;
+                boxes[bin] = AABB::from_aabbs(&boxes[bin], &primitive.bbox);
+            }
+
+            // Sweep from the right to get the area and count of every right child
+            let mut right_area = [0.0; SAH_BINS];
+            let mut right_count = [0usize; SAH_BINS];
+            let mut right_box = AABB::empty();
+            let mut running = 0;
+            for bin in (1..SAH_BINS).rev() {
+                right_box = AABB::from_aabbs(&right_box, &boxes[bin]);
+                running += counts[bin];
+                right_area[bin] = right_box.surface_area();
+                right_count[bin] = running;
+            }
+
+            let mut left_box = AABB::empty();
+            let mut left_count = 0;
+            for split in 0..SAH_BINS - 1 {
+                left_box = AABB::from_aabbs(&left_box, &boxes[split]);
+                left_count += counts[split];
+                let right = right_count[split + 1];
+                if left_count == 0 || right == 0 {
+                    continue;
+                }
+                let cost = TRAVERSAL_COST
+                    + (left_box.surface_area() * left_count as f64
+                        + right_area[split + 1] * right as f64)
+                        / bounds.surface_area().max(f64::MIN_POSITIVE);
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, split));
+                }
+            }
+        }
+
+        match best {
+            Some((cost, _, _)) if !must_split && cost >= count as f64 => None,
+            Some((_, axis, split)) => {
+                let extent = centroid_bounds.axis(axis);
+                let mut mid = 0;
+                for index in 0..count {
+                    let offset =
+                        (primitives[index].centroid.at(axis) - extent.min()) / extent.size();
+                    let bin = ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1);
+                    if bin <= split {
+                        primitives.swap(index, mid);
+                        mid += 1;
+                    }
+                }
+                Some((mid, axis))
+            }
+            // Every centroid landed in the same place, fall back to a median split along
+            // the longest axis of the bounds
+            None if must_split => {
+                let axis = bounds.longest_axis();
+                primitives.sort_by(|a, b| a.centroid.at(axis).total_cmp(&b.centroid.at(axis)));
+                Some((count / 2, axis))
+            }
+            None => None,
+        }
     }
 
     fn box_compare(
@@ -121,3 +405,56 @@ impl BVHNode {
         BVHNode::box_compare(left, right, 2)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::sphere::Sphere;
+
+    fn sphere_grid(count: usize) -> HittableList {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new();
+        for i in 0..count {
+            let center = Vec3::new((i % 10) as f64 * 3.0, (i / 10) as f64 * 3.0, 0.0);
+            list.add(Arc::new(Sphere::new(center, 1.0, mat.clone())));
+        }
+        list
+    }
+
+    #[test]
+    fn builders_agree() {
+        let list = sphere_grid(100);
+        let median = BVHNode::from_list_with(&list, SplitMethod::Median);
+        let sah = BVHNode::from_list_with(&list, SplitMethod::SurfaceAreaHeuristic);
+        assert_eq!(median.stats().primitives, 100);
+        assert_eq!(sah.stats().primitives, 100);
+        assert!(sah.stats().max_leaf_size <= MAX_LEAF_SIZE);
+
+        for i in 0..100 {
+            let origin = Vec3::new((i % 10) as f64 * 3.0 + 0.3, (i / 10) as f64 * 3.0, 10.0);
+            let ray = Ray3::new(origin, Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let mut median_rec = HitRecord::new();
+            let mut sah_rec = HitRecord::new();
+            assert!(median.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut median_rec));
+            assert!(sah.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut sah_rec));
+            assert!((median_rec.time - sah_rec.time).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn coincident_primitives_fall_back() {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new();
+        for _ in 0..20 {
+            list.add(Arc::new(Sphere::new(
+                Vec3::new(0.0, 0.0, 0.0),
+                1.0,
+                mat.clone(),
+            )));
+        }
+        let stats = BVHNode::from_list_with(&list, SplitMethod::SurfaceAreaHeuristic).stats();
+        assert_eq!(stats.primitives, 20);
+        assert!(stats.max_leaf_size <= MAX_LEAF_SIZE);
+    }
+}
@@ -1,3 +1,8 @@
+use crate::adaptive_sampling::{AdaptiveSampling, PixelEstimate};
+use crate::aov::{aov_films, Aov, AovEstimate, FirstHit};
+use crate::color::{DisplayTransform, ToneMap};
+use crate::denoise::Denoiser;
+use crate::film::Film;
 use crate::hittable::{HitRecord, Hittable};
 use crate::hittable_pdf::HittablePDF;
 use crate::interval::Interval;
@@ -5,11 +10,33 @@ use crate::material::{ScatterPDF, ScatterRecord};
 use crate::mixture_pdf::MixturePDF;
 use crate::pdf::PDF;
 use crate::ray::Ray3;
+use crate::render_progress::{CancelToken, RenderOutcome, RenderProgress, RenderStatus};
+use crate::sampler::{self, sample_1d, sample_2d, SamplerKind};
+use crate::spectrum::{self, Wavelengths};
+use crate::tile_scheduler::{self, Tile, TileLayout, TileOrder};
+use nurbs::random;
 use nurbs::{utility::degree_to_radians, vector_3::Vec3};
-use scoped_threadpool::Pool;
+use std::cell::Cell;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
+// Sampler dimensions of the camera ray: the position in the pixel, the lens, the time and
+// the wavelengths
+const CAMERA_DIMENSIONS: u32 = 6;
+// Dimensions set aside for each bounce, more than the materials and light PDFs use, so every
+// bounce starts at the same dimension in all samples
+const BOUNCE_DIMENSIONS: u32 = 8;
+// Largest number of samples per pixel added by one pass of a progressive render
+const PROGRESSIVE_PASS_SAMPLES: u32 = 16;
+
+thread_local! {
+    // Rays traced by the calling thread, for the rays per second of the progress reports
+    static RAYS_TRACED: Cell<u64> = const { Cell::new(0) };
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub samples_per_pixel: i32,
     pub image_width: i32,
@@ -22,6 +49,31 @@ pub struct Camera {
     pub defocus_angle: f64,
     pub focus_dist: f64,
     pub background: Vec3,
+    // Output settings, applied when the film is converted for display
+    pub tone_map: ToneMap,
+    pub exposure: f64,
+    // Every pixel draws its random numbers from its own stream of this seed, so renders are
+    // reproducible and independent of the thread count
+    pub seed: u64,
+    // How the samples of a pixel are spread over the pixel, lens and bounce directions
+    pub sampler: SamplerKind,
+    // Spends the sample budget on the noisy pixels when set, otherwise every pixel takes
+    // samples_per_pixel samples
+    pub adaptive: Option<AdaptiveSampling>,
+    // The image is rendered in square tiles of this many pixels a side, handed out to the
+    // threads in `tile_order`
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    // Extra images to make from the first hits, returned with the film
+    pub aovs: Vec<Aov>,
+    // Filters the noise out of the finished film, rendering the AOVs it needs
+    pub denoise: Option<Denoiser>,
+    // Traces every sample at a few wavelengths instead of in RGB, so dispersive materials
+    // split light into its colors
+    pub spectral: bool,
+    // Print the progress and, once done, the timing and adaptive sampling statistics to
+    // stdout. Off by default, `render_with_progress` reports the same to the caller.
+    pub verbose: bool,
     image_height: i32,
     camera_center: Vec3,
     pixel_delta_u: Vec3,
@@ -61,33 +113,22 @@ impl Camera {
             defocus_disk_u: Vec3::new(0.0, 0.0, 0.0),
             defocus_disk_v: Vec3::new(0.0, 0.0, 0.0),
             background: Vec3::new(0.7, 0.8, 1.0),
+            tone_map: ToneMap::Clamp,
+            exposure: 0.0,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            adaptive: None,
+            tile_size: 16,
+            tile_order: TileOrder::Hilbert,
+            aovs: Vec::new(),
+            denoise: None,
+            spectral: false,
+            verbose: false,
         }
     }
 
-    fn self_clone(&self) -> Camera {
-        Camera {
-            samples_per_pixel: self.samples_per_pixel,
-            image_width: self.image_width,
-            aspect_ratio: self.aspect_ratio,
-            max_depth: self.max_depth,
-            vfov: self.vfov,
-            look_from: self.look_from,
-            look_at: self.look_at,
-            v_up: self.v_up,
-            defocus_angle: self.defocus_angle,
-            focus_dist: self.focus_dist,
-            image_height: self.image_height,
-            camera_center: self.camera_center,
-            pixel_delta_u: self.pixel_delta_u,
-            pixel_delta_v: self.pixel_delta_v,
-            pixel00_loc: self.pixel00_loc,
-            u: self.u,
-            v: self.v,
-            w: self.w,
-            defocus_disk_u: self.defocus_disk_u,
-            defocus_disk_v: self.defocus_disk_v,
-            background: self.background,
-        }
+    pub fn display_transform(&self) -> DisplayTransform {
+        DisplayTransform::new(self.tone_map, self.exposure)
     }
 
     pub fn render_width(&self) -> i32 {
@@ -98,92 +139,359 @@ impl Camera {
         self.image_height
     }
 
+    // Width and height of the image `render` will produce
+    pub fn output_size(&self) -> (u32, u32) {
+        // Calculate the height and ensure it is at least 1
+        let height = (self.image_width as f64 / self.aspect_ratio) as i32;
+        (self.image_width.max(1) as u32, height.max(1) as u32)
+    }
+
+    // Renders on `threads` threads, or on the calling thread when multi_thread is false
     pub fn render(
         &mut self,
         world: Arc<dyn Hittable + Sync + Send>,
         lights: Option<Arc<dyn Hittable + Sync + Send>>,
         multi_thread: bool,
         threads: u32,
-    ) -> Vec<u32> {
+    ) -> Film {
+        let threads = if multi_thread { threads } else { 1 };
+        self.render_passes(
+            world,
+            lights,
+            threads,
+            false,
+            &mut |_| true,
+            &mut |_| {},
+            &CancelToken::new(),
+        )
+        .film
+    }
+
+    // Renders on `threads` threads and calls `on_progress` on the calling thread each time a
+    // tile finishes. Cancelling `cancel` from another thread, or from `on_progress`, stops the
+    // workers at the next pixel and returns the film rendered so far. To follow a render from
+    // another thread, send the progress into a channel from `on_progress`.
+    pub fn render_with_progress(
+        &mut self,
+        world: Arc<dyn Hittable + Sync + Send>,
+        lights: Option<Arc<dyn Hittable + Sync + Send>>,
+        threads: u32,
+        on_progress: &mut dyn FnMut(&RenderProgress),
+        cancel: &CancelToken,
+    ) -> RenderOutcome {
+        self.render_passes(
+            world,
+            lights,
+            threads,
+            false,
+            &mut |_| true,
+            on_progress,
+            cancel,
+        )
+    }
+
+    // Renders in passes of 1, 1, 2, 4, ... samples per pixel, at most PROGRESSIVE_PASS_SAMPLES
+    // at a time, and hands the film to `on_pass` after each one. Rendering stops early when
+    // `on_pass` returns false, the returned film holds every sample taken until then.
+    pub fn render_progressive(
+        &mut self,
+        world: Arc<dyn Hittable + Sync + Send>,
+        lights: Option<Arc<dyn Hittable + Sync + Send>>,
+        threads: u32,
+        on_pass: &mut dyn FnMut(&Film) -> bool,
+    ) -> Film {
+        self.render_passes(
+            world,
+            lights,
+            threads,
+            true,
+            on_pass,
+            &mut |_| {},
+            &CancelToken::new(),
+        )
+        .film
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_passes(
+        &mut self,
+        world: Arc<dyn Hittable + Sync + Send>,
+        lights: Option<Arc<dyn Hittable + Sync + Send>>,
+        threads: u32,
+        progressive: bool,
+        on_pass: &mut dyn FnMut(&Film) -> bool,
+        on_progress: &mut dyn FnMut(&RenderProgress),
+        cancel: &CancelToken,
+    ) -> RenderOutcome {
         self.initalize();
 
         let start_time = Instant::now();
 
-        // Render to buffer
-        let image_size = self.image_width * self.image_height;
-        let mut buffer: Vec<u32> = Vec::with_capacity(image_size as usize);
-        if multi_thread {
-            let arc_cam = Arc::new(self.self_clone());
-            for _ in 0..image_size {
-                buffer.push(0);
+        let spp = self.samples_per_pixel.max(1) as u32;
+        let layout = self.tile_layout();
+        // Kept in the layout's tile by tile order
+        let mut estimates =
+            vec![PixelEstimate::new(); self.image_width as usize * self.image_height as usize];
+        // Empty unless AOVs were asked for
+        let mut aov_estimates = if self.rendered_aovs().is_empty() {
+            Vec::new()
+        } else {
+            vec![AovEstimate::new(); estimates.len()]
+        };
+        // Every pixel takes the same number of samples until adaptive sampling takes over
+        let uniform_samples = match &self.adaptive {
+            Some(adaptive) => adaptive.first_batch(spp),
+            None => spp,
+        };
+        let mut taken = 0;
+        let mut batches = vec![0; estimates.len()];
+        let samples_done = AtomicU64::new(0);
+        let rays = AtomicU64::new(0);
+        let mut progress = RenderProgress {
+            pass: 0,
+            tiles_done: 0,
+            tiles_total: layout.tiles().len(),
+            samples_done: 0,
+            samples_total: estimates.len() as u64 * spp as u64,
+            rays: 0,
+            elapsed: start_time.elapsed(),
+        };
+        let mut status = RenderStatus::Complete;
+        loop {
+            if taken < uniform_samples {
+                let batch = if progressive {
+                    taken.clamp(1, PROGRESSIVE_PASS_SAMPLES)
+                } else {
+                    uniform_samples
+                };
+                let batch = batch.min(uniform_samples - taken);
+                batches.fill(batch);
+                taken += batch;
+            } else {
+                batches = match &self.adaptive {
+                    Some(adaptive) => adaptive.next_batches(&estimates, spp),
+                    None => vec![0; estimates.len()],
+                };
+                if batches.iter().all(|&batch| batch == 0) {
+                    break;
+                }
             }
-            {
-                let mut pool = Pool::new(threads);
-                pool.scoped(|scope| {
-                    for (j, buffer_slice) in
-                        buffer.chunks_mut(self.image_width as usize).enumerate()
-                    {
-                        let cam_clone = Arc::clone(&arc_cam);
-                        let world_clone = Arc::clone(&world);
-                        let lights_clone = match &lights {
-                            None => None,
-                            Some(unwrapped_lights) => Some(Arc::clone(&unwrapped_lights)),
-                        };
-                        scope.execute(move || {
-                            Self::thread_render(
-                                cam_clone,
-                                world_clone,
-                                lights_clone,
-                                j as i32,
-                                buffer_slice,
-                            )
-                        });
+
+            let mut tile_aovs = if aov_estimates.is_empty() {
+                layout.tiles().iter().map(|_| &mut [][..]).collect()
+            } else {
+                layout.split_mut(&mut aov_estimates)
+            };
+            let work: Vec<_> = layout
+                .tiles()
+                .iter()
+                .zip(layout.split_mut(&mut estimates))
+                .zip(layout.split(&batches))
+                .zip(tile_aovs.drain(..))
+                .map(|(((tile, tile_estimates), tile_batches), tile_aovs)| {
+                    (*tile, tile_estimates, tile_batches, tile_aovs)
+                })
+                .collect();
+            let cam: &Camera = self;
+            let mut shown_percent = None;
+            progress.pass += 1;
+            tile_scheduler::run(
+                work,
+                threads,
+                |(tile, tile_estimates, tile_batches, tile_aovs)| {
+                    let (samples, tile_rays) = cam.render_tile(
+                        &world,
+                        &lights,
+                        tile,
+                        tile_estimates,
+                        tile_batches,
+                        tile_aovs,
+                        cancel,
+                    );
+                    samples_done.fetch_add(samples, Ordering::Relaxed);
+                    rays.fetch_add(tile_rays, Ordering::Relaxed);
+                },
+                &mut |done| {
+                    progress.tiles_done = done;
+                    progress.samples_done = samples_done.load(Ordering::Relaxed);
+                    progress.rays = rays.load(Ordering::Relaxed);
+                    progress.elapsed = start_time.elapsed();
+                    on_progress(&progress);
+                    let percent = done * 100 / progress.tiles_total;
+                    if cam.verbose && shown_percent != Some(percent) {
+                        print_progress(&progress, percent);
+                        shown_percent = Some(percent);
                     }
-                });
+                },
+            );
+            if cancel.is_cancelled()
+                || (progressive && !on_pass(&self.film(&layout, &estimates, &aov_estimates)))
+            {
+                status = RenderStatus::Cancelled;
+                break;
             }
-        } else {
-            for j in 0..self.image_height {
-                for i in 0..self.image_width {
-                    let mut color_vec = Vec3::new(0.0, 0.0, 0.0);
-                    for _ in 0..self.samples_per_pixel {
-                        let ray_sample = self.get_ray(i, j);
-                        color_vec =
-                            color_vec + self.ray_color(ray_sample, self.max_depth, &world, &lights);
-                    }
-                    buffer.push(crate::color::vec_to_val(&color_vec, self.samples_per_pixel));
+        }
+
+        let mut film = self.film(&layout, &estimates, &aov_estimates);
+        if let Some(denoiser) = &self.denoise {
+            denoiser.denoise(&mut film);
+            film.retain_aovs(&self.aovs);
+        }
+        progress.elapsed = start_time.elapsed();
+        if self.verbose {
+            if self.adaptive.is_some() {
+                println!(
+                    "\rAdaptive sampling: {} passes, {:.1} samples per pixel on average",
+                    progress.pass,
+                    progress.samples_done as f64 / estimates.len() as f64
+                );
+            }
+            let result = match status {
+                RenderStatus::Complete => "Done!",
+                RenderStatus::Cancelled => "Cancelled,",
+            };
+            // Padded to cover the last progress line
+            let summary = format!(
+                "{} Took {} seconds, {:.2} million rays per second",
+                result,
+                progress.elapsed.as_secs(),
+                progress.rays_per_second() / 1e6
+            );
+            println!("\r{:<64}", summary);
+        }
+        RenderOutcome {
+            film,
+            status,
+            progress,
+        }
+    }
+
+    fn tile_layout(&self) -> TileLayout {
+        TileLayout::new(
+            self.image_width as u32,
+            self.image_height as u32,
+            self.tile_size,
+            self.tile_order,
+        )
+    }
+
+    fn film(
+        &self,
+        layout: &TileLayout,
+        estimates: &[PixelEstimate],
+        aov_estimates: &[AovEstimate],
+    ) -> Film {
+        let width = self.image_width as u32;
+        let mut film = Film::new(width, self.image_height as u32);
+        for ((x, y), estimate) in layout.positions().zip(estimates) {
+            film.set_pixel(x, y, estimate.color());
+            film.set_sample_count(x, y, estimate.count());
+            film.set_variance(x, y, estimate.mean_variance());
+        }
+        if !aov_estimates.is_empty() {
+            // Back in rows, so material ids don't depend on the tiles
+            let mut rows = vec![AovEstimate::new(); aov_estimates.len()];
+            for ((x, y), estimate) in layout.positions().zip(aov_estimates) {
+                rows[(y * width + x) as usize] = *estimate;
+            }
+            film.set_aovs(aov_films(&self.rendered_aovs(), &rows, width));
+        }
+        film
+    }
+
+    // The AOVs asked for and the ones the denoiser needs
+    fn rendered_aovs(&self) -> Vec<Aov> {
+        let mut aovs = self.aovs.clone();
+        if self.denoise.is_some() {
+            for guide in Denoiser::GUIDES {
+                if !aovs.contains(&guide) {
+                    aovs.push(guide);
                 }
             }
         }
-        let elapsed_time = start_time.elapsed();
-        println!("\rDone! Took {} seconds", elapsed_time.as_secs());
-        buffer
+        aovs
     }
 
-    pub fn thread_render(
-        cam: Arc<Camera>,
-        world: Arc<dyn Hittable + Sync + Send>,
-        lights: Option<Arc<dyn Hittable + Sync + Send>>,
-        j_idx: i32,
-        buffer: &mut [u32],
-    ) {
-        for (i, val) in buffer.iter_mut().enumerate() {
-            let mut color_vec = Vec3::new(0.0, 0.0, 0.0);
-            for _ in 0..cam.samples_per_pixel {
-                let ray_sample = cam.get_ray(i as i32, j_idx);
-                color_vec = color_vec + cam.ray_color(ray_sample, cam.max_depth, &world, &lights);
+    // Adds `batches[k]` more samples to pixel k of `tile`, counting row by row, and returns the
+    // number of samples and rays it took. The first hits go into `aov_estimates` unless it is
+    // empty. Stops at the next pixel once `cancel` is cancelled.
+    #[allow(clippy::too_many_arguments)]
+    fn render_tile(
+        &self,
+        world: &Arc<dyn Hittable + Sync + Send>,
+        lights: &Option<Arc<dyn Hittable + Sync + Send>>,
+        tile: Tile,
+        estimates: &mut [PixelEstimate],
+        batches: &[u32],
+        aov_estimates: &mut [AovEstimate],
+        cancel: &CancelToken,
+    ) -> (u64, u64) {
+        sampler::install(Some(self.create_sampler()));
+        RAYS_TRACED.with(|rays| rays.set(0));
+        let mut samples = 0;
+        for (k, (estimate, &batch)) in estimates.iter_mut().zip(batches).enumerate() {
+            if cancel.is_cancelled() {
+                break;
+            }
+            if batch == 0 {
+                continue;
+            }
+            samples += batch as u64;
+            let i = (tile.x + k as u32 % tile.width) as i32;
+            let j = (tile.y + k as u32 / tile.width) as i32;
+            let first_sample = estimate.count();
+            self.start_pixel(i, j, first_sample);
+            let mut aov_estimate = aov_estimates.get_mut(k);
+            for sample in first_sample..first_sample + batch {
+                sampler::start_sample(sample);
+                let ray_sample = self.get_ray(i, j);
+                if self.spectral {
+                    spectrum::install(Some(Wavelengths::sample(sample_1d())));
+                }
+                match aov_estimate.as_deref_mut() {
+                    Some(aov_estimate) => {
+                        let mut first_hit = FirstHit::new();
+                        let (emitted, scattered) = self.trace(
+                            ray_sample,
+                            self.max_depth,
+                            world,
+                            lights,
+                            Some(&mut first_hit),
+                        );
+                        estimate.add(to_rgb(emitted + scattered));
+                        first_hit.direct = to_rgb(first_hit.direct);
+                        first_hit.indirect = to_rgb(first_hit.indirect);
+                        aov_estimate.add(&first_hit);
+                    }
+                    None => estimate.add(to_rgb(self.ray_color(
+                        ray_sample,
+                        self.max_depth,
+                        world,
+                        lights,
+                    ))),
+                }
             }
-            *val = crate::color::vec_to_val(&color_vec, cam.samples_per_pixel);
         }
+        sampler::install(None);
+        spectrum::install(None);
+        (samples, RAYS_TRACED.with(|rays| rays.get()))
+    }
+
+    fn create_sampler(&self) -> Box<dyn sampler::Sampler> {
+        self.sampler
+            .create(self.seed, self.samples_per_pixel.max(1) as u32)
+    }
+
+    // Restarts the random numbers for samples `first_sample..` of pixel i, j. Later adaptive
+    // passes get their own random stream so they don't repeat the earlier samples.
+    fn start_pixel(&self, i: i32, j: i32, first_sample: u32) {
+        let pixel = j as u64 * self.image_width as u64 + i as u64;
+        random::seed_stream(self.seed, pixel | (first_sample as u64) << 40);
+        sampler::start_pixel(pixel);
     }
 
     fn initalize(&mut self) {
-        // Calculate the height and ensure it is at least 1
-        self.image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
-        self.image_height = if self.image_height < 1 {
-            1
-        } else {
-            self.image_height
-        };
+        self.image_height = self.output_size().1 as i32;
 
         self.camera_center = self.look_from;
 
@@ -231,18 +539,20 @@ impl Camera {
             self.defocus_disk_sample()
         };
         let ray_direction = pixel_sample - ray_origin;
-        Ray3::new(ray_origin, ray_direction, rand::random::<f64>())
+        Ray3::new(ray_origin, ray_direction, sample_1d())
     }
 
     fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + rand::random::<f64>();
-        let py = -0.5 + rand::random::<f64>();
+        let (x, y) = sample_2d();
+        let px = -0.5 + x;
+        let py = -0.5 + y;
         (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
     }
 
     fn defocus_disk_sample(&self) -> Vec3 {
         // Returns a random point in the camera defocus disk
-        let point = Vec3::random_in_unit_disk();
+        let (r1, r2) = sample_2d();
+        let point = Vec3::in_unit_disk(r1, r2);
         self.camera_center + (point.x * self.defocus_disk_u) + (point.y * self.defocus_disk_v)
     }
 
@@ -253,52 +563,331 @@ impl Camera {
         world: &Arc<dyn Hittable + Sync + Send>,
         lights: &Option<Arc<dyn Hittable + Sync + Send>>,
     ) -> Vec3 {
-        let mut hit_record = HitRecord::new();
+        let (emitted, scattered) = self.trace(r, depth, world, lights, None);
+        emitted + scattered
+    }
+
+    // Follows a ray and returns the light emitted where it hits and the light scattered there
+    // towards its origin. `first_hit`, when given, is filled in with the surface the ray hit
+    // and the light split into direct and indirect.
+    fn trace(
+        &self,
+        r: Ray3,
+        depth: i32,
+        world: &Arc<dyn Hittable + Sync + Send>,
+        lights: &Option<Arc<dyn Hittable + Sync + Send>>,
+        first_hit: Option<&mut FirstHit>,
+    ) -> (Vec3, Vec3) {
+        sampler::advance_to(
+            CAMERA_DIMENSIONS + BOUNCE_DIMENSIONS * (self.max_depth - depth) as u32,
+        );
         if depth <= 0 {
-            Vec3::new(0.0, 0.0, 0.0)
-        } else if world.hit(&r, Interval::new(0.0001, f64::INFINITY), &mut hit_record) {
-            let mut color_emission = hit_record.mat.emitted(
-                &r,
-                &hit_record,
-                hit_record.u,
-                hit_record.v,
-                hit_record.point,
-            );
-            let mut color_scattered = Vec3::empty();
-            let mut scatter_rec = ScatterRecord::new();
-            if hit_record.mat.scatter(&r, &hit_record, &mut scatter_rec) {
-                match scatter_rec.pdf {
-                    ScatterPDF::PDF(surface_pdf) => {
-                        let pdf: Box<dyn PDF> = match lights {
-                            Some(unwrapped_lights) => {
-                                let light_pdf = Box::new(HittablePDF::new(
-                                    Arc::clone(unwrapped_lights),
-                                    hit_record.point,
-                                ));
-                                Box::new(MixturePDF::new(light_pdf, surface_pdf))
-                            }
-                            None => surface_pdf,
-                        };
-
-                        let scattered = Ray3::new(hit_record.point, pdf.generate(), r.time());
-                        let pdf_val = pdf.value(&scattered.direction());
-
-                        let scattered_pdf =
-                            hit_record.mat.scattering_pdf(&r, &hit_record, &scattered);
-                        let sample_color = self.ray_color(scattered, depth - 1, &world, &lights);
-                        color_scattered =
-                            (scatter_rec.attenuation * scattered_pdf * sample_color) / pdf_val;
-                    }
-                    ScatterPDF::Skip(ray) => {
-                        color_emission = Vec3::empty();
-                        let sample_color = self.ray_color(ray, depth - 1, &world, &lights);
-                        color_scattered = scatter_rec.attenuation * sample_color;
+            return (Vec3::empty(), Vec3::empty());
+        }
+        RAYS_TRACED.with(|rays| rays.set(rays.get() + 1));
+        // Colors from the scene are RGB, a spectral sample takes their values at its wavelengths
+        let wavelengths = spectrum::wavelengths();
+        let uplift = |color: Vec3| match &wavelengths {
+            Some(wavelengths) => wavelengths.uplift(color),
+            None => color,
+        };
+        let unit = |color: Vec3| {
+            Vec3::new(
+                color.x.clamp(0.0, 1.0),
+                color.y.clamp(0.0, 1.0),
+                color.z.clamp(0.0, 1.0),
+            )
+        };
+
+        let mut hit_record = HitRecord::new();
+        if !world.hit(&r, Interval::new(0.0001, f64::INFINITY), &mut hit_record) {
+            if let Some(first_hit) = first_hit {
+                *first_hit = FirstHit {
+                    albedo: unit(self.background),
+                    direct: uplift(self.background),
+                    ..FirstHit::new()
+                };
+            }
+            return (uplift(self.background), Vec3::empty());
+        }
+
+        let mut color_emission = uplift(hit_record.mat.emitted(
+            &r,
+            &hit_record,
+            hit_record.u,
+            hit_record.v,
+            hit_record.point,
+        ));
+        let mut scatter_rec = ScatterRecord::new();
+        let scatters = hit_record.mat.scatter(&r, &hit_record, &mut scatter_rec);
+        // The next ray and the factor its light is scaled by
+        let bounce = if scatters {
+            match scatter_rec.pdf {
+                ScatterPDF::PDF(surface_pdf) => {
+                    let pdf: Box<dyn PDF> = match lights {
+                        Some(unwrapped_lights) => {
+                            let light_pdf = Box::new(HittablePDF::new(
+                                Arc::clone(unwrapped_lights),
+                                hit_record.point,
+                            ));
+                            Box::new(MixturePDF::new(light_pdf, surface_pdf))
+                        }
+                        None => surface_pdf,
+                    };
+
+                    let scattered = Ray3::new(hit_record.point, pdf.generate(), r.time());
+                    let pdf_val = pdf.value(&scattered.direction());
+
+                    // Rough surfaces can scatter below themselves, where nothing samples
+                    if pdf_val > 0.0 {
+                        let scattering = hit_record.mat.scattering(
+                            &r,
+                            &hit_record,
+                            scatter_rec.attenuation,
+                            &scattered,
+                        );
+                        Some((scattered, scattering / pdf_val))
+                    } else {
+                        None
                     }
                 }
+                ScatterPDF::Skip(ray) => {
+                    color_emission = Vec3::empty();
+                    Some((ray, scatter_rec.attenuation))
+                }
             }
-            color_emission + color_scattered
         } else {
-            self.background
+            None
+        };
+        let (next_emitted, next_scattered) = match bounce {
+            Some((ray, weight)) => {
+                let weight = uplift(weight);
+                let (emitted, scattered) = self.trace(ray, depth - 1, world, lights, None);
+                (weight * emitted, weight * scattered)
+            }
+            None => (Vec3::empty(), Vec3::empty()),
+        };
+
+        if let Some(first_hit) = first_hit {
+            *first_hit = FirstHit {
+                hit: true,
+                normal: hit_record.normal,
+                albedo: unit(if scatters {
+                    scatter_rec.attenuation
+                } else {
+                    color_emission
+                }),
+                position: hit_record.point,
+                depth: (hit_record.point - self.camera_center).dot(&-self.w),
+                material: Arc::as_ptr(&hit_record.mat) as *const () as usize,
+                object: hit_record.object,
+                direct: color_emission + next_emitted,
+                indirect: next_scattered,
+            };
+        }
+        (color_emission, next_emitted + next_scattered)
+    }
+}
+
+// The color of a sample's radiance, which is in RGB already unless the sample is spectral
+fn to_rgb(radiance: Vec3) -> Vec3 {
+    match spectrum::wavelengths() {
+        Some(wavelengths) => wavelengths.to_rgb(radiance),
+        None => radiance,
+    }
+}
+
+fn print_progress(progress: &RenderProgress, percent: usize) {
+    let eta = match progress.eta() {
+        Some(eta) => format!("{} s left", eta.as_secs()),
+        None => "".to_owned(),
+    };
+    print!(
+        "\rPass {}: {:>3}%, {:.2} million rays per second, {}   ",
+        progress.pass,
+        percent,
+        progress.rays_per_second() / 1e6,
+        eta
+    );
+    let _ = std::io::stdout().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adaptive_sampling::AdaptiveSampling;
+    use crate::aov::Aov;
+    use crate::demo_render::demo_scene;
+    use crate::film::Film;
+    use crate::render_progress::{CancelToken, RenderStatus};
+    use crate::tile_scheduler::TileOrder;
+
+    fn render(name: &str, seed: u64, threads: u32) -> Film {
+        nurbs::random::seed(1);
+        let mut scene = demo_scene(name).unwrap();
+        scene.camera.image_width = 24;
+        scene.camera.samples_per_pixel = 3;
+        scene.camera.max_depth = 6;
+        scene.camera.seed = seed;
+        scene.render(threads)
+    }
+
+    #[test]
+    fn seeded_renders_are_reproducible() {
+        for name in [
+            "cornell_smoke",
+            "perlin_spheres",
+            "cornell_box_glass_sphere",
+        ] {
+            let single = render(name, 7, 1);
+            let threaded = render(name, 7, 3);
+            assert!(single.pixels() == threaded.pixels(), "{}", name);
+            let reseeded = render(name, 8, 3);
+            assert!(single.pixels() != reseeded.pixels(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn tiles_do_not_change_the_image() {
+        let render = |tile_size: u32, tile_order: TileOrder, threads: u32| {
+            let mut scene = demo_scene("cornell_box").unwrap();
+            scene.camera.image_width = 30;
+            scene.camera.samples_per_pixel = 2;
+            scene.camera.max_depth = 4;
+            scene.camera.tile_size = tile_size;
+            scene.camera.tile_order = tile_order;
+            scene.render(threads)
+        };
+        let reference = render(16, TileOrder::Hilbert, 1);
+        for (tile_size, tile_order) in [
+            (7, TileOrder::Scanline),
+            (4, TileOrder::Spiral),
+            (64, TileOrder::Hilbert),
+        ] {
+            assert!(reference.pixels() == render(tile_size, tile_order, 4).pixels());
+        }
+    }
+
+    #[test]
+    fn aovs_add_up_to_the_image() {
+        let mut scene = demo_scene("cornell_box").unwrap();
+        scene.camera.image_width = 16;
+        scene.camera.samples_per_pixel = 4;
+        scene.camera.max_depth = 4;
+        scene.camera.tile_size = 5;
+        let plain = scene.render(2);
+        assert!(plain.aovs().is_empty());
+
+        scene.camera.aovs = Aov::ALL.to_vec();
+        let film = scene.render(2);
+        assert!(plain.pixels() == film.pixels());
+        let (direct, indirect) = (
+            film.aov(Aov::Direct).unwrap(),
+            film.aov(Aov::Indirect).unwrap(),
+        );
+        let (normals, depth) = (
+            film.aov(Aov::Normal).unwrap(),
+            film.aov(Aov::Depth).unwrap(),
+        );
+        for y in 0..film.height() {
+            for x in 0..film.width() {
+                // Films store f32
+                let sum = direct.pixel(x, y) + indirect.pixel(x, y);
+                assert!((sum - film.pixel(x, y)).length() <= 1e-5 * sum.length().max(1.0));
+                assert!(normals.pixel(x, y).length() <= 1.0 + 1e-5);
+                assert!(depth.pixel(x, y).x > 0.0);
+            }
         }
+        // Red, green, white and the light
+        let ids = film.aov(Aov::MaterialId).unwrap();
+        let most = ids.pixels().iter().map(|id| id[0] as u32).max().unwrap();
+        assert_eq!(most, 4);
+        // The light, five walls and two boxes, the walls sharing white
+        let ids = film.aov(Aov::ObjectId).unwrap();
+        let most = ids.pixels().iter().map(|id| id[0] as u32).max().unwrap();
+        assert_eq!(most, 8);
+    }
+
+    #[test]
+    fn progress_and_cancellation() {
+        let mut scene = demo_scene("cornell_box").unwrap();
+        scene.camera.image_width = 32;
+        scene.camera.samples_per_pixel = 8;
+        scene.camera.max_depth = 4;
+        scene.camera.tile_size = 8;
+
+        let mut reports = Vec::new();
+        let outcome = scene.render_with_progress(
+            3,
+            &mut |progress| reports.push(progress.clone()),
+            &CancelToken::new(),
+        );
+        assert_eq!(outcome.status, RenderStatus::Complete);
+        let last = reports.last().unwrap();
+        assert_eq!((last.tiles_done, last.tiles_total), (16, 16));
+        assert_eq!(reports.len(), 16);
+        assert!(reports
+            .windows(2)
+            .all(|pair| pair[0].samples_done <= pair[1].samples_done));
+        assert_eq!(last.samples_done, 32 * 32 * 8);
+        assert_eq!(last.samples_done, last.samples_total);
+        assert!(last.rays >= last.samples_done);
+
+        // Cancelled once the first tile is done, the other threads stop at their next pixel
+        let cancel = CancelToken::new();
+        let outcome = scene.render_with_progress(3, &mut |_| cancel.cancel(), &cancel);
+        assert_eq!(outcome.status, RenderStatus::Cancelled);
+        let counts = outcome.film.sample_counts();
+        let taken: u64 = counts.iter().map(|&count| count as u64).sum();
+        assert_eq!(taken, outcome.progress.samples_done);
+        assert!(counts.iter().filter(|&&count| count == 8).count() >= 64);
+        assert!(counts.contains(&0));
+    }
+
+    #[test]
+    fn adaptive_sampling_moves_samples() {
+        let render = |threads: u32| {
+            let mut scene = demo_scene("cornell_box_glass_sphere").unwrap();
+            scene.camera.image_width = 16;
+            scene.camera.samples_per_pixel = 32;
+            scene.camera.max_depth = 6;
+            scene.camera.adaptive = Some(AdaptiveSampling {
+                min_samples: 8,
+                ..AdaptiveSampling::new(0.05)
+            });
+            scene.render(threads)
+        };
+        let single = render(1);
+        let threaded = render(3);
+        assert!(single.pixels() == threaded.pixels());
+        assert_eq!(single.sample_counts(), threaded.sample_counts());
+
+        let counts = single.sample_counts();
+        let total: u32 = counts.iter().sum();
+        assert!(total <= 32 * counts.len() as u32);
+        assert!(counts.iter().all(|&count| (8..=256).contains(&count)));
+        assert!(counts.iter().max() > counts.iter().min());
+    }
+
+    #[test]
+    fn progressive_passes_stop_early() {
+        let mut scene = demo_scene("cornell_box").unwrap();
+        scene.camera.image_width = 8;
+        scene.camera.samples_per_pixel = 40;
+        scene.camera.max_depth = 4;
+
+        let mut passes = Vec::new();
+        let film = scene.render_progressive(2, &mut |film| {
+            passes.push(film.sample_counts()[0]);
+            true
+        });
+        assert_eq!(passes, [1, 2, 4, 8, 16, 32, 40]);
+        assert!(film.sample_counts().iter().all(|&count| count == 40));
+
+        let mut passes = 0;
+        let film = scene.render_progressive(1, &mut |_| {
+            passes += 1;
+            passes < 3
+        });
+        assert!(film.sample_counts().iter().all(|&count| count == 4));
     }
 }
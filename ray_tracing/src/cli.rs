@@ -0,0 +1,736 @@
+use crate::adaptive_sampling::AdaptiveSampling;
+use crate::aov::Aov;
+use crate::bvh_node::SplitMethod;
+use crate::color::ToneMap;
+use crate::demo_render::{demo_scene_with, DEMO_SCENES};
+use crate::denoise::Denoiser;
+use crate::film::Film;
+#[cfg(feature = "window")]
+use crate::interactive::{camera_code, explore, CONTROLS};
+use crate::sampler::SamplerKind;
+use crate::save_show::{
+    is_output_path, output_path, save_film, save_image_to, ExrCompression, RenderInfo, SaveOptions,
+    DEFAULT_OUTPUT_TEMPLATE, OUTPUT_EXTENSIONS,
+};
+#[cfg(feature = "window")]
+use crate::save_show::{render_buffer, show_progressive};
+use crate::scene::Scene;
+use crate::scene_file::{load_scene_with, SceneError};
+use crate::tile_scheduler::TileOrder;
+use nurbs::random;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub const USAGE: &str = "\
+Usage: ray_tracing [OPTIONS] [SCENE]
+
+SCENE is the name of a built-in scene or the path to a .scene file.
+Defaults to cornell_box_glass_sphere.
+
+Options:
+  -w, --width <PIXELS>     Image width
+  -s, --samples <COUNT>    Samples per pixel
+  -d, --depth <BOUNCES>    Maximum ray depth
+  -t, --threads <COUNT>    Worker threads, 1 renders on the calling thread
+      --tile-size <PIXELS> Side of the square tiles handed to the threads
+      --tile-order <ORDER> scanline, spiral or hilbert (default)
+  -o, --output <PATH>      Output image, or output directory with --all. May hold
+                           {name}, {width}, {height}, {samples}, {depth}, {seed}
+                           and {timestamp}. .hdr, .pfm and .exr keep the linear float
+                           data, .png, .jpg, .bmp, .tga and .ppm are tone mapped.
+                           Defaults to renders/{name}_w{width}_s{samples}_d{depth}.png
+      --png-16             Write 16 bits per channel to .png output
+      --jpeg-quality <Q>   JPEG quality from 1 to 100, 90 by default
+      --tone-map <NAME>    clamp, reinhard, extended_reinhard or aces, overrides the
+                           scene's setting for 8-bit output and the window
+      --white-point <L>    Luminance that maps to white with extended_reinhard
+      --exposure <EV>      Exposure compensation in stops
+      --seed <SEED>        Seed for the scene setup and the render, the same seed
+                           gives the same image on any number of threads
+      --sampler <NAME>     independent, stratified, halton or sobol, overrides the
+                           scene's setting
+      --adaptive <ERROR>   Stop sampling pixels once the relative error of their
+                           brightness is below ERROR (e.g. 0.02) and spend the
+                           samples on the noisy ones, --samples is the average
+      --min-samples <COUNT>
+                           Samples every pixel takes before --adaptive judges it
+      --heatmap            Also save the number of samples per pixel as
+                           <image>_samples.png
+      --aov <LIST>         Also save AOVs from the first hits as <image>_<aov>.exr,
+                           a comma separated list of normal, albedo, depth,
+                           position, material_id, object_id, direct and indirect,
+                           or all
+      --denoise            Filter the noise out of the finished image, guided by
+                           the normal, albedo and depth AOVs
+      --spectral           Trace wavelengths instead of RGB, for dispersion
+      --exr-compression <none|zip>
+                           Compression of .exr output, zip by default
+      --headless           Don't open a window to show the result
+      --progressive        Show the image in the window while it renders, pass by
+                           pass. Escape or closing the window stops early and the
+                           samples taken so far are saved
+      --interactive        Fly the camera around the scene in the window, P prints
+                           the camera setup as Rust code. Nothing is saved
+      --no-save            Don't write an image
+      --all                Render every built-in scene headless
+      --bvh <BUILDER>      BVH builder, sah (default) or median
+      --bvh-stats          Print the depth, node count and leaf sizes of the BVHs
+  -l, --list               List the built-in scenes
+  -h, --help               Print this message
+
+Exit codes: 0 on success, 1 if the scene can't be loaded or the image can't be
+saved, 2 for bad arguments.";
+
+const DEFAULT_SCENE: &str = "cornell_box_glass_sphere";
+
+// Without the window feature every render is headless
+const HAS_WINDOW: bool = cfg!(feature = "window");
+
+#[derive(Debug, PartialEq)]
+pub enum SceneSource {
+    Demo(String),
+    File(PathBuf),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RenderOptions {
+    pub scene: SceneSource,
+    pub width: Option<i32>,
+    pub samples: Option<i32>,
+    pub depth: Option<i32>,
+    pub threads: u32,
+    pub tile_size: Option<i32>,
+    pub tile_order: Option<TileOrder>,
+    pub output: Option<PathBuf>,
+    pub show: bool,
+    pub save: bool,
+    pub bvh: SplitMethod,
+    pub bvh_stats: bool,
+    pub exr_compression: ExrCompression,
+    pub png_16_bit: bool,
+    pub jpeg_quality: Option<u8>,
+    pub tone_map: Option<ToneMap>,
+    pub exposure: Option<f64>,
+    pub seed: Option<u64>,
+    pub sampler: Option<SamplerKind>,
+    pub adaptive: Option<f64>,
+    pub min_samples: Option<i32>,
+    pub heatmap: bool,
+    pub aovs: Option<Vec<Aov>>,
+    pub denoise: bool,
+    pub spectral: bool,
+    pub progressive: bool,
+    pub interactive: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Render(RenderOptions),
+    RenderAll(RenderOptions),
+    List,
+    Help,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Scene(SceneError),
+    Save(PathBuf, image::ImageError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::Scene(error) => write!(f, "{}", error),
+            CliError::Save(path, error) => {
+                write!(f, "could not save {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl CliError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Usage(_) => 2,
+            CliError::Scene(_) | CliError::Save(_, _) => 1,
+        }
+    }
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    let mut options = RenderOptions {
+        scene: SceneSource::Demo(DEFAULT_SCENE.to_owned()),
+        width: None,
+        samples: None,
+        depth: None,
+        threads: default_threads(),
+        tile_size: None,
+        tile_order: None,
+        output: None,
+        show: HAS_WINDOW,
+        save: true,
+        bvh: SplitMethod::default(),
+        bvh_stats: false,
+        exr_compression: ExrCompression::Zip,
+        png_16_bit: false,
+        jpeg_quality: None,
+        tone_map: None,
+        exposure: None,
+        seed: None,
+        sampler: None,
+        adaptive: None,
+        min_samples: None,
+        heatmap: false,
+        aovs: None,
+        denoise: false,
+        spectral: false,
+        progressive: false,
+        interactive: false,
+    };
+    let mut tone_map_name = None;
+    let mut white_point = None;
+    let mut render_all = false;
+    let mut scene_given = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-l" | "--list" => return Ok(Command::List),
+            "-w" | "--width" => options.width = Some(positive(&arg, args.next())?),
+            "-s" | "--samples" => options.samples = Some(positive(&arg, args.next())?),
+            "-d" | "--depth" => options.depth = Some(positive(&arg, args.next())?),
+            "-t" | "--threads" => options.threads = positive(&arg, args.next())? as u32,
+            "--tile-size" => options.tile_size = Some(positive(&arg, args.next())?),
+            "--tile-order" => match args.next() {
+                Some(name) => {
+                    options.tile_order =
+                        Some(TileOrder::from_name(&name).ok_or_else(|| {
+                            CliError::Usage(format!("unknown tile order '{}'", name))
+                        })?)
+                }
+                None => return Err(CliError::Usage(format!("{} needs a name", arg))),
+            },
+            "-o" | "--output" => match args.next() {
+                Some(path) => options.output = Some(PathBuf::from(path)),
+                None => return Err(CliError::Usage(format!("{} needs a path", arg))),
+            },
+            "--headless" => options.show = false,
+            "--progressive" => options.progressive = true,
+            "--interactive" => options.interactive = true,
+            "--no-save" => options.save = false,
+            "--all" => render_all = true,
+            "--bvh" => options.bvh = split_method(&arg, args.next())?,
+            "--bvh-stats" => options.bvh_stats = true,
+            "--tone-map" => match args.next() {
+                Some(name) => tone_map_name = Some(name),
+                None => return Err(CliError::Usage(format!("{} needs a name", arg))),
+            },
+            "--white-point" => white_point = Some(number(&arg, args.next())?),
+            "--exposure" => options.exposure = Some(number(&arg, args.next())?),
+            "--seed" => match args.next().map(|value| value.parse::<u64>()) {
+                Some(Ok(seed)) => options.seed = Some(seed),
+                _ => return Err(CliError::Usage(format!("{} needs a whole number", arg))),
+            },
+            "--sampler" => match args.next() {
+                Some(name) => {
+                    options.sampler =
+                        Some(SamplerKind::from_name(&name).ok_or_else(|| {
+                            CliError::Usage(format!("unknown sampler '{}'", name))
+                        })?)
+                }
+                None => return Err(CliError::Usage(format!("{} needs a name", arg))),
+            },
+            "--adaptive" => match number(&arg, args.next())? {
+                error if error > 0.0 => options.adaptive = Some(error),
+                _ => return Err(CliError::Usage(format!("{} needs an error above 0", arg))),
+            },
+            "--min-samples" => options.min_samples = Some(positive(&arg, args.next())?),
+            "--heatmap" => options.heatmap = true,
+            "--denoise" => options.denoise = true,
+            "--spectral" => options.spectral = true,
+            "--aov" => options.aovs = Some(aov_list(&arg, args.next())?),
+            "--png-16" => options.png_16_bit = true,
+            "--jpeg-quality" => match positive(&arg, args.next())? {
+                quality @ 1..=100 => options.jpeg_quality = Some(quality as u8),
+                _ => return Err(CliError::Usage(format!("{} needs 1 to 100", arg))),
+            },
+            "--exr-compression" => {
+                options.exr_compression = match args.next().as_deref() {
+                    Some("none") => ExrCompression::None,
+                    Some("zip") => ExrCompression::Zip,
+                    _ => {
+                        return Err(CliError::Usage(format!("{} needs none or zip", arg)));
+                    }
+                }
+            }
+            _ if arg.starts_with('-') => {
+                return Err(CliError::Usage(format!("unknown option '{}'", arg)));
+            }
+            _ if scene_given => {
+                return Err(CliError::Usage(format!("unexpected argument '{}'", arg)));
+            }
+            _ => {
+                options.scene = scene_source(&arg);
+                scene_given = true;
+            }
+        }
+    }
+
+    if let Some(name) = tone_map_name {
+        options.tone_map = Some(
+            ToneMap::from_name(&name, white_point)
+                .ok_or_else(|| CliError::Usage(format!("unknown tone map '{}'", name)))?,
+        );
+    } else if let Some(white) = white_point {
+        options.tone_map = Some(ToneMap::ExtendedReinhard { white });
+    }
+
+    if let Some(output) = &options.output {
+        output_path(&output.to_string_lossy(), &RenderInfo::default()).map_err(CliError::Usage)?;
+        // A plain path given with --all is a directory, the images in it are PNG
+        let is_png = output
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+        let is_directory = render_all && !output.to_string_lossy().contains('{');
+        if !is_directory && !is_output_path(output) {
+            return Err(CliError::Usage(format!(
+                "can't write images to {}, the output needs one of the extensions {}",
+                output.display(),
+                OUTPUT_EXTENSIONS.join(", ")
+            )));
+        }
+        if options.png_16_bit && !is_png && !is_directory {
+            return Err(CliError::Usage(
+                "--png-16 only applies to .png output".to_owned(),
+            ));
+        }
+    }
+
+    if options.interactive && !HAS_WINDOW {
+        return Err(CliError::Usage(
+            "--interactive needs a build with the window feature".to_owned(),
+        ));
+    }
+    if options.interactive && (render_all || !options.show) {
+        return Err(CliError::Usage(
+            "--interactive needs the window, it can't be combined with --headless or --all"
+                .to_owned(),
+        ));
+    }
+
+    if render_all {
+        if scene_given {
+            return Err(CliError::Usage(
+                "--all can't be combined with a scene".to_owned(),
+            ));
+        }
+        options.show = false;
+        Ok(Command::RenderAll(options))
+    } else {
+        Ok(Command::Render(options))
+    }
+}
+
+pub fn run(command: Command) -> Result<(), CliError> {
+    match command {
+        Command::Help => println!("{}", USAGE),
+        Command::List => {
+            for (name, _) in DEMO_SCENES.iter() {
+                println!("{}", name);
+            }
+        }
+        Command::Render(options) => {
+            random::seed(options.seed.unwrap_or(0));
+            let scene = match &options.scene {
+                SceneSource::Demo(name) => demo_scene_with(name, options.bvh).ok_or_else(|| {
+                    CliError::Usage(format!(
+                        "unknown scene '{}', use --list to see the built-in scenes",
+                        name
+                    ))
+                })?,
+                SceneSource::File(path) => {
+                    load_scene_with(path, options.bvh).map_err(CliError::Scene)?
+                }
+            };
+            render(scene, &options, options.output.as_deref())?;
+        }
+        Command::RenderAll(options) => {
+            for (_, build) in DEMO_SCENES.iter() {
+                random::seed(options.seed.unwrap_or(0));
+                let scene = build(options.bvh);
+                // A plain path is the directory for the images
+                let output = options.output.as_ref().map(|dir| {
+                    if dir.to_string_lossy().contains('{') {
+                        dir.clone()
+                    } else {
+                        dir.join(Path::new(DEFAULT_OUTPUT_TEMPLATE).file_name().unwrap())
+                    }
+                });
+                render(scene, &options, output.as_deref())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn render(
+    mut scene: Scene,
+    options: &RenderOptions,
+    output: Option<&Path>,
+) -> Result<(), CliError> {
+    if let Some(width) = options.width {
+        scene.camera.image_width = width;
+    }
+    if let Some(samples) = options.samples {
+        scene.camera.samples_per_pixel = samples;
+    }
+    if let Some(depth) = options.depth {
+        scene.camera.max_depth = depth;
+    }
+    if let Some(tone_map) = options.tone_map {
+        scene.camera.tone_map = tone_map;
+    }
+    if let Some(exposure) = options.exposure {
+        scene.camera.exposure = exposure;
+    }
+    if let Some(seed) = options.seed {
+        scene.camera.seed = seed;
+    }
+    if let Some(sampler) = options.sampler {
+        scene.camera.sampler = sampler;
+    }
+    if let Some(tile_size) = options.tile_size {
+        scene.camera.tile_size = tile_size as u32;
+    }
+    if let Some(tile_order) = options.tile_order {
+        scene.camera.tile_order = tile_order;
+    }
+    if let Some(aovs) = &options.aovs {
+        scene.camera.aovs = aovs.clone();
+    }
+    if options.denoise && scene.camera.denoise.is_none() {
+        scene.camera.denoise = Some(Denoiser::new());
+    }
+    if options.spectral {
+        scene.camera.spectral = true;
+    }
+    if let Some(threshold) = options.adaptive {
+        let adaptive = scene
+            .camera
+            .adaptive
+            .get_or_insert(AdaptiveSampling::new(threshold));
+        adaptive.threshold = threshold;
+    }
+    if let Some(min_samples) = options.min_samples {
+        match &mut scene.camera.adaptive {
+            Some(adaptive) => adaptive.min_samples = min_samples as u32,
+            None => {
+                return Err(CliError::Usage(format!(
+                    "--min-samples needs --adaptive, {} doesn't sample adaptively",
+                    scene.name
+                )))
+            }
+        }
+    }
+
+    scene.camera.verbose = true;
+    println!("Rendering {}", scene.name);
+    if options.bvh_stats {
+        match scene.world.bvh_stats() {
+            Some(stats) => println!("BVH: {}", stats),
+            None => println!("BVH: none"),
+        }
+    }
+    #[cfg(feature = "window")]
+    if options.interactive {
+        println!("{}", CONTROLS);
+        let camera = explore(scene, options.threads);
+        println!("Final camera:\n{}", camera_code(&camera));
+        return Ok(());
+    }
+    let transform = scene.camera.display_transform();
+    let start = Instant::now();
+    let film = render_film(&mut scene, options);
+
+    if options.save {
+        let info = RenderInfo::new(&scene.name, &scene.camera, start.elapsed());
+        let template = match output {
+            Some(path) => path.to_string_lossy(),
+            None => DEFAULT_OUTPUT_TEMPLATE.into(),
+        };
+        let path = output_path(&template, &info).map_err(CliError::Usage)?;
+        let save_options = SaveOptions {
+            exr_compression: options.exr_compression,
+            png_16_bit: options.png_16_bit,
+            jpeg_quality: options
+                .jpeg_quality
+                .unwrap_or(SaveOptions::new().jpeg_quality),
+            info: Some(info),
+        };
+        save_film(&path, &film, &transform, &save_options)
+            .map_err(|error| CliError::Save(path.clone(), error))?;
+        println!("Saved {}", path.display());
+
+        if options.heatmap {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let heatmap_path = path.with_file_name(format!("{}_samples.png", stem));
+            save_image_to(
+                &heatmap_path,
+                &film.sample_heatmap(),
+                film.width(),
+                film.height(),
+            )
+            .map_err(|error| CliError::Save(heatmap_path.clone(), error))?;
+            println!("Saved {}", heatmap_path.display());
+        }
+        for (aov, aov_film) in film.aovs() {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let aov_path = path.with_file_name(format!("{}_{}.exr", stem, aov.name()));
+            save_film(&aov_path, aov_film, &transform, &save_options)
+                .map_err(|error| CliError::Save(aov_path.clone(), error))?;
+            println!("Saved {}", aov_path.display());
+        }
+    }
+    #[cfg(feature = "window")]
+    if options.show && !options.progressive {
+        render_buffer(film.to_argb(&transform), film.width(), film.height());
+    }
+    Ok(())
+}
+
+// Renders the scene, in the progressive preview window when it is shown with --progressive
+#[cfg(feature = "window")]
+fn render_film(scene: &mut Scene, options: &RenderOptions) -> Film {
+    if options.show && options.progressive {
+        let (width, height) = scene.camera.output_size();
+        let transform = scene.camera.display_transform();
+        let title = scene.name.clone();
+        show_progressive(&title, width, height, transform, |on_pass| {
+            scene.render_progressive(options.threads, on_pass)
+        })
+    } else {
+        scene.render(options.threads)
+    }
+}
+
+#[cfg(not(feature = "window"))]
+fn render_film(scene: &mut Scene, options: &RenderOptions) -> Film {
+    scene.render(options.threads)
+}
+
+fn scene_source(arg: &str) -> SceneSource {
+    if DEMO_SCENES.iter().any(|(name, _)| *name == arg) {
+        SceneSource::Demo(arg.to_owned())
+    } else if arg.contains('.') || arg.contains('/') || arg.contains('\\') {
+        SceneSource::File(PathBuf::from(arg))
+    } else {
+        SceneSource::Demo(arg.to_owned())
+    }
+}
+
+fn positive(option: &str, value: Option<String>) -> Result<i32, CliError> {
+    match value.as_deref().map(str::parse::<i32>) {
+        Some(Ok(number)) if number > 0 => Ok(number),
+        Some(_) => Err(CliError::Usage(format!(
+            "{} needs a positive whole number, got '{}'",
+            option,
+            value.unwrap()
+        ))),
+        None => Err(CliError::Usage(format!("{} needs a value", option))),
+    }
+}
+
+fn number(option: &str, value: Option<String>) -> Result<f64, CliError> {
+    match value.as_deref().map(str::parse::<f64>) {
+        Some(Ok(number)) if number.is_finite() => Ok(number),
+        Some(_) => Err(CliError::Usage(format!(
+            "{} needs a number, got '{}'",
+            option,
+            value.unwrap()
+        ))),
+        None => Err(CliError::Usage(format!("{} needs a value", option))),
+    }
+}
+
+fn split_method(option: &str, value: Option<String>) -> Result<SplitMethod, CliError> {
+    match value.as_deref() {
+        Some("sah") => Ok(SplitMethod::SurfaceAreaHeuristic),
+        Some("median") => Ok(SplitMethod::Median),
+        Some(other) => Err(CliError::Usage(format!(
+            "{} needs sah or median, got '{}'",
+            option, other
+        ))),
+        None => Err(CliError::Usage(format!("{} needs a value", option))),
+    }
+}
+
+fn aov_list(option: &str, value: Option<String>) -> Result<Vec<Aov>, CliError> {
+    let value = value.ok_or_else(|| CliError::Usage(format!("{} needs a list", option)))?;
+    if value == "all" {
+        return Ok(Aov::ALL.to_vec());
+    }
+    value
+        .split(',')
+        .map(|name| {
+            Aov::from_name(name.trim())
+                .ok_or_else(|| CliError::Usage(format!("unknown AOV '{}'", name)))
+        })
+        .collect()
+}
+
+fn default_threads() -> u32 {
+    std::thread::available_parallelism()
+        .map(|count| count.get() as u32)
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn render_overrides() {
+        let command = parse(&[
+            "cornell_box",
+            "-w",
+            "200",
+            "--samples",
+            "4",
+            "-d",
+            "8",
+            "-t",
+            "1",
+            "--tile-size",
+            "8",
+            "--tile-order",
+            "spiral",
+            "-o",
+            "out/box.png",
+            "--headless",
+            "--sampler",
+            "stratified",
+            "--adaptive",
+            "0.05",
+            "--heatmap",
+            "--aov",
+            "depth,normal",
+            "--denoise",
+            "--spectral",
+            "--progressive",
+            "--png-16",
+            "--jpeg-quality",
+            "75",
+        ])
+        .unwrap();
+        match command {
+            Command::Render(options) => {
+                assert_eq!(options.scene, SceneSource::Demo("cornell_box".to_owned()));
+                assert_eq!(options.width, Some(200));
+                assert_eq!(options.samples, Some(4));
+                assert_eq!(options.depth, Some(8));
+                assert_eq!(options.threads, 1);
+                assert_eq!(options.tile_size, Some(8));
+                assert_eq!(options.tile_order, Some(TileOrder::Spiral));
+                assert_eq!(options.output, Some(PathBuf::from("out/box.png")));
+                assert!(!options.show);
+                assert!(options.save);
+                assert_eq!(options.sampler, Some(SamplerKind::Stratified));
+                assert_eq!(options.adaptive, Some(0.05));
+                assert!(options.heatmap);
+                assert_eq!(options.aovs, Some(vec![Aov::Depth, Aov::Normal]));
+                assert!(options.denoise);
+                assert!(options.spectral);
+                assert!(options.progressive);
+                assert!(options.png_16_bit);
+                assert_eq!(options.jpeg_quality, Some(75));
+            }
+            _ => panic!("expected a render command"),
+        }
+    }
+
+    #[test]
+    fn tone_mapping() {
+        match parse(&[
+            "--tone-map",
+            "extended_reinhard",
+            "--white-point",
+            "6",
+            "--exposure",
+            "-1.5",
+        ])
+        .unwrap()
+        {
+            Command::Render(options) => {
+                assert_eq!(
+                    options.tone_map,
+                    Some(ToneMap::ExtendedReinhard { white: 6.0 })
+                );
+                assert_eq!(options.exposure, Some(-1.5));
+                assert_eq!(options.sampler, None);
+            }
+            _ => panic!("expected a render command"),
+        }
+    }
+
+    #[test]
+    fn scene_files() {
+        match parse(&["scenes/cornell_box.scene"]).unwrap() {
+            Command::Render(options) => assert_eq!(
+                options.scene,
+                SceneSource::File(PathBuf::from("scenes/cornell_box.scene"))
+            ),
+            _ => panic!("expected a render command"),
+        }
+    }
+
+    #[test]
+    fn usage_errors() {
+        for args in [
+            vec!["--width"],
+            vec!["--samples", "0"],
+            vec!["--depth", "deep"],
+            vec!["--fast"],
+            vec!["quads", "earth"],
+            vec!["--all", "quads"],
+            vec!["--bvh", "octree"],
+            vec!["--tone-map", "filmic"],
+            vec!["--exposure", "bright"],
+            vec!["--seed", "-1"],
+            vec!["--sampler", "random"],
+            vec!["--min-samples", "0"],
+            vec!["--adaptive", "0"],
+            vec!["--adaptive", "-1"],
+            vec!["--interactive", "--headless"],
+            vec!["--tile-order", "random"],
+            vec!["--jpeg-quality", "101"],
+            vec!["--aov", "normal,shadow"],
+            vec!["-o", "renders/{scene}.png"],
+            vec!["--png-16", "-o", "out/box.jpg"],
+            vec!["--all", "--png-16", "-o", "out/{name}.exr"],
+            vec!["-o", "out.xyz"],
+            vec!["-o", "renders/{name}"],
+        ] {
+            match parse(&args) {
+                Err(error) => assert_eq!(error.exit_code(), 2),
+                Ok(_) => panic!("{:?} should not parse", args),
+            }
+        }
+
+        // Only known once the scene is loaded, the check comes before rendering
+        let command = parse(&["quads", "--min-samples", "4", "--no-save"]).unwrap();
+        match run(command) {
+            Err(error) => assert_eq!(error.exit_code(), 2),
+            Ok(_) => panic!("--min-samples without adaptive sampling should fail"),
+        }
+    }
+}
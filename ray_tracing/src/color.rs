@@ -1,39 +1,185 @@
 use crate::interval::Interval;
 use nurbs::vector_3::Vec3;
 
+// Operator that maps linear scene radiance into the displayable [0, 1] range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    // Clips every channel at 1
+    Clamp,
+    // L / (1 + L) on the luminance, never reaches white
+    Reinhard,
+    // Reinhard with a white point, luminance at or above `white` maps to 1
+    ExtendedReinhard { white: f64 },
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMap {
+    // Looks an operator up by the name used in scene files and on the command line. The white
+    // point is only used by extended_reinhard.
+    pub fn from_name(name: &str, white: Option<f64>) -> Option<ToneMap> {
+        match name {
+            "clamp" => Some(ToneMap::Clamp),
+            "reinhard" => Some(ToneMap::Reinhard),
+            "extended_reinhard" => Some(ToneMap::ExtendedReinhard {
+                white: white.unwrap_or(4.0),
+            }),
+            "aces" => Some(ToneMap::Aces),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        match *self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard { white } => {
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMap::Aces => {
+                // The fit overshoots 1 slightly for bright values
+                let aces = |x: f64| {
+                    ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+                };
+                Vec3::new(aces(color.x), aces(color.y), aces(color.z))
+            }
+        }
+    }
+}
+
+// The conversion from film radiance to display values: exposure, tone mapping and the sRGB
+// transfer function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform {
+    pub tone_map: ToneMap,
+    // Exposure compensation in stops, every EV doubles the brightness
+    pub exposure: f64,
+}
+
+impl DisplayTransform {
+    pub fn new(tone_map: ToneMap, exposure: f64) -> DisplayTransform {
+        DisplayTransform { tone_map, exposure }
+    }
+
+    // Clamping at 0 EV, which matches the images from before tone mapping was added
+    pub fn clamp() -> DisplayTransform {
+        DisplayTransform::new(ToneMap::Clamp, 0.0)
+    }
+
+    pub fn to_val(&self, color: &Vec3) -> u32 {
+        let color = replace_nan(*color) * 2f64.powf(self.exposure);
+        let mapped = self.tone_map.apply(color);
+        encode_srgb(mapped.x, mapped.y, mapped.z)
+    }
+
+    // The sRGB encoded color in [0, 1] before it is quantised, for outputs deeper than 8 bits
+    pub fn to_display(&self, color: &Vec3) -> Vec3 {
+        let color = replace_nan(*color) * 2f64.powf(self.exposure);
+        let mapped = self.tone_map.apply(color);
+        let intensity = Interval::new(0.0, 1.0);
+        let encode = |value: f64| linear_to_srgb(intensity.clamp(value));
+        Vec3::new(encode(mapped.x), encode(mapped.y), encode(mapped.z))
+    }
+}
+
 pub fn vec_to_val(color: &Vec3, samples: i32) -> u32 {
     vals_to_val(color.x, color.y, color.z, samples)
 }
 
 pub fn vals_to_val(x: f64, y: f64, z: f64, samples: i32) -> u32 {
-    let intensity = Interval::new(0.0, 0.999);
+    // Divde color by the number of samples
     let scale = 1.0 / samples as f64;
-    let to_8bit = 255.999;
+    let color = replace_nan(Vec3::new(x, y, z)) * scale;
+    encode_srgb(color.x, color.y, color.z)
+}
 
-    // Replace NaN components with zero
-    let x = if x.is_nan() { 0.0 } else { x };
-    let y = if y.is_nan() { 0.0 } else { y };
-    let z = if z.is_nan() { 0.0 } else { z };
+// The piecewise sRGB OETF, linear near black and a 2.4 power curve above
+pub fn linear_to_srgb(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
 
-    // Divde color by the number of samples
-    let red = x * scale;
-    let green = y * scale;
-    let blue = z * scale;
+pub fn srgb_to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
 
-    // Apply the linear to gamma transform
-    let red = linear_to_gamma(red);
-    let green = linear_to_gamma(green);
-    let blue = linear_to_gamma(blue);
+pub fn luminance(color: Vec3) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
 
-    // Clamp the colors
-    let red = (intensity.clamp(red) * to_8bit) as u8;
-    let green = (intensity.clamp(green) * to_8bit) as u8;
-    let blue = (intensity.clamp(blue) * to_8bit) as u8;
+fn scale_luminance(color: Vec3, curve: impl Fn(f64) -> f64) -> Vec3 {
+    let l = luminance(color);
+    if l <= 0.0 {
+        color
+    } else {
+        color * (curve(l) / l)
+    }
+}
+
+// Replace NaN components with zero
+fn replace_nan(color: Vec3) -> Vec3 {
+    let clean = |x: f64| if x.is_nan() { 0.0 } else { x };
+    Vec3::new(clean(color.x), clean(color.y), clean(color.z))
+}
+
+// Clamps display-referred linear values, applies the sRGB curve and packs them as 0xFFRRGGBB
+fn encode_srgb(x: f64, y: f64, z: f64) -> u32 {
+    let intensity = Interval::new(0.0, 1.0);
+    let to_8bit = |value: f64| (linear_to_srgb(intensity.clamp(value)) * 255.0 + 0.5) as u8;
+
+    let red = to_8bit(x);
+    let green = to_8bit(y);
+    let blue = to_8bit(z);
 
     // Return a u32
     0xFF000000 | blue as u32 | ((green as u32) << 8) | ((red as u32) << 16)
 }
 
-fn linear_to_gamma(x: f64) -> f64 {
-    x.sqrt()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_curve() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-12);
+        assert!((linear_to_srgb(0.18) - 0.4614).abs() < 1e-3);
+        for x in [0.001, 0.0031308, 0.2, 0.7] {
+            assert!((srgb_to_linear(linear_to_srgb(x)) - x).abs() < 1e-12);
+        }
+        assert_eq!(vec_to_val(&Vec3::new(0.5, 0.0, 2.0), 1), 0xFFBC00FF);
+    }
+
+    #[test]
+    fn tone_maps_compress_highlights() {
+        let bright = Vec3::new(8.0, 8.0, 8.0);
+        for tone_map in [
+            ToneMap::Reinhard,
+            ToneMap::ExtendedReinhard { white: 16.0 },
+            ToneMap::Aces,
+        ] {
+            let mapped = tone_map.apply(bright);
+            assert!(mapped.x > 0.5 && mapped.x <= 1.0, "{:?}", tone_map);
+            assert!(tone_map.apply(Vec3::new(0.0, 0.0, 0.0)).x.abs() < 1e-12);
+        }
+        let white = ToneMap::ExtendedReinhard { white: 8.0 }.apply(bright);
+        assert!((white.x - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn exposure_doubles_per_stop() {
+        let half = DisplayTransform::new(ToneMap::Clamp, -1.0);
+        let full = DisplayTransform::clamp();
+        assert_eq!(
+            half.to_val(&Vec3::new(1.0, 1.0, 1.0)),
+            full.to_val(&Vec3::new(0.5, 0.5, 0.5))
+        );
+    }
 }
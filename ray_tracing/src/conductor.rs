@@ -0,0 +1,227 @@
+use crate::ggx::GGX;
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterPDF, ScatterRecord};
+use crate::microfacet_pdf::MicrofacetPDF;
+use crate::orthonormal_basis::OrthonormalBasis;
+use crate::pdf::PDF;
+use crate::ray::Ray3;
+use nurbs::vector_3::Vec3;
+
+// Complex indices of refraction of measured metals, the real part eta and the extinction k,
+// for red, green and blue light (650, 550 and 450 nm)
+pub const CONDUCTOR_PRESETS: [(&str, [f64; 3], [f64; 3]); 4] = [
+    ("gold", [0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+    ("copper", [0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+    ("aluminium", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+    ("silver", [0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+];
+
+// A metal with a rough GGX microfacet surface and the Fresnel reflectance of its complex index
+// of refraction. Unlike `Metal` it is energy conserving and importance sampled through the
+// visible normals, so it takes part in light sampling. Anisotropic highlights are stretched
+// along dpdu of the hit, surfaces without tangents use the u axis of the `OrthonormalBasis`
+// around the normal instead.
+pub struct Conductor {
+    eta: Vec3,
+    k: Vec3,
+    distribution: GGX,
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray_in: &Ray3, hit_rec: &HitRecord, scatter_rec: &mut ScatterRecord) -> bool {
+        let direction = ray_in.direction().unit_vector();
+        if self.distribution.is_smooth() {
+            let cos_theta = -direction.dot(&hit_rec.normal);
+            scatter_rec.attenuation = fresnel_conductor(cos_theta, self.eta, self.k);
+            scatter_rec.pdf = ScatterPDF::Skip(Ray3::new(
+                hit_rec.point,
+                direction.reflect(&hit_rec.normal),
+                ray_in.time(),
+            ));
+            return true;
+        }
+
+        // The colour depends on the direction, `scattering` works it out. The reflectance
+        // head on stands in for it as the albedo.
+        scatter_rec.attenuation = fresnel_conductor(1.0, self.eta, self.k);
+        scatter_rec.pdf = ScatterPDF::PDF(Box::new(MicrofacetPDF::from_basis(
+            basis(hit_rec),
+            &direction,
+            self.distribution,
+        )));
+        true
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray3, hit_rec: &HitRecord, scattered: &Ray3) -> f64 {
+        MicrofacetPDF::from_basis(basis(hit_rec), &ray_in.direction(), self.distribution)
+            .value(&scattered.direction())
+    }
+
+    fn scattering(
+        &self,
+        ray_in: &Ray3,
+        hit_rec: &HitRecord,
+        _attenuation: Vec3,
+        scattered: &Ray3,
+    ) -> Vec3 {
+        let uvw = basis(hit_rec);
+        let wo = uvw.to_local(-ray_in.direction().unit_vector());
+        let wi = uvw.to_local(scattered.direction().unit_vector());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::empty();
+        }
+        // F D G / (4 cos_o cos_i) times cos_i
+        let m = (wo + wi).unit_vector();
+        let fresnel = fresnel_conductor(wo.dot(&m), self.eta, self.k);
+        fresnel * (self.distribution.d(&m) * self.distribution.g2(&wo, &wi) / (4.0 * wo.z))
+    }
+}
+
+impl Conductor {
+    // `roughness` in [0, 1], 0 is a mirror. `anisotropy` in [-1, 1] stretches the highlights.
+    pub fn new(eta: Vec3, k: Vec3, roughness: f64, anisotropy: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: GGX::from_roughness(roughness, anisotropy),
+        }
+    }
+
+    // One of the metals in `CONDUCTOR_PRESETS`, aluminum is accepted too
+    pub fn preset(name: &str, roughness: f64, anisotropy: f64) -> Option<Conductor> {
+        let name = if name == "aluminum" {
+            "aluminium"
+        } else {
+            name
+        };
+        CONDUCTOR_PRESETS
+            .iter()
+            .find(|(preset, _, _)| *preset == name)
+            .map(|(_, [er, eg, eb], [kr, kg, kb])| {
+                Conductor::new(
+                    Vec3::new(*er, *eg, *eb),
+                    Vec3::new(*kr, *kg, *kb),
+                    roughness,
+                    anisotropy,
+                )
+            })
+    }
+}
+
+// The frame the microfacet distribution is oriented in
+fn basis(hit_rec: &HitRecord) -> OrthonormalBasis {
+    OrthonormalBasis::from_tangent(&hit_rec.normal, &hit_rec.dpdu)
+}
+
+// Unpolarised Fresnel reflectance of a conductor in air, per colour channel
+pub fn fresnel_conductor(cos_theta: f64, eta: Vec3, k: Vec3) -> Vec3 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_theta * cos_theta;
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cos_theta * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    };
+    Vec3::new(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_reflect_their_colour() {
+        let gold = Conductor::preset("gold", 0.0, 0.0).unwrap();
+        let head_on = fresnel_conductor(1.0, gold.eta, gold.k);
+        assert!(head_on.x > 0.9 && head_on.x > head_on.y && head_on.y > head_on.z);
+        // Every metal turns into a mirror at grazing angles
+        let grazing = fresnel_conductor(0.0, gold.eta, gold.k);
+        assert!((grazing - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-9);
+        assert!(Conductor::preset("aluminum", 0.5, 0.0).is_some());
+        assert!(Conductor::preset("tin", 0.5, 0.0).is_none());
+    }
+
+    #[test]
+    fn reflects_no_more_than_it_receives() {
+        // The reflected share of light arriving from one direction, estimated with the
+        // material's own sampling, can't exceed the reflectance of silver. Light that would
+        // bounce between the microfacets more than once is lost, which only matters once the
+        // surface is rough.
+        let mut hit_rec = HitRecord::new();
+        hit_rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        hit_rec.dpdu = Vec3::new(1.0, 1.0, 0.0);
+        let ray_in = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.6, 0.0, -0.8), 0.0);
+        for roughness in [0.2, 0.5, 1.0] {
+            let silver = Conductor::preset("silver", roughness, 0.3).unwrap();
+            let pdf = MicrofacetPDF::from_basis(
+                basis(&hit_rec),
+                &ray_in.direction(),
+                silver.distribution,
+            );
+            let samples = 20_000;
+            let mut albedo = Vec3::empty();
+            for _ in 0..samples {
+                let scattered = Ray3::new(hit_rec.point, pdf.generate(), 0.0);
+                let value = pdf.value(&scattered.direction());
+                if value > 0.0 {
+                    let scattering =
+                        silver.scattering(&ray_in, &hit_rec, Vec3::empty(), &scattered);
+                    albedo += scattering / value;
+                }
+            }
+            let albedo = albedo / samples as f64;
+            let reflectance = fresnel_conductor(0.8, silver.eta, silver.k);
+            assert!(
+                albedo.x <= reflectance.x * 1.01,
+                "{} {:?}",
+                roughness,
+                albedo
+            );
+            if roughness <= 0.2 {
+                assert!(albedo.x > reflectance.x * 0.95, "{:?}", albedo);
+            }
+        }
+    }
+
+    #[test]
+    fn highlights_follow_the_tangent() {
+        // Turning the tangent with everything else turns the reflection with it, and the
+        // stretched highlight looks different along and across the tangent
+        let brushed = Conductor::preset("aluminium", 0.4, 0.8).unwrap();
+        let reflection = |dpdu: Vec3, ray: Vec3, scattered: Vec3| {
+            let mut hit_rec = HitRecord::new();
+            hit_rec.normal = Vec3::new(0.0, 0.0, 1.0);
+            hit_rec.dpdu = dpdu;
+            let ray_in = Ray3::new(Vec3::empty(), ray, 0.0);
+            let scattered = Ray3::new(Vec3::empty(), scattered, 0.0);
+            (
+                brushed.scattering(&ray_in, &hit_rec, Vec3::empty(), &scattered),
+                brushed.scattering_pdf(&ray_in, &hit_rec, &scattered),
+            )
+        };
+        let (ray, scattered) = (Vec3::new(0.6, 0.0, -0.8), Vec3::new(-0.3, 0.5, 0.8));
+        let along = reflection(Vec3::new(2.0, 0.0, 0.0), ray, scattered);
+        let turn = |v: Vec3| Vec3::new(-v.y, v.x, v.z);
+        let turned = reflection(Vec3::new(0.0, 2.0, 0.0), turn(ray), turn(scattered));
+        assert!((along.0 - turned.0).length() < 1e-9 && (along.1 - turned.1).abs() < 1e-9);
+        let across = reflection(Vec3::new(0.0, 2.0, 0.0), ray, scattered);
+        assert!(
+            (along.1 - across.1).abs() > 0.1 * along.1,
+            "{:?} {:?}",
+            along,
+            across
+        );
+    }
+}
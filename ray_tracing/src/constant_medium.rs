@@ -4,6 +4,8 @@ use crate::interval::Interval;
 use crate::isotropic::Isotropic;
 use crate::material::Material;
 use crate::ray::Ray3;
+use crate::sampler::sample_1d;
+use crate::texture::Texture;
 use nurbs::vector_3::Vec3;
 use std::f64::INFINITY;
 use std::sync::Arc;
@@ -41,7 +43,7 @@ impl Hittable for ConstantMedium {
 
                 let ray_length = ray_in.direction().length();
                 let distance_inside_boundary = (rec_2.time - rec_1.time) * ray_length;
-                let hit_dist = self.neg_inv_density * rand::random::<f64>().log10();
+                let hit_dist = self.neg_inv_density * sample_1d().log10();
 
                 if hit_dist > distance_inside_boundary {
                     false
@@ -51,6 +53,7 @@ impl Hittable for ConstantMedium {
                     hit_record.normal = Vec3::new(1.0, 0.0, 0.0);
                     hit_record.front_face = true;
                     hit_record.mat = Arc::clone(&self.phase_func);
+                    hit_record.object = HitRecord::object_id(self);
                     true
                 }
             }
@@ -67,17 +70,17 @@ impl Hittable for ConstantMedium {
 }
 
 impl ConstantMedium {
-    // pub fn new(
-    //     boundary: Rc<dyn Hittable>,
-    //     density: f64,
-    //     phase_func: Arc<dyn Texture>,
-    // ) -> ConstantMedium {
-    //     ConstantMedium {
-    //         boundary,
-    //         phase_func: Arc::new(Isotropic::new(phase_func)),
-    //         neg_inv_density: -1.0 / density,
-    //     }
-    // }
+    pub fn new(
+        boundary: Arc<dyn Hittable + Sync + Send>,
+        density: f64,
+        phase_func: Arc<dyn Texture + Sync + Send>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            phase_func: Arc::new(Isotropic::new(phase_func)),
+            neg_inv_density: -1.0 / density,
+        }
+    }
 
     pub fn color(
         boundary: Arc<dyn Hittable + Sync + Send>,
@@ -1,5 +1,6 @@
 use crate::orthonormal_basis::OrthonormalBasis;
 use crate::pdf::PDF;
+use crate::sampler::sample_2d;
 use nurbs::vector_3::Vec3;
 use std::f64::consts::FRAC_1_PI;
 
@@ -14,7 +15,8 @@ impl PDF for CosinePDF {
     }
 
     fn generate(&self) -> Vec3 {
-        self.uvw.transform(Vec3::random_cosine_direction())
+        let (r1, r2) = sample_2d();
+        self.uvw.transform(Vec3::cosine_direction(r1, r2))
     }
 }
 
@@ -1,9 +1,9 @@
 use crate::affine_transforms::{RotateY, Translate};
-use crate::bvh_node::BVHNode;
+use crate::bvh_node::{BVHNode, SplitMethod};
 use crate::camera::Camera;
 use crate::checker_texture::CheckerTexture;
 use crate::constant_medium::ConstantMedium;
-use crate::dielectric::Dielectric;
+use crate::dielectric::{Dielectric, Dispersion};
 use crate::diffuse_light::DiffuseLight;
 use crate::hittable::Hittable;
 use crate::hittable_list::HittableList;
@@ -13,14 +13,48 @@ use crate::material::Material;
 use crate::metal::Metal;
 use crate::noise_texture::NoiseTexture;
 use crate::quad::{quad_box, Quad};
-use crate::save_show::{render_buffer, save_image};
+use crate::scene::Scene;
 use crate::sphere::Sphere;
 use crate::texture::Texture;
-use nurbs::{utility::random_f64_range, vector_3::Vec3};
-use rand;
+use crate::triangle::prism;
+use nurbs::{random::random_f64, utility::random_f64_range, vector_3::Vec3};
 use std::sync::Arc;
 
-pub fn final_render_book1(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+const DEFAULT_WIDTH: i32 = 800;
+const DEFAULT_SAMPLES: i32 = 10;
+const DEFAULT_DEPTH: i32 = 50;
+
+// Builds a scene, with the split method for any BVHs it makes
+pub type SceneBuilder = fn(SplitMethod) -> Scene;
+
+// Built-in scenes by the name used on the command line
+pub const DEMO_SCENES: [(&str, SceneBuilder); 12] = [
+    ("book1_final", final_render_book1),
+    ("checker_spheres", |_| checker_spheres()),
+    ("earth", |_| earth()),
+    ("perlin_spheres", |_| two_perlin_sphere()),
+    ("quads", |_| quads()),
+    ("simple_light", |_| simple_light()),
+    ("cornell_box", |_| cornell_box()),
+    ("cornell_smoke", |_| cornell_smoke()),
+    ("book2_final", final_scene_book2),
+    ("cornell_box_metal", |_| cornell_box_metal()),
+    ("cornell_box_glass_sphere", |_| cornell_box_glass_sphere()),
+    ("prism", |_| glass_prism()),
+];
+
+pub fn demo_scene(name: &str) -> Option<Scene> {
+    demo_scene_with(name, SplitMethod::default())
+}
+
+pub fn demo_scene_with(name: &str, split_method: SplitMethod) -> Option<Scene> {
+    DEMO_SCENES
+        .iter()
+        .find(|(demo_name, _)| *demo_name == name)
+        .map(|(_, build)| build(split_method))
+}
+
+pub fn final_render_book1(split_method: SplitMethod) -> Scene {
     // World
     let mut build_world = HittableList::new();
 
@@ -36,11 +70,11 @@ pub fn final_render_book1(width: i32, samples: i32, depth: i32, show: bool, save
     let compare_vec = Vec3::new(4.0, 0.2, 0.0);
     for a in -11..11 {
         for b in -11..11 {
-            let rand_mat = rand::random::<f64>();
+            let rand_mat = random_f64();
             let center = Vec3::new(
-                a as f64 + (0.9 * rand::random::<f64>()),
+                a as f64 + (0.9 * random_f64()),
                 0.2,
-                b as f64 + (0.9 * rand::random::<f64>()),
+                b as f64 + (0.9 * random_f64()),
             );
 
             if (center - compare_vec).length() > 0.9 {
@@ -48,7 +82,7 @@ pub fn final_render_book1(width: i32, samples: i32, depth: i32, show: bool, save
                 if rand_mat < 0.8 {
                     let albedo = Vec3::random() * Vec3::random();
                     mat = Arc::new(Lambertian::from_color(albedo));
-                    let center_end = center + Vec3::new(0.0, rand::random::<f64>() * 0.25, 0.0);
+                    let center_end = center + Vec3::new(0.0, random_f64() * 0.25, 0.0);
                     build_world.add(Arc::new(Sphere::new_moving(center, 0.2, mat, center_end)));
                 } else if rand_mat < 0.95 {
                     let albedo = Vec3::random_range(0.5, 1.0);
@@ -75,14 +109,17 @@ pub fn final_render_book1(width: i32, samples: i32, depth: i32, show: bool, save
 
     // Make the world into a bvh
     let mut world = HittableList::new();
-    world.add(Arc::new(BVHNode::from_list(&build_world)));
+    world.add(Arc::new(BVHNode::from_list_with(
+        &build_world,
+        split_method,
+    )));
 
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
 
     cam.vfov = 20.0;
     cam.look_from = Vec3::new(13.0, 2.0, 3.0);
@@ -92,28 +129,10 @@ pub fn final_render_book1(width: i32, samples: i32, depth: i32, show: bool, save
     cam.defocus_angle = 0.6;
     cam.focus_dist = 10.0;
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, true, 6);
-    if save {
-        save_image(
-            "Book1_Final",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Book1_Final", Arc::new(world), None, cam)
 }
 
-pub fn checker_spheres(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn checker_spheres() -> Scene {
     // World
     let mut world = HittableList::new();
 
@@ -138,9 +157,9 @@ pub fn checker_spheres(width: i32, samples: i32, depth: i32, show: bool, save: b
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
 
     cam.vfov = 20.0;
     cam.look_from = Vec3::new(13.0, 2.0, 3.0);
@@ -150,28 +169,10 @@ pub fn checker_spheres(width: i32, samples: i32, depth: i32, show: bool, save: b
     cam.defocus_angle = 0.0;
     cam.focus_dist = 10.0;
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, true, 6);
-    if save {
-        save_image(
-            "Checker_Spheres",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Checker_Spheres", Arc::new(world), None, cam)
 }
 
-pub fn earth(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn earth() -> Scene {
     let mut world = HittableList::new();
 
     let earth_texture = Arc::new(ImageTexture::new("ray_tracing/src/earthmap.jpg"));
@@ -185,9 +186,9 @@ pub fn earth(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
 
     cam.vfov = 20.0;
     cam.look_from = Vec3::new(0.0, 0.0, 12.0);
@@ -196,28 +197,10 @@ pub fn earth(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, false, 6);
-    if save {
-        save_image(
-            "Earth",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Earth", Arc::new(world), None, cam)
 }
 
-pub fn two_perlin_sphere(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn two_perlin_sphere() -> Scene {
     let mut world = HittableList::new();
 
     let pertext: Arc<dyn Texture + Sync + Send> = Arc::new(NoiseTexture::new(4.0));
@@ -233,9 +216,9 @@ pub fn two_perlin_sphere(width: i32, samples: i32, depth: i32, show: bool, save:
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
 
     cam.vfov = 20.0;
     cam.look_from = Vec3::new(13.0, 2.0, 3.0);
@@ -244,28 +227,10 @@ pub fn two_perlin_sphere(width: i32, samples: i32, depth: i32, show: bool, save:
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, true, 6);
-    if save {
-        save_image(
-            "Perlin_Sphere",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Perlin_Sphere", Arc::new(world), None, cam)
 }
 
-pub fn quads(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn quads() -> Scene {
     let mut world = HittableList::new();
 
     let red = Arc::new(Lambertian::from_color(Vec3::new(1.0, 0.2, 0.2)));
@@ -308,9 +273,9 @@ pub fn quads(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
 
     cam.vfov = 80.0;
     cam.look_from = Vec3::new(0.0, 0.0, 9.0);
@@ -319,28 +284,10 @@ pub fn quads(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, true, 6);
-    if save {
-        save_image(
-            "Quads",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Quads", Arc::new(world), None, cam)
 }
 
-pub fn simple_light(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn simple_light() -> Scene {
     let mut world = HittableList::new();
 
     let pertext: Arc<dyn Material + Sync + Send> =
@@ -378,9 +325,9 @@ pub fn simple_light(width: i32, samples: i32, depth: i32, show: bool, save: bool
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
     cam.background = Vec3::new(0.0, 0.0, 0.0);
 
     cam.vfov = 20.0;
@@ -390,29 +337,10 @@ pub fn simple_light(width: i32, samples: i32, depth: i32, show: bool, save: bool
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(world);
-    let lights = Arc::new(lights);
-    let buffer = cam.render(world, Some(lights), true, 6);
-    if save {
-        save_image(
-            "Simple_Light",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Simple_Light", Arc::new(world), Some(Arc::new(lights)), cam)
 }
 
-pub fn cornell_box(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn cornell_box() -> Scene {
     let mut world = HittableList::new();
 
     let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
@@ -482,9 +410,9 @@ pub fn cornell_box(width: i32, samples: i32, depth: i32, show: bool, save: bool)
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
     cam.background = Vec3::new(0.0, 0.0, 0.0);
 
     cam.vfov = 40.0;
@@ -494,30 +422,10 @@ pub fn cornell_box(width: i32, samples: i32, depth: i32, show: bool, save: bool)
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, Some(light), true, 6);
-
-    if save {
-        save_image(
-            "Cornell_Box",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Cornell_Box", Arc::new(world), Some(light), cam)
 }
 
-pub fn cornell_smoke(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn cornell_smoke() -> Scene {
     let mut world = HittableList::new();
     let mut lights = HittableList::new();
 
@@ -597,9 +505,9 @@ pub fn cornell_smoke(width: i32, samples: i32, depth: i32, show: bool, save: boo
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
     cam.background = Vec3::new(0.0, 0.0, 0.0);
 
     cam.vfov = 40.0;
@@ -609,31 +517,15 @@ pub fn cornell_smoke(width: i32, samples: i32, depth: i32, show: bool, save: boo
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(world);
-    let lights = Arc::new(lights);
-    let buffer = cam.render(world, Some(lights), true, 6);
-
-    if save {
-        save_image(
-            "Cornell_Smoke",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new(
+        "Cornell_Smoke",
+        Arc::new(world),
+        Some(Arc::new(lights)),
+        cam,
+    )
 }
 
-pub fn final_scene_book2(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn final_scene_book2(split_method: SplitMethod) -> Scene {
     // Create the ground
     let mut boxes_1 = HittableList::new();
     let ground_mat: Arc<dyn Material + Sync + Send> =
@@ -660,7 +552,7 @@ pub fn final_scene_book2(width: i32, samples: i32, depth: i32, show: bool, save:
 
     // Create the scene
     let mut scene = HittableList::new();
-    scene.add(Arc::new(BVHNode::from_list(&boxes_1)));
+    scene.add(Arc::new(BVHNode::from_list_with(&boxes_1, split_method)));
 
     // Light
     let diff_light = Arc::new(DiffuseLight::color(Vec3::new(7.0, 7.0, 7.0)));
@@ -746,16 +638,19 @@ pub fn final_scene_book2(width: i32, samples: i32, depth: i32, show: bool, save:
         )));
     }
     scene.add(Arc::new(Translate::new(
-        Arc::new(RotateY::new(Arc::new(BVHNode::from_list(&spheres)), 15.0)),
+        Arc::new(RotateY::new(
+            Arc::new(BVHNode::from_list_with(&spheres, split_method)),
+            15.0,
+        )),
         Vec3::new(-100.0, 270.0, 395.0),
     )));
 
     // Render
     let mut cam = Camera::new();
     cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
     cam.background = Vec3::new(0.0, 0.0, 0.0);
 
     cam.vfov = 40.0;
@@ -765,31 +660,10 @@ pub fn final_scene_book2(width: i32, samples: i32, depth: i32, show: bool, save:
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(scene);
-    let buffer = cam.render(world, Some(light), true, 6);
-
-    if save {
-        save_image(
-            "Book2_Final",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-
-    // Render to a window
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Book2_Final", Arc::new(scene), Some(light), cam)
 }
 
-pub fn cornell_box_metal(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn cornell_box_metal() -> Scene {
     let mut world = HittableList::new();
 
     let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
@@ -861,9 +735,9 @@ pub fn cornell_box_metal(width: i32, samples: i32, depth: i32, show: bool, save:
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
     cam.background = Vec3::new(0.0, 0.0, 0.0);
 
     cam.vfov = 40.0;
@@ -873,30 +747,10 @@ pub fn cornell_box_metal(width: i32, samples: i32, depth: i32, show: bool, save:
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, Some(light), true, 7);
-
-    if save {
-        save_image(
-            "Cornell_Box_Metal",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
-
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
-    }
+    Scene::new("Cornell_Box_Metal", Arc::new(world), Some(light), cam)
 }
 
-pub fn cornell_box_glass_sphere(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
+pub fn cornell_box_glass_sphere() -> Scene {
     let mut world = HittableList::new();
 
     let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
@@ -961,9 +815,9 @@ pub fn cornell_box_glass_sphere(width: i32, samples: i32, depth: i32, show: bool
     // Camera
     let mut cam = Camera::new();
     cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = DEFAULT_SAMPLES;
+    cam.max_depth = DEFAULT_DEPTH;
     cam.background = Vec3::new(0.0, 0.0, 0.0);
 
     cam.vfov = 40.0;
@@ -973,25 +827,67 @@ pub fn cornell_box_glass_sphere(width: i32, samples: i32, depth: i32, show: bool
 
     cam.defocus_angle = 0.0;
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, Some(light), true, 7);
-
-    if save {
-        save_image(
-            "Cornell_Box_Glass_Sphere",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
-    }
+    Scene::new(
+        "Cornell_Box_Glass_Sphere",
+        Arc::new(world),
+        Some(light),
+        cam,
+    )
+}
+
+// A dense flint glass prism in front of white light bars, which it splits into their colors
+// in a spectral render
+pub fn glass_prism() -> Scene {
+    let mut world = HittableList::new();
 
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+    let floor = Arc::new(Lambertian::from_color(Vec3::new(0.4, 0.4, 0.4)));
+    let light_mat: Arc<dyn Material + Sync + Send> =
+        Arc::new(DiffuseLight::color(Vec3::new(8.0, 8.0, 8.0)));
+    let flint = Arc::new(Dielectric::new_dispersive(Dispersion::SF11, None));
+
+    world.add(Arc::new(Quad::new(
+        Vec3::new(-10.0, 0.0, -10.0),
+        Vec3::new(20.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 20.0),
+        floor,
+    )));
+
+    // Bars facing the camera
+    let mut bars = HittableList::new();
+    for y in [0.3, 0.9, 1.5, 2.1] {
+        bars.add(Arc::new(Quad::new(
+            Vec3::new(-3.0, y, 4.0),
+            Vec3::new(0.0, 0.06, 0.0),
+            Vec3::new(6.0, 0.0, 0.0),
+            Arc::clone(&light_mat),
+        )));
     }
-}
\ No newline at end of file
+    let bars: Arc<dyn Hittable + Sync + Send> = Arc::new(bars);
+    world.add(Arc::clone(&bars));
+
+    world.add(prism(
+        Vec3::new(-1.5, 0.5, -0.4),
+        Vec3::new(-1.5, 0.5, 0.4),
+        Vec3::new(-1.5, 1.4, 0.0),
+        Vec3::new(3.0, 0.0, 0.0),
+        flint,
+    ));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = DEFAULT_WIDTH;
+    cam.samples_per_pixel = 64;
+    cam.max_depth = 20;
+    cam.background = Vec3::new(0.0, 0.0, 0.0);
+    cam.spectral = true;
+
+    cam.vfov = 30.0;
+    cam.look_from = Vec3::new(0.0, 1.2, -6.0);
+    cam.look_at = Vec3::new(0.0, 0.9, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    Scene::new("Prism", Arc::new(world), Some(bars), cam)
+}
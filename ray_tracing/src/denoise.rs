@@ -0,0 +1,360 @@
+use crate::aov::Aov;
+use crate::color::luminance;
+use crate::film::Film;
+use nurbs::vector_3::Vec3;
+
+// Taps of the B3 spline the filter spreads further apart every iteration
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Albedo channels darker than this are left out of the demodulation, the film can't be divided
+// by them
+const MIN_ALBEDO: f64 = 1e-3;
+
+// Removes Monte Carlo noise from a finished film with an edge-avoiding à-trous wavelet filter
+// (Dammertz et al. 2010) and the variance guided colour weight of SVGF (Schied et al. 2017).
+// The normal, albedo and depth AOVs of the film stop it from blurring across edges and the
+// colours are divided by the albedo while they are filtered so textures stay sharp. Guides the
+// film doesn't have are ignored, without any the filter only follows the colours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    // Filter passes, each one doubles the reach. 5 passes reach 62 pixels in every direction.
+    // Counts outside `ITERATIONS` are clamped into it.
+    pub iterations: u32,
+    // How many standard deviations of a pixel's noise a neighbour's luminance may differ by
+    // before it stops counting
+    pub color_sigma: f64,
+    // Exponent on the cosine between two normals
+    pub normal_power: f64,
+    // Allowed depth difference in multiples of what the depth gradient predicts
+    pub depth_sigma: f64,
+    // Albedo difference at which a neighbour's weight falls to 1/e
+    pub albedo_sigma: f64,
+}
+
+impl Denoiser {
+    // The AOVs the filter is guided by, a camera with a denoiser renders them
+    pub const GUIDES: [Aov; 3] = [Aov::Normal, Aov::Albedo, Aov::Depth];
+
+    // The pass counts that make sense, 16 passes already reach further than any image is wide
+    pub const ITERATIONS: std::ops::RangeInclusive<u32> = 1..=16;
+
+    pub fn new() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            color_sigma: 4.0,
+            normal_power: 128.0,
+            depth_sigma: 1.0,
+            albedo_sigma: 0.1,
+        }
+    }
+
+    // Filters the pixels of `film` in place, its AOVs and sample counts are left as they are
+    pub fn denoise(&self, film: &mut Film) {
+        let (width, height) = (film.width() as usize, film.height() as usize);
+        let guides = Guides::new(film);
+
+        // Demodulated colours, and the variances that go with them
+        let modulation: Vec<Vec3> = match &guides.albedo {
+            Some(albedo) => albedo
+                .iter()
+                .map(|a| {
+                    let channel = |value: f64| if value < MIN_ALBEDO { 1.0 } else { value };
+                    Vec3::new(channel(a.x), channel(a.y), channel(a.z))
+                })
+                .collect(),
+            None => vec![Vec3::new(1.0, 1.0, 1.0); width * height],
+        };
+        let mut colors: Vec<Vec3> = pixel_vectors(film)
+            .into_iter()
+            .zip(&modulation)
+            .map(|(color, &m)| color / m)
+            .collect();
+        let spatial = spatial_variances(&colors, width, height);
+        let mut variances: Vec<f64> = (0..width * height)
+            .map(|index| {
+                let variance = film.variance((index % width) as u32, (index / width) as u32);
+                if variance.is_finite() {
+                    variance / luminance(modulation[index]).powi(2)
+                } else {
+                    spatial[index]
+                }
+            })
+            .collect();
+
+        let iterations = self
+            .iterations
+            .clamp(*Self::ITERATIONS.start(), *Self::ITERATIONS.end());
+        for iteration in 0..iterations {
+            let step = 1 << iteration;
+            let sigmas: Vec<f64> = blur_3x3(&variances, width, height)
+                .into_iter()
+                .map(|variance| self.color_sigma * variance.max(0.0).sqrt() + 1e-6)
+                .collect();
+            let mut next_colors = colors.clone();
+            let mut next_variances = variances.clone();
+            for y in 0..height {
+                for x in 0..width {
+                    let p = y * width + x;
+                    let center = luminance(colors[p]);
+                    let mut weight_sum = 0.0;
+                    let mut color_sum = Vec3::empty();
+                    let mut variance_sum = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as i64 + (j as i64 - 2) * step;
+                        if qy < 0 || qy >= height as i64 {
+                            continue;
+                        }
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as i64 + (i as i64 - 2) * step;
+                            if qx < 0 || qx >= width as i64 {
+                                continue;
+                            }
+                            let q = qy as usize * width + qx as usize;
+                            let distance = (i.abs_diff(2).max(j.abs_diff(2)) as i64 * step) as f64;
+                            let color_weight =
+                                (-(luminance(colors[q]) - center).abs() / sigmas[p]).exp();
+                            let weight =
+                                kx * ky * color_weight * self.guide_weight(&guides, p, q, distance);
+                            if weight.is_finite() && weight > 0.0 {
+                                weight_sum += weight;
+                                color_sum += colors[q] * weight;
+                                variance_sum += variances[q] * weight * weight;
+                            }
+                        }
+                    }
+                    if weight_sum > 0.0 {
+                        next_colors[p] = color_sum / weight_sum;
+                        next_variances[p] = variance_sum / (weight_sum * weight_sum);
+                    }
+                }
+            }
+            colors = next_colors;
+            variances = next_variances;
+        }
+
+        for (index, (color, m)) in colors.into_iter().zip(modulation).enumerate() {
+            film.set_pixel((index % width) as u32, (index / width) as u32, color * m);
+        }
+    }
+
+    // How much neighbour q counts for pixel p according to the AOVs, `distance` is in pixels
+    fn guide_weight(&self, guides: &Guides, p: usize, q: usize, distance: f64) -> f64 {
+        let mut weight = 1.0;
+        if let Some(normals) = &guides.normals {
+            // Pixels without a surface are told apart by their depth
+            if !normals[p].near_zero() && !normals[q].near_zero() {
+                weight *= normals[p].dot(&normals[q]).max(0.0).powf(self.normal_power);
+            }
+        }
+        if let Some(depths) = &guides.depths {
+            let (zp, zq) = (depths[p], depths[q]);
+            weight *= match (zp.is_finite(), zq.is_finite()) {
+                (true, true) => {
+                    let expected = self.depth_sigma * guides.depth_gradients[p] * distance;
+                    (-(zp - zq).abs() / (expected + 1e-4 * zp.abs() + 1e-9)).exp()
+                }
+                (false, false) => 1.0,
+                _ => 0.0,
+            };
+        }
+        if let Some(albedo) = &guides.albedo {
+            weight *= (-(albedo[p] - albedo[q]).length() / self.albedo_sigma).exp();
+        }
+        weight
+    }
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser::new()
+    }
+}
+
+// The AOVs of a film that guide the filter, row by row
+struct Guides {
+    // Unit length, or zero where nothing was hit
+    normals: Option<Vec<Vec3>>,
+    albedo: Option<Vec<Vec3>>,
+    depths: Option<Vec<f64>>,
+    // Largest change in depth to a neighbouring pixel, empty without depths
+    depth_gradients: Vec<f64>,
+}
+
+impl Guides {
+    fn new(film: &Film) -> Guides {
+        let (width, height) = (film.width() as usize, film.height() as usize);
+        let normals = film.aov(Aov::Normal).map(|normals| {
+            pixel_vectors(normals)
+                .into_iter()
+                .map(|n| {
+                    if n.near_zero() {
+                        Vec3::empty()
+                    } else {
+                        n.unit_vector()
+                    }
+                })
+                .collect()
+        });
+        let albedo = film.aov(Aov::Albedo).map(pixel_vectors);
+        let depths: Option<Vec<f64>> = film
+            .aov(Aov::Depth)
+            .map(|depths| depths.pixels().iter().map(|&[z, _, _]| z as f64).collect());
+        let depth_gradients = match &depths {
+            Some(depths) => depth_gradients(depths, width, height),
+            None => Vec::new(),
+        };
+        Guides {
+            normals,
+            albedo,
+            depths,
+            depth_gradients,
+        }
+    }
+}
+
+fn pixel_vectors(film: &Film) -> Vec<Vec3> {
+    film.pixels()
+        .iter()
+        .map(|&[r, g, b]| Vec3::new(r as f64, g as f64, b as f64))
+        .collect()
+}
+
+fn depth_gradients(depths: &[f64], width: usize, height: usize) -> Vec<f64> {
+    (0..width * height)
+        .map(|p| {
+            let (x, y) = (p % width, p / width);
+            let neighbours = [
+                (x > 0).then(|| p - 1),
+                (x + 1 < width).then_some(p + 1),
+                (y > 0).then(|| p - width),
+                (y + 1 < height).then_some(p + width),
+            ];
+            neighbours
+                .into_iter()
+                .flatten()
+                .map(|q| (depths[q] - depths[p]).abs())
+                .filter(|change| change.is_finite())
+                .fold(0.0, f64::max)
+        })
+        .collect()
+}
+
+// Variance of the luminance in the 3x3 neighbourhood of every pixel, for films that don't
+// know the variance of their pixels
+fn spatial_variances(colors: &[Vec3], width: usize, height: usize) -> Vec<f64> {
+    let luminances: Vec<f64> = colors.iter().map(|&color| luminance(color)).collect();
+    let mean = blur_3x3(&luminances, width, height);
+    let squares: Vec<f64> = luminances.iter().map(|l| l * l).collect();
+    blur_3x3(&squares, width, height)
+        .into_iter()
+        .zip(mean)
+        .map(|(square, mean)| (square - mean * mean).max(0.0))
+        .collect()
+}
+
+// Box filter over the 3x3 neighbourhood of every pixel, cut off at the edges. Infinite values
+// are skipped.
+fn blur_3x3(values: &[f64], width: usize, height: usize) -> Vec<f64> {
+    (0..width * height)
+        .map(|p| {
+            let (x, y) = (p % width, p / width);
+            let mut sum = 0.0;
+            let mut count = 0;
+            for qy in y.saturating_sub(1)..(y + 2).min(height) {
+                for qx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let value = values[qy * width + qx];
+                    if value.is_finite() {
+                        sum += value;
+                        count += 1;
+                    }
+                }
+            }
+            if count == 0 {
+                0.0
+            } else {
+                sum / count as f64
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demo_render::demo_scene;
+
+    fn mean_squared_error(film: &Film, reference: &Film) -> f64 {
+        let errors = film.pixels().iter().zip(reference.pixels()).map(|(a, b)| {
+            (0..3)
+                .map(|c| (a[c].min(1.0) as f64 - b[c].min(1.0) as f64).powi(2))
+                .sum::<f64>()
+        });
+        errors.sum::<f64>() / film.pixels().len() as f64
+    }
+
+    #[test]
+    fn closer_to_the_reference() {
+        let render = |samples: i32, denoise: Option<Denoiser>| {
+            let mut scene = demo_scene("cornell_box").unwrap();
+            scene.camera.image_width = 32;
+            scene.camera.samples_per_pixel = samples;
+            scene.camera.max_depth = 8;
+            scene.camera.denoise = denoise;
+            scene.render(4)
+        };
+        let reference = render(256, None);
+        let noisy = render(8, None);
+        let denoised = render(8, Some(Denoiser::new()));
+        assert!(denoised.aovs().is_empty());
+        let (before, after) = (
+            mean_squared_error(&noisy, &reference),
+            mean_squared_error(&denoised, &reference),
+        );
+        assert!(after < before / 3.0, "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn clamps_the_iterations() {
+        // Far more passes than the step can be shifted by
+        let denoised = |iterations: u32| {
+            let mut film = Film::new(4, 4);
+            film.set_pixel(1, 2, Vec3::new(1.0, 1.0, 1.0));
+            Denoiser {
+                iterations,
+                ..Denoiser::new()
+            }
+            .denoise(&mut film);
+            film
+        };
+        assert_eq!(denoised(70).pixels(), denoised(16).pixels());
+    }
+
+    #[test]
+    fn keeps_edges() {
+        // A noisy grey wall meeting a black one, the normals tell them apart
+        let mut film = Film::new(16, 16);
+        let mut normals = Film::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                let noise = if (x * 7 + y * 13) % 5 < 2 { 0.2 } else { -0.1 };
+                let (color, normal) = if x < 8 {
+                    (0.5 + noise, Vec3::new(1.0, 0.0, 0.0))
+                } else {
+                    (0.0, Vec3::new(0.0, 0.0, 1.0))
+                };
+                film.set_pixel(x, y, Vec3::new(color, color, color));
+                normals.set_pixel(x, y, normal);
+            }
+        }
+        film.set_aovs(vec![(Aov::Normal, normals)]);
+        Denoiser::new().denoise(&mut film);
+        for y in 0..16 {
+            assert!(
+                (film.pixel(7, y).x - 0.52).abs() < 0.05,
+                "{:?}",
+                film.pixel(7, y)
+            );
+            assert_eq!(film.pixel(8, y).x, 0.0);
+        }
+    }
+}
@@ -1,30 +1,37 @@
 use crate::hittable::HitRecord;
 use crate::material::{Material, ScatterPDF, ScatterRecord};
 use crate::ray::Ray3;
+use crate::sampler::sample_1d;
+use crate::spectrum;
 use nurbs::vector_3::Vec3;
 
+// Wavelength of the sodium d line, where the index of refraction of glass is usually given
+const D_LINE: f64 = 587.6;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Dielectric {
     ir: f64,
+    dispersion: Option<Dispersion>,
+    absorption: Option<Absorption>,
 }
 
 impl Material for Dielectric {
     fn scatter(&self, ray_in: &Ray3, hit_rec: &HitRecord, scatter_rec: &mut ScatterRecord) -> bool {
-        scatter_rec.attenuation = Vec3::new(1.0, 1.0, 1.0);
-
-        let refraction_ratio = if hit_rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
+        scatter_rec.attenuation = match &self.absorption {
+            Some(absorption) => absorption.along(ray_in, hit_rec),
+            None => Vec3::new(1.0, 1.0, 1.0),
         };
 
+        let ir = self.ir();
+        let refraction_ratio = if hit_rec.front_face { 1.0 / ir } else { ir };
+
         let unit_dir = ray_in.direction().unit_vector();
         let cos_theta = (-unit_dir).dot(&hit_rec.normal).min(1.0);
         let sin_theta = (1.0 - (cos_theta * cos_theta)).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
         let direction: Vec3;
-        if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > rand::random() {
+        if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > sample_1d() {
             direction = unit_dir.reflect(&hit_rec.normal);
         } else {
             direction = Vec3::refract(&unit_dir, &hit_rec.normal, refraction_ratio);
@@ -38,7 +45,41 @@ impl Material for Dielectric {
 
 impl Dielectric {
     pub fn new(ir: f64) -> Dielectric {
-        Dielectric { ir }
+        Dielectric {
+            ir,
+            dispersion: None,
+            absorption: None,
+        }
+    }
+
+    pub fn new_absorbing(ir: f64, color: Vec3, distance: f64) -> Dielectric {
+        Dielectric {
+            ir,
+            dispersion: None,
+            absorption: Some(Absorption::new(color, distance)),
+        }
+    }
+
+    // Glass whose index of refraction changes with the wavelength, which splits white light
+    // into its colors in a spectral render. RGB renders use the index at the d line.
+    pub fn new_dispersive(dispersion: Dispersion, absorption: Option<Absorption>) -> Dielectric {
+        Dielectric {
+            ir: dispersion.ior(D_LINE),
+            dispersion: Some(dispersion),
+            absorption,
+        }
+    }
+
+    // The index of refraction for the light being traced. Only the hero wavelength of a
+    // spectral sample can follow the direction it picks.
+    fn ir(&self) -> f64 {
+        match (&self.dispersion, spectrum::wavelengths()) {
+            (Some(dispersion), Some(wavelengths)) => {
+                spectrum::terminate_secondary();
+                dispersion.ior(wavelengths.hero())
+            }
+            _ => self.ir,
+        }
     }
 
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -47,3 +88,119 @@ impl Dielectric {
         r0 + ((1.0 - r0) * (1.0 - cosine).powf(5.0))
     }
 }
+
+// How the index of refraction of a dielectric depends on the wavelength, in micrometres in
+// both formulas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    // n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum of b_i lambda^2 / (lambda^2 - c_i)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // Schott's N-BK7, the usual crown glass of lenses
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    // Schott's SF11, a dense flint glass that spreads colors about four times as far
+    pub const SF11: Dispersion = Dispersion::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+
+    // The index of refraction at `lambda` nanometres
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let micrometres = lambda / 1000.0;
+        let squared = micrometres * micrometres;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / squared,
+            Dispersion::Sellmeier { b, c } => (1.0
+                + (0..3)
+                    .map(|i| b[i] * squared / (squared - c[i]))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
+}
+
+// Beer-Lambert absorption of light travelling through a dielectric, which tints thick glass
+// and liquids. Light keeps `color` of itself every `distance` it travels.
+#[derive(Debug, Clone, Copy)]
+pub struct Absorption {
+    coefficient: Vec3,
+}
+
+impl Absorption {
+    pub fn new(color: Vec3, distance: f64) -> Absorption {
+        let coefficient = |kept: f64| -kept.clamp(1e-6, 1.0).ln() / distance.max(1e-9);
+        Absorption {
+            coefficient: Vec3::new(
+                coefficient(color.x),
+                coefficient(color.y),
+                coefficient(color.z),
+            ),
+        }
+    }
+
+    // Share of the light left after `distance`
+    pub fn transmittance(&self, distance: f64) -> Vec3 {
+        Vec3::new(
+            (-self.coefficient.x * distance).exp(),
+            (-self.coefficient.y * distance).exp(),
+            (-self.coefficient.z * distance).exp(),
+        )
+    }
+
+    // Share of the light left along `ray_in` when it reached the surface from inside, which
+    // means it started on the surface where it went in. All of it if it came from outside.
+    pub fn along(&self, ray_in: &Ray3, hit_rec: &HitRecord) -> Vec3 {
+        if hit_rec.front_face {
+            Vec3::new(1.0, 1.0, 1.0)
+        } else {
+            self.transmittance((hit_rec.point - ray_in.origin()).length())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absorption_follows_beer_lambert() {
+        let absorption = Absorption::new(Vec3::new(0.5, 1.0, 0.25), 2.0);
+        let after = absorption.transmittance(4.0);
+        assert!((after - Vec3::new(0.25, 1.0, 0.0625)).length() < 1e-12);
+
+        // Only light that crossed the inside is tinted
+        let ray_in = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let mut hit_rec = HitRecord::new();
+        hit_rec.point = Vec3::new(0.0, 0.0, 2.0);
+        hit_rec.front_face = true;
+        assert_eq!(absorption.along(&ray_in, &hit_rec).x, 1.0);
+        hit_rec.front_face = false;
+        assert!((absorption.along(&ray_in, &hit_rec).x - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn glass_disperses() {
+        // Catalogue values at the d line
+        assert!((Dispersion::BK7.ior(D_LINE) - 1.5168).abs() < 1e-4);
+        assert!((Dispersion::SF11.ior(D_LINE) - 1.7847).abs() < 1e-4);
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.ior(500.0) - 1.516).abs() < 1e-12);
+        // Blue light bends more than red
+        assert!(Dispersion::BK7.ior(450.0) > Dispersion::BK7.ior(650.0));
+
+        // Only spectral renders see the dispersion, and only through their hero wavelength
+        let glass = Dielectric::new_dispersive(Dispersion::SF11, None);
+        assert_eq!(glass.ir(), glass.ir);
+        spectrum::install(Some(spectrum::Wavelengths::sample(0.1)));
+        assert!(glass.ir() > glass.ir);
+        assert!(spectrum::wavelengths().unwrap().secondary_terminated);
+        spectrum::install(None);
+    }
+}
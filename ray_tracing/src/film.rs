@@ -0,0 +1,127 @@
+use crate::aov::Aov;
+use crate::color::DisplayTransform;
+use nurbs::vector_3::Vec3;
+
+// Linear RGB radiance for every pixel of a render, already averaged over the samples. Rows
+// are stored top to bottom.
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 3]>,
+    // Number of samples taken for every pixel
+    sample_counts: Vec<u32>,
+    // Variance of every pixel's mean luminance, infinite where it is unknown
+    variances: Vec<f32>,
+    // Films of the AOVs the camera was asked for
+    aovs: Vec<(Aov, Film)>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![[0.0; 3]; (width * height) as usize],
+            sample_counts: vec![0; (width * height) as usize],
+            variances: vec![f32::INFINITY; (width * height) as usize],
+            aovs: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[[f32; 3]] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Vec3 {
+        let [r, g, b] = self.pixels[(y * self.width + x) as usize];
+        Vec3::new(r as f64, g as f64, b as f64)
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Vec3) {
+        self.pixels[(y * self.width + x) as usize] = film_pixel(color);
+    }
+
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.sample_counts
+    }
+
+    pub fn set_sample_count(&mut self, x: u32, y: u32, samples: u32) {
+        self.sample_counts[(y * self.width + x) as usize] = samples;
+    }
+
+    pub fn variance(&self, x: u32, y: u32) -> f64 {
+        self.variances[(y * self.width + x) as usize] as f64
+    }
+
+    pub fn set_variance(&mut self, x: u32, y: u32, variance: f64) {
+        self.variances[(y * self.width + x) as usize] = variance as f32;
+    }
+
+    pub fn aovs(&self) -> &[(Aov, Film)] {
+        &self.aovs
+    }
+
+    pub fn aov(&self, aov: Aov) -> Option<&Film> {
+        self.aovs
+            .iter()
+            .find(|(kind, _)| *kind == aov)
+            .map(|(_, film)| film)
+    }
+
+    pub fn set_aovs(&mut self, aovs: Vec<(Aov, Film)>) {
+        self.aovs = aovs;
+    }
+
+    // Drops the AOVs that aren't in `keep`
+    pub fn retain_aovs(&mut self, keep: &[Aov]) {
+        self.aovs.retain(|(aov, _)| keep.contains(aov));
+    }
+
+    // Visualises where the samples went, from black for the fewest samples over red and
+    // yellow to white for the most. Packed as 0xFFRRGGBB like `to_argb`.
+    pub fn sample_heatmap(&self) -> Vec<u32> {
+        let min = self.sample_counts.iter().copied().min().unwrap_or(0);
+        let max = self.sample_counts.iter().copied().max().unwrap_or(0);
+        let range = (max - min).max(1) as f64;
+        self.sample_counts
+            .iter()
+            .map(|&count| {
+                let t = 3.0 * (count - min) as f64 / range;
+                let channel = |offset: f64| ((t - offset).clamp(0.0, 1.0) * 255.0 + 0.5) as u32;
+                0xFF000000 | (channel(0.0) << 16) | (channel(1.0) << 8) | channel(2.0)
+            })
+            .collect()
+    }
+
+    // The 8-bit output stage, packs every pixel as 0xFFRRGGBB for display and LDR formats
+    pub fn to_argb(&self, transform: &DisplayTransform) -> Vec<u32> {
+        self.pixels
+            .iter()
+            .map(|&[r, g, b]| transform.to_val(&Vec3::new(r as f64, g as f64, b as f64)))
+            .collect()
+    }
+
+    // The 16-bit output stage, three channels per pixel
+    pub fn to_rgb16(&self, transform: &DisplayTransform) -> Vec<u16> {
+        self.pixels
+            .iter()
+            .flat_map(|&[r, g, b]| {
+                let color = transform.to_display(&Vec3::new(r as f64, g as f64, b as f64));
+                [color.x, color.y, color.z].map(|value| (value * 65535.0 + 0.5) as u16)
+            })
+            .collect()
+    }
+}
+
+// NaN components are dropped so they don't poison the HDR outputs
+fn film_pixel(color: Vec3) -> [f32; 3] {
+    [color.x, color.y, color.z].map(|value| if value.is_nan() { 0.0 } else { value as f32 })
+}
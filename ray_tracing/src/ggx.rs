@@ -0,0 +1,169 @@
+use nurbs::vector_3::Vec3;
+use std::f64::consts::PI;
+
+// The Trowbridge-Reitz (GGX) distribution of microfacet normals. Directions are in the local
+// frame of the surface where z is the normal, anisotropic surfaces have a different roughness
+// along x and y.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GGX {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl GGX {
+    // Surfaces smoother than this along both axes are treated as perfect mirrors, the
+    // distribution is too close to a spike to sample or evaluate
+    pub const SMOOTH: f64 = 1e-3;
+
+    pub fn new(alpha_x: f64, alpha_y: f64) -> GGX {
+        GGX {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    // From the perceptual roughness in [0, 1], which is squared, and an anisotropy in [-1, 1]
+    // that stretches highlights along x when positive and along y when negative
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> GGX {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.abs().min(1.0)).sqrt();
+        if anisotropy >= 0.0 {
+            GGX::new(alpha / aspect, alpha * aspect)
+        } else {
+            GGX::new(alpha * aspect, alpha / aspect)
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < GGX::SMOOTH
+    }
+
+    // Density of microfacet normal m per unit of projected area
+    pub fn d(&self, m: &Vec3) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (m.x / self.alpha_x).powi(2) + (m.y / self.alpha_y).powi(2) + m.z * m.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    // Smith's auxiliary function, the shadowed share of the microfacets seen from w over the
+    // lit share
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        if w.z.abs() < 1e-12 {
+            return f64::INFINITY;
+        }
+        let t = ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / (w.z * w.z);
+        0.5 * ((1.0 + t).sqrt() - 1.0)
+    }
+
+    // Share of the microfacets visible from w
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Share of the microfacets visible from both wo and wi, the height correlated form
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals visible from wo, which is what `sample_visible` draws from
+    pub fn visible_d(&self, wo: &Vec3, m: &Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+
+    // A microfacet normal visible from wo (Heitz 2018), wo is above the surface
+    pub fn sample_visible(&self, wo: &Vec3, r1: f64, r2: f64) -> Vec3 {
+        // Stretch to a hemisphere of unit roughness
+        let v = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).unit_vector();
+        let length_squared = v.x * v.x + v.y * v.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-v.y, v.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v.cross(&t1);
+
+        // A point on the disk, squashed onto the part of the hemisphere seen from v
+        let radius = r1.sqrt();
+        let phi = 2.0 * PI * r2;
+        let p1 = radius * phi.cos();
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * radius * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        // And back
+        Vec3::new(self.alpha_x * n.x, self.alpha_y * n.y, n.z.max(0.0)).unit_vector()
+    }
+}
+
+// The microfacet normal that refracts wo into wi, `eta` being the index of refraction on wi's
+// side over wo's, facing the same side as wo. None if no microfacet can.
+pub fn refraction_half_vector(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+    let m = -(*wo + eta * *wi);
+    if m.near_zero() {
+        return None;
+    }
+    let m = m.unit_vector();
+    let m = if m.z < 0.0 { -m } else { m };
+    (wo.dot(&m) > 0.0 && wi.dot(&m) < 0.0).then_some(m)
+}
+
+// Directions and solid angles of a `steps` by `steps` grid over the sphere, or only its z >= 0
+// half, at the midpoints of equal steps in cos(theta) and phi. Summing a function times the
+// solid angles integrates it with the midpoint rule.
+#[cfg(test)]
+pub(crate) fn sphere_grid(steps: usize, upper_half: bool) -> impl Iterator<Item = (Vec3, f64)> {
+    let (min_cos, span) = if upper_half { (0.0, 1.0) } else { (-1.0, 2.0) };
+    let solid_angle = 2.0 * PI * span / (steps * steps) as f64;
+    (0..steps * steps).map(move |cell| {
+        let (i, j) = (cell / steps, cell % steps);
+        let cos_theta = min_cos + span * (i as f64 + 0.5) / steps as f64;
+        let phi = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let direction = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        (direction, solid_angle)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visible_normals_match_their_density() {
+        // The projected area of the normals is 1, and the histogram of the sampled normals
+        // over cos(theta) follows the density of the visible normals
+        let ggx = GGX::from_roughness(0.6, 0.5);
+        let wo = Vec3::new(0.5, -0.3, 0.8).unit_vector();
+        let bins = 8;
+        let bin_of = |m: &Vec3| ((m.z * bins as f64) as usize).min(bins - 1);
+        let mut projected = 0.0;
+        let mut expected = vec![0.0; bins];
+        for (m, solid_angle) in sphere_grid(400, true) {
+            projected += ggx.d(&m) * m.z * solid_angle;
+            expected[bin_of(&m)] += ggx.visible_d(&wo, &m) * solid_angle;
+        }
+        assert!((projected - 1.0).abs() < 1e-2, "{}", projected);
+
+        let samples = 100_000;
+        let mut found = vec![0.0; bins];
+        for k in 0..samples {
+            let r1 = (k as f64 + 0.5) / samples as f64;
+            let r2 = (k as f64 * 0.618_033_988_75).fract();
+            let m = ggx.sample_visible(&wo, r1, r2);
+            found[bin_of(&m)] += 1.0 / samples as f64;
+        }
+        for (found, expected) in found.iter().zip(&expected) {
+            assert!(
+                (found - expected).abs() < 0.01,
+                "{:?} {:?}",
+                found,
+                expected
+            );
+        }
+    }
+}
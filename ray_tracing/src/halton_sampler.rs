@@ -0,0 +1,79 @@
+use crate::sampler::{mix, to_unit, Sampler};
+use nurbs::random::random_f64;
+
+// Bases of the Halton dimensions, later dimensions fall back to random numbers
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// The Halton sequence, dimension n is the radical inverse of the sample index in the nth
+// prime. Every pixel uses the same points with a random toroidal shift (Cranley-Patterson
+// rotation) per dimension, so neighbouring pixels don't repeat each other's error.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_key: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel(&mut self, pixel: u64) {
+        self.pixel_key = mix(self.seed, pixel);
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn advance_to(&mut self, dimension: u32) {
+        self.dimension = self.dimension.max(dimension);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let dimension = self.dimension as usize;
+        self.dimension += 1;
+        if dimension >= PRIMES.len() {
+            return random_f64();
+        }
+        let shift = to_unit(mix(self.pixel_key, dimension as u64) as u32);
+        let value = radical_inverse(PRIMES[dimension], self.index) + shift;
+        if value >= 1.0 {
+            value - 1.0
+        } else {
+            value
+        }
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel_key: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+// Mirrors the digits of `index` in `base` around the radix point
+fn radical_inverse(base: u32, index: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut digits = index;
+    let mut scale = inv_base;
+    let mut value = 0.0;
+    while digits > 0 {
+        value += (digits % base) as f64 * scale;
+        digits /= base;
+        scale *= inv_base;
+    }
+    value
+}
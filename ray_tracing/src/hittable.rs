@@ -1,4 +1,5 @@
 use crate::aabb::AABB;
+use crate::bvh_node::BVHStats;
 use crate::interval::Interval;
 use crate::lambertian::Lambertian;
 use crate::material::Material;
@@ -6,6 +7,7 @@ use crate::ray::Ray3;
 use nurbs::vector_3::Vec3;
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub point: Vec3,
     pub normal: Vec3,
@@ -13,7 +15,13 @@ pub struct HitRecord {
     pub time: f64,
     pub u: f64,
     pub v: f64,
+    // How the point moves along the surface with u and v, the tangent frame for normal and
+    // bump maps. Zero where the surface has no parametrisation, like inside a volume.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub front_face: bool,
+    // Which object was hit, see `HitRecord::object_id`. 0 until a shape sets it.
+    pub object: usize,
 }
 
 pub trait Hittable {
@@ -28,6 +36,11 @@ pub trait Hittable {
     fn random(&self, _origin: &Vec3) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+
+    // Combined shape of the BVHs in this object, None if it doesn't contain any
+    fn bvh_stats(&self) -> Option<BVHStats> {
+        None
+    }
 }
 
 impl HitRecord {
@@ -39,10 +52,20 @@ impl HitRecord {
             time: f64::MAX,
             u: 0.0,
             v: 0.0,
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
             front_face: false,
+            object: 0,
         }
     }
 
+    // Identifies an object for as long as it lives, by its address. Shapes put their own id on
+    // their hits and instances and meshes replace it with theirs, so all of the hits on one
+    // instance share an id.
+    pub fn object_id<T>(object: &T) -> usize {
+        object as *const T as usize
+    }
+
     pub fn set_face_normal(&mut self, r: &Ray3, outward_normal: Vec3) {
         self.front_face = r.direction().dot(&outward_normal) < 0.0;
         self.normal = if self.front_face {
@@ -59,6 +82,9 @@ impl HitRecord {
         self.time = rhs.time;
         self.u = rhs.u;
         self.v = rhs.v;
+        self.dpdu = rhs.dpdu;
+        self.dpdv = rhs.dpdv;
         self.front_face = rhs.front_face;
+        self.object = rhs.object;
     }
 }
@@ -1,8 +1,10 @@
 use crate::aabb::AABB;
+use crate::bvh_node::BVHStats;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
 use crate::ray::Ray3;
-use nurbs::{utility::random_u32_range, vector_3::Vec3};
+use crate::sampler::sample_1d;
+use nurbs::vector_3::Vec3;
 use std::sync::Arc;
 
 pub struct HittableList {
@@ -45,13 +47,21 @@ impl Hittable for HittableList {
     }
 
     fn random(&self, origin: &Vec3) -> Vec3 {
-        let size = self.objects.len() as u32;
+        let size = self.objects.len();
         if size == 0 {
             Vec3::new(1.0, 0.0, 0.0)
         } else {
-            self.objects[random_u32_range(0, size - 1) as usize].random(origin)
+            let index = ((sample_1d() * size as f64) as usize).min(size - 1);
+            self.objects[index].random(origin)
         }
     }
+
+    fn bvh_stats(&self) -> Option<BVHStats> {
+        self.objects
+            .iter()
+            .filter_map(|object| object.bvh_stats())
+            .reduce(|a, b| a.merge(&b))
+    }
 }
 
 impl HittableList {
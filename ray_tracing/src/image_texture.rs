@@ -2,7 +2,8 @@ use crate::interval::Interval;
 use crate::texture::Texture;
 use nurbs::vector_3::Vec3;
 use image::io;
-use image::RgbImage;
+use image::{ImageResult, RgbImage};
+use std::path::Path;
 
 pub struct ImageTexture {
     image: RgbImage,
@@ -33,11 +34,11 @@ impl Texture for ImageTexture {
 
 impl ImageTexture {
     pub fn new(path: &str) -> ImageTexture {
-        let image = io::Reader::open(path)
-            .unwrap()
-            .decode()
-            .unwrap()
-            .into_rgb8();
-        ImageTexture { image }
+        ImageTexture::open(path).unwrap()
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<ImageTexture> {
+        let image = io::Reader::open(path)?.decode()?.into_rgb8();
+        Ok(ImageTexture { image })
     }
 }
@@ -0,0 +1,22 @@
+use crate::sampler::Sampler;
+use nurbs::random::random_f64;
+
+// Independent uniform numbers from the thread's random number generator, which the camera
+// restarts on a separate stream for every pixel
+pub struct IndependentSampler {}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel(&mut self, _pixel: u64) {}
+
+    fn start_sample(&mut self, _index: u32) {}
+
+    fn advance_to(&mut self, _dimension: u32) {}
+
+    fn next_1d(&mut self) -> f64 {
+        random_f64()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (random_f64(), random_f64())
+    }
+}
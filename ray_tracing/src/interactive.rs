@@ -0,0 +1,390 @@
+use crate::camera::Camera;
+use crate::color::DisplayTransform;
+use crate::film::Film;
+use crate::mat4::Mat4;
+use crate::scene::Scene;
+use nurbs::vector_3::Vec3;
+use std::num::NonZeroU32;
+use std::rc::Rc;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use winit::dpi::{PhysicalPosition, PhysicalSize, Size};
+use winit::event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoopBuilder};
+use winit::keyboard::{Key, NamedKey};
+use winit::window::WindowBuilder;
+
+// Degrees the view orbits per key press and per pixel the mouse is dragged
+const ORBIT_STEP: f64 = 5.0;
+const DRAG_DEGREES_PER_PIXEL: f64 = 0.25;
+// Factors the distance to look_at, the field of view and the focus distance change by per
+// key press or scroll step
+const DOLLY_FACTOR: f64 = 0.9;
+const ZOOM_FACTOR: f64 = 0.95;
+const FOCUS_FACTOR: f64 = 1.1;
+// Degrees the defocus angle changes by per key press
+const DEFOCUS_STEP: f64 = 0.25;
+// While the camera moves frames are rendered at 1/PREVIEW_SCALE of the width with a single
+// sample and at most PREVIEW_DEPTH bounces
+const PREVIEW_SCALE: i32 = 4;
+const PREVIEW_DEPTH: i32 = 3;
+// How long after the last input the view still counts as moving
+const SETTLE_TIME: Duration = Duration::from_millis(250);
+
+pub const CONTROLS: &str = "\
+Controls:
+  A/D, left drag   Orbit around look_at
+  W/S              Move towards or away from look_at
+  R/F              Orbit up or down
+  Scroll           Field of view
+  [ and ]          Defocus angle
+  - and =          Focus distance, T sets it to the distance to look_at
+  P                Print the camera setup as Rust code
+  Escape           Quit";
+
+// Orbits look_from around look_at, by `yaw` degrees around v_up and `pitch` degrees up. The
+// pitch is dropped when it would bring the view too close to looking along v_up or over the
+// pole, where the image would flip.
+pub fn orbit(camera: &mut Camera, yaw: f64, pitch: f64) {
+    let up = camera.v_up.unit_vector();
+    let offset = Mat4::rotation(up, yaw).transform_vector(camera.look_from - camera.look_at);
+    let axis = offset.cross(&up);
+    let pitched = Mat4::rotation(axis, pitch).transform_vector(offset);
+    let flat = |v: Vec3| v - up * v.dot(&up);
+    let offset = if axis.near_zero()
+        || pitched.unit_vector().dot(&up).abs() > 0.995
+        || flat(pitched).dot(&flat(offset)) <= 0.0
+    {
+        offset
+    } else {
+        pitched
+    };
+    camera.look_from = camera.look_at + offset;
+}
+
+// Scales the distance between look_from and look_at
+pub fn dolly(camera: &mut Camera, factor: f64) {
+    camera.look_from = camera.look_at + (camera.look_from - camera.look_at) * factor;
+}
+
+pub fn zoom(camera: &mut Camera, factor: f64) {
+    camera.vfov = (camera.vfov * factor).clamp(1.0, 170.0);
+}
+
+// The view as the statements the built-in scenes use to set up their camera
+pub fn camera_code(camera: &Camera) -> String {
+    let vec = |v: Vec3| {
+        format!(
+            "Vec3::new({}, {}, {})",
+            number(v.x),
+            number(v.y),
+            number(v.z)
+        )
+    };
+    format!(
+        "    cam.vfov = {};\n    cam.look_from = {};\n    cam.look_at = {};\n    cam.v_up = {};\n\n    cam.defocus_angle = {};\n    cam.focus_dist = {};",
+        number(camera.vfov),
+        vec(camera.look_from),
+        vec(camera.look_at),
+        vec(camera.v_up),
+        number(camera.defocus_angle),
+        number(camera.focus_dist)
+    )
+}
+
+// A float literal rounded to three decimals
+fn number(value: f64) -> String {
+    let rounded = format!("{:.3}", value);
+    let trimmed = rounded.trim_end_matches('0');
+    let trimmed = if trimmed.ends_with('.') {
+        format!("{}0", trimmed)
+    } else {
+        trimmed.to_owned()
+    };
+    if trimmed == "-0.0" {
+        "0.0".to_owned()
+    } else {
+        trimmed
+    }
+}
+
+// The camera shared between the window and the render thread. Every change bumps the
+// generation, which restarts the accumulation.
+struct View {
+    camera: Camera,
+    generation: u64,
+    last_change: Instant,
+    quit: bool,
+}
+
+// A rendered frame for the window
+struct Frame {
+    pixels: Vec<u32>,
+    width: u32,
+    height: u32,
+    samples: f64,
+    preview: bool,
+}
+
+// Opens a window that renders `scene` while its camera is flown around with the keyboard and
+// mouse, see CONTROLS. Rendering accumulates progressively while the view is still and falls
+// back to quick low resolution frames while it moves. Returns the camera as it was left.
+pub fn explore(scene: Scene, threads: u32) -> Camera {
+    let (width, height) = scene.camera.output_size();
+    let transform = scene.camera.display_transform();
+    let event_loop = EventLoopBuilder::<Frame>::with_user_event()
+        .build()
+        .unwrap();
+    let window = Rc::new(
+        WindowBuilder::new()
+            .with_inner_size(Size::Physical(PhysicalSize { width, height }))
+            .with_title(&scene.name)
+            .build(&event_loop)
+            .unwrap(),
+    );
+    let context = unsafe { softbuffer::Context::new(&window) }.unwrap();
+    let mut surface = unsafe { softbuffer::Surface::new(&context, &window) }.unwrap();
+
+    let view = Mutex::new(View {
+        camera: scene.camera.clone(),
+        generation: 0,
+        last_change: Instant::now() - SETTLE_TIME,
+        quit: false,
+    });
+    let changed = Condvar::new();
+    let change = |update: &dyn Fn(&mut Camera)| {
+        let mut view = view.lock().unwrap();
+        update(&mut view.camera);
+        view.generation += 1;
+        view.last_change = Instant::now();
+        changed.notify_all();
+    };
+
+    std::thread::scope(|scope| {
+        let proxy = event_loop.create_proxy();
+        let (view, changed) = (&view, &changed);
+        let world = &scene.world;
+        let lights = &scene.lights;
+        scope.spawn(move || {
+            let mut rendered = None;
+            loop {
+                let (mut camera, generation, moving) = {
+                    let mut view = view.lock().unwrap();
+                    while !view.quit && rendered == Some(view.generation) {
+                        view = changed.wait(view).unwrap();
+                    }
+                    if view.quit {
+                        return;
+                    }
+                    let moving = view.last_change.elapsed() < SETTLE_TIME;
+                    (view.camera.clone(), view.generation, moving)
+                };
+                camera.verbose = false;
+
+                if moving {
+                    camera.image_width = (camera.image_width / PREVIEW_SCALE).max(1);
+                    camera.max_depth = camera.max_depth.min(PREVIEW_DEPTH);
+                    camera.samples_per_pixel = 1;
+                    camera.adaptive = None;
+                    let film = camera.render(world.clone(), lights.clone(), threads > 1, threads);
+                    let _ = proxy.send_event(frame(&film, &transform, true));
+
+                    // Come back for the full render once the view settles, or earlier if it
+                    // moves again
+                    let view = view.lock().unwrap();
+                    let left = SETTLE_TIME.saturating_sub(view.last_change.elapsed());
+                    if view.generation == generation {
+                        let _ = changed.wait_timeout(view, left).unwrap();
+                    }
+                } else {
+                    camera.render_progressive(
+                        world.clone(),
+                        lights.clone(),
+                        threads,
+                        &mut |film| {
+                            let _ = proxy.send_event(frame(film, &transform, false));
+                            let view = view.lock().unwrap();
+                            !view.quit && view.generation == generation
+                        },
+                    );
+                    rendered = Some(generation);
+                }
+            }
+        });
+
+        let mut shown = Frame {
+            pixels: vec![0; (width * height) as usize],
+            width,
+            height,
+            samples: 0.0,
+            preview: true,
+        };
+        let mut dragging = false;
+        let mut cursor: Option<PhysicalPosition<f64>> = None;
+        event_loop
+            .run(|event, elwt| {
+                elwt.set_control_flow(ControlFlow::Wait);
+
+                let Event::WindowEvent { window_id, event } = event else {
+                    if let Event::UserEvent(frame) = event {
+                        let state = if frame.preview {
+                            "moving".to_owned()
+                        } else {
+                            format!("{:.0} spp", frame.samples)
+                        };
+                        window.set_title(&format!("{} - {}", scene.name, state));
+                        shown = frame;
+                        window.request_redraw();
+                    }
+                    return;
+                };
+                if window_id != window.id() {
+                    return;
+                }
+                match event {
+                    WindowEvent::RedrawRequested => {
+                        let size = window.inner_size();
+                        let (Some(surface_width), Some(surface_height)) =
+                            (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+                        else {
+                            return;
+                        };
+                        surface.resize(surface_width, surface_height).unwrap();
+
+                        // Nearest neighbour scaling, previews are smaller than the window
+                        let mut buffer = surface.buffer_mut().unwrap();
+                        for y in 0..size.height {
+                            let source_row = (y * shown.height / size.height) * shown.width;
+                            for x in 0..size.width {
+                                let source = source_row + x * shown.width / size.width;
+                                buffer[(y * size.width + x) as usize] =
+                                    shown.pixels[source as usize];
+                            }
+                        }
+                        buffer.present().unwrap();
+                    }
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                logical_key,
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    } => match logical_key {
+                        Key::Named(NamedKey::Escape) => elwt.exit(),
+                        Key::Character(key) => match key.to_lowercase().as_str() {
+                            "a" => change(&|camera| orbit(camera, -ORBIT_STEP, 0.0)),
+                            "d" => change(&|camera| orbit(camera, ORBIT_STEP, 0.0)),
+                            "r" => change(&|camera| orbit(camera, 0.0, ORBIT_STEP)),
+                            "f" => change(&|camera| orbit(camera, 0.0, -ORBIT_STEP)),
+                            "w" => change(&|camera| dolly(camera, DOLLY_FACTOR)),
+                            "s" => change(&|camera| dolly(camera, 1.0 / DOLLY_FACTOR)),
+                            "[" => change(&|camera| {
+                                camera.defocus_angle =
+                                    (camera.defocus_angle - DEFOCUS_STEP).max(0.0)
+                            }),
+                            "]" => change(&|camera| camera.defocus_angle += DEFOCUS_STEP),
+                            "-" => change(&|camera| camera.focus_dist /= FOCUS_FACTOR),
+                            "=" => change(&|camera| camera.focus_dist *= FOCUS_FACTOR),
+                            "t" => change(&|camera| {
+                                camera.focus_dist = (camera.look_from - camera.look_at).length()
+                            }),
+                            "p" => println!("{}\n", camera_code(&view.lock().unwrap().camera)),
+                            _ => {}
+                        },
+                        _ => {}
+                    },
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    } => dragging = state == ElementState::Pressed,
+                    WindowEvent::CursorMoved { position, .. } => {
+                        if let (true, Some(last)) = (dragging, cursor) {
+                            let yaw = (last.x - position.x) * DRAG_DEGREES_PER_PIXEL;
+                            let pitch = (position.y - last.y) * DRAG_DEGREES_PER_PIXEL;
+                            change(&|camera| orbit(camera, yaw, pitch));
+                        }
+                        cursor = Some(position);
+                    }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        let steps = match delta {
+                            MouseScrollDelta::LineDelta(_, lines) => lines as f64,
+                            MouseScrollDelta::PixelDelta(pixels) => pixels.y / 40.0,
+                        };
+                        change(&|camera| zoom(camera, ZOOM_FACTOR.powf(steps)));
+                    }
+                    WindowEvent::CloseRequested => elwt.exit(),
+                    _ => {}
+                }
+            })
+            .unwrap();
+
+        let mut view = view.lock().unwrap();
+        view.quit = true;
+        changed.notify_all();
+        view.camera.clone()
+    })
+}
+
+fn frame(film: &Film, transform: &DisplayTransform, preview: bool) -> Frame {
+    let counts = film.sample_counts();
+    Frame {
+        pixels: film.to_argb(transform),
+        width: film.width(),
+        height: film.height(),
+        samples: counts.iter().map(|&count| count as f64).sum::<f64>() / counts.len() as f64,
+        preview,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        let mut camera = Camera::new();
+        camera.look_from = Vec3::new(0.0, 0.0, 10.0);
+        camera.look_at = Vec3::new(0.0, 0.0, 0.0);
+        camera.v_up = Vec3::new(0.0, 1.0, 0.0);
+        camera
+    }
+
+    #[test]
+    fn orbit_keeps_distance() {
+        let mut cam = camera();
+        orbit(&mut cam, 90.0, 0.0);
+        assert!((cam.look_from.x - 10.0).abs() < 1e-9 && cam.look_from.z.abs() < 1e-9);
+        orbit(&mut cam, 0.0, 30.0);
+        assert!((cam.look_from.length() - 10.0).abs() < 1e-9);
+        assert!((cam.look_from.y - 5.0).abs() < 1e-9);
+
+        // Never over the pole
+        orbit(&mut cam, 0.0, 70.0);
+        assert!((cam.look_from.y - 5.0).abs() < 1e-9);
+
+        dolly(&mut cam, 0.5);
+        assert!((cam.look_from.length() - 5.0).abs() < 1e-9);
+        zoom(&mut cam, 0.5);
+        assert_eq!(cam.vfov, 45.0);
+    }
+
+    #[test]
+    fn printed_setup() {
+        let mut cam = camera();
+        cam.vfov = 40.0;
+        cam.look_from = Vec3::new(278.0, 278.12345, -800.0);
+        cam.defocus_angle = 0.6;
+        assert_eq!(
+            camera_code(&cam),
+            "    cam.vfov = 40.0;
+    cam.look_from = Vec3::new(278.0, 278.123, -800.0);
+    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.6;
+    cam.focus_dist = 10.0;"
+        );
+    }
+}
@@ -1,33 +1,62 @@
 pub mod aabb;
+pub mod adaptive_sampling;
 pub mod affine_transforms;
+pub mod aov;
 pub mod bvh_node;
 pub mod camera;
 pub mod checker_texture;
+pub mod cli;
 pub mod color;
+pub mod conductor;
 pub mod constant_medium;
 pub mod cosine_pdf;
 pub mod demo_render;
+pub mod denoise;
 pub mod dielectric;
 pub mod diffuse_light;
+pub mod film;
+pub mod ggx;
+pub mod halton_sampler;
 pub mod hittable;
 pub mod hittable_list;
 pub mod hittable_pdf;
 pub mod image_texture;
+pub mod independent_sampler;
+#[cfg(feature = "window")]
+pub mod interactive;
 pub mod interval;
 pub mod isotropic;
 pub mod lambertian;
+pub mod linear_bvh;
+pub mod mat4;
 pub mod material;
 pub mod metal;
+pub mod microfacet_pdf;
 pub mod mixture_pdf;
 pub mod noise_texture;
+pub mod normal_map;
+pub mod obj_loader;
 pub mod orthonormal_basis;
 pub mod pdf;
 pub mod perlin;
+#[cfg(feature = "window")]
+pub mod preview_window;
+pub mod principled;
 pub mod quad;
 pub mod ray;
+pub mod render_progress;
+pub mod rough_dielectric;
+pub mod sampler;
 pub mod save_show;
+pub mod scene;
+pub mod scene_file;
+pub mod sobol_sampler;
 pub mod solid_texture;
+pub mod spectrum;
 pub mod sphere;
 pub mod sphere_pdf;
+pub mod stratified_sampler;
 pub mod texture;
-
+pub mod tile_scheduler;
+pub mod triangle;
+pub mod triangle_mesh;
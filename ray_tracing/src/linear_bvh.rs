@@ -0,0 +1,247 @@
+use crate::aabb::AABB;
+use crate::bvh_node::{BVHNode, BVHStats, Primitive};
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::ray::Ray3;
+use nurbs::vector_3::Vec3;
+use std::sync::Arc;
+
+// Node of a flattened BVH. The first child of an interior node directly follows it, so only
+// the index of the second child is stored. Leaves reference a range of `primitives`.
+struct LinearNode {
+    min: Vec3,
+    max: Vec3,
+    // Second child for interior nodes, first primitive for leaves
+    offset: usize,
+    // Zero for interior nodes
+    count: usize,
+    axis: u32,
+}
+
+// BVH stored depth first in a single Vec and traversed without recursion, visiting the
+// child nearer to the ray origin first. Built with the same SAH splits as `BVHNode`.
+pub struct LinearBVH {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Arc<dyn Hittable + Sync + Send>>,
+    // Index of each primitive in the objects the BVH was built from
+    indices: Vec<usize>,
+    stats: BVHStats,
+}
+
+impl Hittable for LinearBVH {
+    fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        self.hit_object(ray_in, time, hit_record).is_some()
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::from_vec3s(self.nodes[0].min, self.nodes[0].max)
+    }
+
+    fn bvh_stats(&self) -> Option<BVHStats> {
+        Some(self.stats)
+    }
+}
+
+impl LinearBVH {
+    pub fn from_list(list: &HittableList) -> LinearBVH {
+        LinearBVH::from_vec(&list.objects)
+    }
+
+    pub fn from_vec(objects: &[Arc<dyn Hittable + Sync + Send>]) -> LinearBVH {
+        assert!(!objects.is_empty(), "a BVH needs at least one object");
+        let mut primitives: Vec<Primitive> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| Primitive::new(index, object))
+            .collect();
+        let mut nodes = Vec::with_capacity(2 * primitives.len());
+        let stats = LinearBVH::build(&mut nodes, &mut primitives, 0, 0);
+        LinearBVH {
+            nodes,
+            indices: primitives.iter().map(|primitive| primitive.index).collect(),
+            primitives: primitives
+                .into_iter()
+                .map(|primitive| primitive.object)
+                .collect(),
+            stats,
+        }
+    }
+
+    // Appends the subtree for `primitives`, which start at index `first` of the final
+    // primitive list, in depth first order
+    fn build(
+        nodes: &mut Vec<LinearNode>,
+        primitives: &mut [Primitive],
+        first: usize,
+        depth: usize,
+    ) -> BVHStats {
+        let mut bounds = AABB::empty();
+        for primitive in primitives.iter() {
+            bounds = AABB::from_aabbs(&bounds, &primitive.bbox);
+        }
+        let index = nodes.len();
+        nodes.push(LinearNode {
+            min: Vec3::new(
+                bounds.axis(0).min(),
+                bounds.axis(1).min(),
+                bounds.axis(2).min(),
+            ),
+            max: Vec3::new(
+                bounds.axis(0).max(),
+                bounds.axis(1).max(),
+                bounds.axis(2).max(),
+            ),
+            offset: first,
+            count: primitives.len(),
+            axis: 0,
+        });
+
+        // Keep the traversal stack bounded, a subtree this deep is degenerate anyway
+        let split = if primitives.len() == 1 || depth >= 48 {
+            None
+        } else {
+            BVHNode::partition_sah(primitives, depth == 0)
+        };
+        let Some((mid, axis)) = split else {
+            let count = primitives.len();
+            return BVHStats {
+                depth: 0,
+                nodes: 0,
+                leaves: 1,
+                primitives: count,
+                max_leaf_size: count,
+            };
+        };
+
+        let (left, right) = primitives.split_at_mut(mid);
+        let left_stats = LinearBVH::build(nodes, left, first, depth + 1);
+        let second = nodes.len();
+        let right_stats = LinearBVH::build(nodes, right, first + mid, depth + 1);
+
+        let node = &mut nodes[index];
+        node.offset = second;
+        node.count = 0;
+        node.axis = axis;
+
+        let mut stats = left_stats.merge(&right_stats);
+        stats.depth += 1;
+        stats.nodes += 1;
+        stats
+    }
+
+    // Like `hit`, but also tells which object was hit, by its index in the objects the BVH
+    // was built from
+    pub fn hit_object(
+        &self,
+        ray_in: &Ray3,
+        time: Interval,
+        hit_record: &mut HitRecord,
+    ) -> Option<usize> {
+        let origin = ray_in.origin();
+        let direction = ray_in.direction();
+        let inv_dir = Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+
+        let mut closest = time.max();
+        let mut hit_object = None;
+        let mut stack = [0usize; 64];
+        let mut stack_size = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if LinearBVH::slab_hit(node, &origin, &inv_dir, time.min(), closest) {
+                if node.count > 0 {
+                    for index in node.offset..node.offset + node.count {
+                        let primitive = &self.primitives[index];
+                        if primitive.hit(ray_in, Interval::new(time.min(), closest), hit_record) {
+                            hit_object = Some(self.indices[index]);
+                            closest = hit_record.time;
+                        }
+                    }
+                } else {
+                    // Visit the near child next and come back for the far one
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[stack_size] = far;
+                    stack_size += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+        hit_object
+    }
+
+    fn slab_hit(node: &LinearNode, origin: &Vec3, inv_dir: &Vec3, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let inv_d = inv_dir.at(axis);
+            let mut t0 = (node.min.at(axis) - origin.at(axis)) * inv_d;
+            let mut t1 = (node.max.at(axis) - origin.at(axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh_node::SplitMethod;
+    use crate::lambertian::Lambertian;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn matches_bvh_node() {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new();
+        for i in 0..500 {
+            let center = Vec3::random_range(-10.0, 10.0);
+            let radius = 0.2 + (i % 7) as f64 * 0.1;
+            list.add(Arc::new(Sphere::new(center, radius, mat.clone())));
+        }
+        let tree = BVHNode::from_list_with(&list, SplitMethod::SurfaceAreaHeuristic);
+        let linear = LinearBVH::from_list(&list);
+        assert_eq!(linear.stats, tree.stats());
+        assert_eq!(
+            linear.node_count(),
+            tree.stats().nodes + tree.stats().leaves
+        );
+
+        for _ in 0..2000 {
+            let origin = Vec3::random_range(-15.0, 15.0);
+            let ray = Ray3::new(origin, Vec3::random_unit_vector(), 0.0);
+            let mut tree_rec = HitRecord::new();
+            let mut linear_rec = HitRecord::new();
+            let interval = Interval::new(0.001, f64::INFINITY);
+            let tree_hit = tree.hit(&ray, interval.copy(), &mut tree_rec);
+            let linear_hit = linear.hit(&ray, interval, &mut linear_rec);
+            assert_eq!(tree_hit, linear_hit);
+            if tree_hit {
+                assert_eq!(tree_rec.time, linear_rec.time);
+                assert_eq!(tree_rec.point.x, linear_rec.point.x);
+            }
+        }
+    }
+}
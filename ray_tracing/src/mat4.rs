@@ -0,0 +1,251 @@
+use nurbs::{utility::degree_to_radians, vector_3::Vec3};
+use std::ops;
+
+// Row-major 4x4 matrix for affine transforms. Points are treated as column vectors, so
+// `a * b` applies `b` first and then `a`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(factors: Vec3) -> Mat4 {
+        Mat4::new([
+            [factors.x, 0.0, 0.0, 0.0],
+            [0.0, factors.y, 0.0, 0.0],
+            [0.0, 0.0, factors.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counter-clockwise rotation in degrees about `axis` when looking down the axis
+    pub fn rotation(axis: Vec3, angle: f64) -> Mat4 {
+        let a = axis.unit_vector();
+        let radians = degree_to_radians(angle);
+        let sin_theta = radians.sin();
+        let cos_theta = radians.cos();
+        let t = 1.0 - cos_theta;
+        Mat4::new([
+            [
+                t * a.x * a.x + cos_theta,
+                t * a.x * a.y - sin_theta * a.z,
+                t * a.x * a.z + sin_theta * a.y,
+                0.0,
+            ],
+            [
+                t * a.x * a.y + sin_theta * a.z,
+                t * a.y * a.y + cos_theta,
+                t * a.y * a.z - sin_theta * a.x,
+                0.0,
+            ],
+            [
+                t * a.x * a.z - sin_theta * a.y,
+                t * a.y * a.z + sin_theta * a.x,
+                t * a.z * a.z + cos_theta,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotate_x(angle: f64) -> Mat4 {
+        Mat4::rotation(Vec3::new(1.0, 0.0, 0.0), angle)
+    }
+
+    pub fn rotate_y(angle: f64) -> Mat4 {
+        Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn rotate_z(angle: f64) -> Mat4 {
+        Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), angle)
+    }
+
+    // Places an object at `from` with its +z axis pointing at `to` and its +y axis as close
+    // to `up` as possible
+    pub fn look_at(from: Vec3, to: Vec3, up: Vec3) -> Mat4 {
+        let w = (to - from).unit_vector();
+        let u = up.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        Mat4::new([
+            [u.x, v.x, w.x, from.x],
+            [u.y, v.y, w.y, from.y],
+            [u.z, v.z, w.z, from.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn at(&self, row: usize, col: usize) -> f64 {
+        self.m[row][col]
+    }
+
+    // Applies `self` and then `next`
+    pub fn then(&self, next: &Mat4) -> Mat4 {
+        *next * *self
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut result = [[0.0; 4]; 4];
+        for (row, values) in result.iter_mut().enumerate() {
+            for (col, value) in values.iter_mut().enumerate() {
+                *value = self.m[col][row];
+            }
+        }
+        Mat4::new(result)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None if the matrix is singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for k in 0..4 {
+                a[col][k] *= scale;
+                inv[col][k] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for k in 0..4 {
+                        a[row][k] -= factor * a[col][k];
+                        inv[row][k] -= factor * inv[col][k];
+                    }
+                }
+            }
+        }
+        Some(Mat4::new(inv))
+    }
+
+    // Determinant of the upper 3x3, i.e. how much the transform scales volumes
+    pub fn determinant_3x3(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.transform_vector(point) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z,
+        )
+    }
+
+    // Normals transform by the inverse transpose, `self` must already be the inverse of the
+    // transform being applied
+    pub fn transform_normal_by_inverse(&self, normal: Vec3) -> Vec3 {
+        self.transpose().transform_vector(normal)
+    }
+}
+
+impl ops::Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut result = [[0.0; 4]; 4];
+        for (row, values) in result.iter_mut().enumerate() {
+            for (col, value) in values.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[row][k] * rhs.m[k][col]).sum();
+            }
+        }
+        Mat4::new(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn inverse_round_trip() {
+        let m = Mat4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Mat4::scale(Vec3::new(2.0, 0.5, 3.0));
+        let product = m * m.inverse().unwrap();
+        for row in 0..4 {
+            for col in 0..4 {
+                let expected = if row == col { 1.0 } else { 0.0 };
+                assert!((product.at(row, col) - expected).abs() < 1e-9);
+            }
+        }
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn rotations() {
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        assert_near(
+            Mat4::rotate_z(90.0).transform_vector(x),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_near(
+            Mat4::rotate_y(90.0).transform_vector(x),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_near(
+            Mat4::rotate_x(90.0).transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn composition_order() {
+        let m = Mat4::scale(Vec3::new(2.0, 2.0, 2.0))
+            .then(&Mat4::translation(Vec3::new(1.0, 0.0, 0.0)));
+        assert_near(
+            m.transform_point(Vec3::new(1.0, 1.0, 1.0)),
+            Vec3::new(3.0, 2.0, 2.0),
+        );
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let m = Mat4::scale(Vec3::new(4.0, 1.0, 1.0)) * Mat4::rotate_z(30.0);
+        let tangent = Vec3::new(1.0, 1.0, 0.0);
+        let normal = Vec3::new(1.0, -1.0, 0.0);
+        let new_tangent = m.transform_vector(tangent);
+        let new_normal = m.inverse().unwrap().transform_normal_by_inverse(normal);
+        assert!(new_tangent.dot(&new_normal).abs() < 1e-9);
+    }
+}
@@ -26,6 +26,19 @@ pub trait Material {
         0.0
     }
 
+    // What light arriving along `scattered` is multiplied by on its way to `ray_in`, the BSDF
+    // times the cosine, for the `attenuation` that `scatter` reported. Materials whose colour
+    // depends on the direction, like the Fresnel reflectance of a metal, work it out here.
+    fn scattering(
+        &self,
+        ray_in: &Ray3,
+        hit_record: &HitRecord,
+        attenuation: Vec3,
+        scattered: &Ray3,
+    ) -> Vec3 {
+        attenuation * self.scattering_pdf(ray_in, hit_record, scattered)
+    }
+
     fn emitted(
         &self,
         _ray_in: &Ray3,
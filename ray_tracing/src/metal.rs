@@ -1,6 +1,7 @@
 use crate::hittable::HitRecord;
 use crate::material::{Material, ScatterPDF, ScatterRecord};
 use crate::ray::Ray3;
+use crate::sampler::{sample_1d, sample_2d};
 use nurbs::vector_3::Vec3;
 
 #[derive(Debug, Clone, Copy)]
@@ -11,8 +12,11 @@ pub struct Metal {
 
 impl Material for Metal {
     fn scatter(&self, ray_in: &Ray3, hit_rec: &HitRecord, scatter_rec: &mut ScatterRecord) -> bool {
-        let reflected = ray_in.direction().reflect(&hit_rec.normal).unit_vector()
-            + (self.fuzz * Vec3::random_in_unit_sphere());
+        // Perturb the mirror direction by a uniform point in a ball of radius fuzz
+        let (r1, r2) = sample_2d();
+        let in_sphere = Vec3::on_unit_sphere(r1, r2) * sample_1d().cbrt();
+        let reflected =
+            ray_in.direction().reflect(&hit_rec.normal).unit_vector() + (self.fuzz * in_sphere);
 
         scatter_rec.attenuation = self.albedo;
         scatter_rec.pdf = ScatterPDF::Skip(Ray3::new(hit_rec.point, reflected, ray_in.time()));
@@ -0,0 +1,120 @@
+use crate::ggx::{refraction_half_vector, GGX};
+use crate::orthonormal_basis::OrthonormalBasis;
+use crate::pdf::PDF;
+use crate::rough_dielectric::fresnel_dielectric;
+use crate::sampler::{sample_1d, sample_2d};
+use nurbs::vector_3::Vec3;
+
+// Reflections off a rough surface, sampled through the microfacet normals visible from the
+// incoming ray
+pub struct MicrofacetPDF {
+    uvw: OrthonormalBasis,
+    // Towards where the ray came from, in the surface's frame
+    wo: Vec3,
+    distribution: GGX,
+}
+
+impl PDF for MicrofacetPDF {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let wi = self.uvw.to_local(direction.unit_vector());
+        if wi.z <= 0.0 || self.wo.z <= 0.0 {
+            return 0.0;
+        }
+        let m = (self.wo + wi).unit_vector();
+        self.distribution.visible_d(&self.wo, &m) / (4.0 * self.wo.dot(&m))
+    }
+
+    fn generate(&self) -> Vec3 {
+        let (r1, r2) = sample_2d();
+        let m = self.distribution.sample_visible(&self.wo, r1, r2);
+        self.uvw.transform((-self.wo).reflect(&m))
+    }
+}
+
+impl MicrofacetPDF {
+    pub fn new(normal: &Vec3, ray_direction: &Vec3, distribution: GGX) -> MicrofacetPDF {
+        MicrofacetPDF::from_basis(OrthonormalBasis::new(normal), ray_direction, distribution)
+    }
+
+    // For anisotropic distributions, which are stretched along the u axis of `uvw`
+    pub fn from_basis(
+        uvw: OrthonormalBasis,
+        ray_direction: &Vec3,
+        distribution: GGX,
+    ) -> MicrofacetPDF {
+        let wo = uvw.to_local(-ray_direction.unit_vector());
+        MicrofacetPDF {
+            uvw,
+            wo,
+            distribution,
+        }
+    }
+}
+
+// Reflections and refractions through a rough interface between two dielectrics. A normal
+// visible from the incoming ray is sampled, then its Fresnel reflectance picks whether the ray
+// reflects off it or refracts through it. `eta` is the index of refraction beyond the surface
+// over the one in front of it.
+pub struct MicrofacetTransmissionPDF {
+    uvw: OrthonormalBasis,
+    wo: Vec3,
+    eta: f64,
+    distribution: GGX,
+}
+
+impl PDF for MicrofacetTransmissionPDF {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let wi = self.uvw.to_local(direction.unit_vector());
+        if self.wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        if wi.z > 0.0 {
+            let m = (self.wo + wi).unit_vector();
+            let fresnel = fresnel_dielectric(self.wo.dot(&m), self.eta);
+            return fresnel * self.distribution.visible_d(&self.wo, &m) / (4.0 * self.wo.dot(&m));
+        }
+        match refraction_half_vector(&self.wo, &wi, self.eta) {
+            Some(m) => {
+                // With the change from the density of m to the density of the refracted ray
+                let fresnel = fresnel_dielectric(self.wo.dot(&m), self.eta);
+                let denominator = self.wo.dot(&m) + self.eta * wi.dot(&m);
+                (1.0 - fresnel)
+                    * self.distribution.visible_d(&self.wo, &m)
+                    * self.eta
+                    * self.eta
+                    * -wi.dot(&m)
+                    / (denominator * denominator)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn generate(&self) -> Vec3 {
+        let (r1, r2) = sample_2d();
+        let m = self.distribution.sample_visible(&self.wo, r1, r2);
+        let wi = if sample_1d() < fresnel_dielectric(self.wo.dot(&m), self.eta) {
+            (-self.wo).reflect(&m)
+        } else {
+            Vec3::refract(&-self.wo, &m, 1.0 / self.eta)
+        };
+        self.uvw.transform(wi)
+    }
+}
+
+impl MicrofacetTransmissionPDF {
+    pub fn new(
+        normal: &Vec3,
+        ray_direction: &Vec3,
+        eta: f64,
+        distribution: GGX,
+    ) -> MicrofacetTransmissionPDF {
+        let uvw = OrthonormalBasis::new(normal);
+        let wo = uvw.to_local(-ray_direction.unit_vector());
+        MicrofacetTransmissionPDF {
+            uvw,
+            wo,
+            eta,
+            distribution,
+        }
+    }
+}
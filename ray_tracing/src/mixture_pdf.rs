@@ -1,4 +1,5 @@
 use crate::pdf::PDF;
+use crate::sampler::sample_1d;
 use nurbs::vector_3::Vec3;
 
 pub struct MixturePDF {
@@ -12,7 +13,7 @@ impl PDF for MixturePDF {
     }
 
     fn generate(&self) -> Vec3 {
-        if rand::random::<f64>() < 0.5 {
+        if sample_1d() < 0.5 {
             self.pdf_0.generate()
         } else {
             self.pdf_1.generate()
@@ -0,0 +1,226 @@
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::orthonormal_basis::OrthonormalBasis;
+use crate::ray::Ray3;
+use crate::texture::Texture;
+use nurbs::vector_3::Vec3;
+use std::sync::Arc;
+
+// Step in u and v over which bump maps are differentiated
+const BUMP_STEP: f64 = 1.0 / 1024.0;
+
+enum Perturbation {
+    // Tangent space normals stored as colors, x along dpdu, y along dpdv and z out of the
+    // surface, each mapped from [-1, 1] to [0, 1]
+    NormalMap(Arc<dyn Texture + Sync + Send>),
+    // Heights over the surface, the red channel of the texture times the scale
+    Bump(Arc<dyn Texture + Sync + Send>, f64),
+}
+
+// Wraps a material to give a surface detail its geometry doesn't have. The shading normal
+// is tilted by a normal map or by the slope of a bump map before the material scatters, in
+// the tangent frame of the hit.
+pub struct NormalMapped {
+    material: Arc<dyn Material + Sync + Send>,
+    perturbation: Perturbation,
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray_in: &Ray3, hit_rec: &HitRecord, scatter_rec: &mut ScatterRecord) -> bool {
+        self.material
+            .scatter(ray_in, &self.shaded(hit_rec), scatter_rec)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray3, hit_rec: &HitRecord, scattered: &Ray3) -> f64 {
+        self.material
+            .scattering_pdf(ray_in, &self.shaded(hit_rec), scattered)
+    }
+
+    fn scattering(
+        &self,
+        ray_in: &Ray3,
+        hit_rec: &HitRecord,
+        attenuation: Vec3,
+        scattered: &Ray3,
+    ) -> Vec3 {
+        self.material
+            .scattering(ray_in, &self.shaded(hit_rec), attenuation, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray3, hit_record: &HitRecord, u: f64, v: f64, point: Vec3) -> Vec3 {
+        self.material.emitted(ray_in, hit_record, u, v, point)
+    }
+}
+
+impl NormalMapped {
+    // `texture` is usually an `ImageTexture` of a tangent space normal map
+    pub fn new_normal_map(
+        material: Arc<dyn Material + Sync + Send>,
+        texture: Arc<dyn Texture + Sync + Send>,
+    ) -> NormalMapped {
+        NormalMapped {
+            material,
+            perturbation: Perturbation::NormalMap(texture),
+        }
+    }
+
+    // Bumps `scale` high where the texture is 1, any texture will do
+    pub fn new_bump(
+        material: Arc<dyn Material + Sync + Send>,
+        texture: Arc<dyn Texture + Sync + Send>,
+        scale: f64,
+    ) -> NormalMapped {
+        NormalMapped {
+            material,
+            perturbation: Perturbation::Bump(texture, scale),
+        }
+    }
+
+    fn shaded(&self, hit_rec: &HitRecord) -> HitRecord {
+        let mut shaded = hit_rec.clone();
+        shaded.normal = self.shading_normal(hit_rec);
+        shaded
+    }
+
+    // The perturbed normal, on the same side of the surface as the hit's normal
+    pub fn shading_normal(&self, hit_rec: &HitRecord) -> Vec3 {
+        // Work with the normal facing out of the surface, like the tangents
+        let outward = if hit_rec.front_face {
+            hit_rec.normal
+        } else {
+            -hit_rec.normal
+        };
+        let (dpdu, dpdv) = tangent_frame(hit_rec, outward);
+
+        let normal = match &self.perturbation {
+            Perturbation::NormalMap(texture) => {
+                let color = texture.value(hit_rec.u, hit_rec.v, hit_rec.point);
+                let local = 2.0 * color - Vec3::new(1.0, 1.0, 1.0);
+                let tangent = dpdu.unit_vector();
+                let bitangent = dpdv.unit_vector();
+                local.x * tangent + local.y * bitangent + local.z * outward
+            }
+            Perturbation::Bump(texture, scale) => {
+                let height = |u: f64, v: f64, point: Vec3| scale * texture.value(u, v, point).x;
+                let (u, v, point) = (hit_rec.u, hit_rec.v, hit_rec.point);
+                let here = height(u, v, point);
+                let along_u = height(u + BUMP_STEP, v, point + BUMP_STEP * dpdu);
+                let along_v = height(u, v + BUMP_STEP, point + BUMP_STEP * dpdv);
+                // The derivatives of the bumped surface, leaving out the bend of the surface
+                // itself
+                let bumped_dpdu = dpdu + (along_u - here) / BUMP_STEP * outward;
+                let bumped_dpdv = dpdv + (along_v - here) / BUMP_STEP * outward;
+                let normal = bumped_dpdu.cross(&bumped_dpdv);
+                if normal.dot(&outward) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                }
+            }
+        };
+        if normal.near_zero() || normal.dot(&outward) <= 0.0 {
+            return hit_rec.normal;
+        }
+        let normal = normal.unit_vector();
+        if hit_rec.front_face {
+            normal
+        } else {
+            -normal
+        }
+    }
+}
+
+// dpdu and dpdv of the hit made perpendicular to `outward` and to each other, keeping their
+// lengths and the side dpdv is on. Hits without tangents get an arbitrary unit frame.
+fn tangent_frame(hit_rec: &HitRecord, outward: Vec3) -> (Vec3, Vec3) {
+    let dpdu = hit_rec.dpdu - hit_rec.dpdu.dot(&outward) * outward;
+    if dpdu.near_zero() {
+        let uvw = OrthonormalBasis::new(&outward);
+        return (uvw.u(), uvw.v());
+    }
+    let bitangent = outward.cross(&dpdu).unit_vector();
+    let length = hit_rec.dpdv.length();
+    let length = if length > 0.0 { length } else { dpdu.length() };
+    if bitangent.dot(&hit_rec.dpdv) < 0.0 {
+        (dpdu, -length * bitangent)
+    } else {
+        (dpdu, length * bitangent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::solid_texture::SolidTexture;
+
+    fn hit(front_face: bool) -> HitRecord {
+        let mut hit_rec = HitRecord::new();
+        hit_rec.normal = Vec3::new(0.0, 0.0, if front_face { 1.0 } else { -1.0 });
+        hit_rec.front_face = front_face;
+        hit_rec.dpdu = Vec3::new(2.0, 0.0, 0.0);
+        hit_rec.dpdv = Vec3::new(0.0, 2.0, 0.0);
+        hit_rec
+    }
+
+    fn lambertian() -> Arc<dyn Material + Sync + Send> {
+        Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn normal_maps_tilt_along_the_tangents() {
+        let flat = NormalMapped::new_normal_map(
+            lambertian(),
+            Arc::new(SolidTexture::new(Vec3::new(0.5, 0.5, 1.0))),
+        );
+        assert!((flat.shading_normal(&hit(true)) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        // Tilted 45 degrees towards +u, on either side of the surface
+        let tilted = NormalMapped::new_normal_map(
+            lambertian(),
+            Arc::new(SolidTexture::new(Vec3::new(1.0, 0.5, 1.0))),
+        );
+        let half = 0.5f64.sqrt();
+        let front = tilted.shading_normal(&hit(true));
+        assert!(
+            (front - Vec3::new(half, 0.0, half)).length() < 1e-12,
+            "{:?}",
+            front
+        );
+        let back = tilted.shading_normal(&hit(false));
+        assert!((back + front).length() < 1e-12, "{:?}", back);
+    }
+
+    // Heights of a ramp rising along x, which solid textures see through the point
+    struct Ramp {}
+
+    impl Texture for Ramp {
+        fn value(&self, _u: f64, _v: f64, point: Vec3) -> Vec3 {
+            Vec3::new(point.x, point.x, point.x)
+        }
+    }
+
+    #[test]
+    fn bumps_follow_the_slope() {
+        // A slope of 1 tilts the normal 45 degrees away from the way it rises
+        let bumped = NormalMapped::new_bump(lambertian(), Arc::new(Ramp {}), 1.0);
+        let half = 0.5f64.sqrt();
+        let normal = bumped.shading_normal(&hit(true));
+        assert!(
+            (normal - Vec3::new(-half, 0.0, half)).length() < 1e-9,
+            "{:?}",
+            normal
+        );
+
+        // Constant heights change nothing, even without tangents
+        let flat = NormalMapped::new_bump(
+            lambertian(),
+            Arc::new(SolidTexture::new(Vec3::new(0.3, 0.3, 0.3))),
+            2.0,
+        );
+        let mut hit_rec = hit(false);
+        hit_rec.dpdu = Vec3::empty();
+        hit_rec.dpdv = Vec3::empty();
+        assert!((flat.shading_normal(&hit_rec) - hit_rec.normal).length() < 1e-12);
+    }
+}
@@ -0,0 +1,661 @@
+// Wavefront OBJ/MTL import. Faces are fan triangulated and split into one `TriangleMesh` per
+// group and material. MTL materials are mapped onto the existing material types:
+//   Ke set                      -> DiffuseLight
+//   d < 1, Tr > 0 or illum 4..7 -> Dielectric with Ni as the index of refraction
+//   illum 3 or Pm >= 0.5        -> Metal with Ks (Kd for Pm or without Ks) as the albedo and Ns
+//                                  for the fuzz
+//   otherwise                   -> Lambertian with map_Kd, or Kd when there is no texture
+
+use crate::bvh_node::BVHNode;
+use crate::dielectric::Dielectric;
+use crate::diffuse_light::DiffuseLight;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::image_texture::ImageTexture;
+use crate::lambertian::Lambertian;
+use crate::material::Material;
+use crate::metal::Metal;
+use crate::triangle::MeshBuffers;
+use crate::triangle_mesh::{MeshError, TriangleMesh};
+use nurbs::vector_3::Vec3;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Image(PathBuf, image::ImageError),
+    Parse {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+    NoFaces(PathBuf),
+    Mesh(PathBuf, MeshError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, error) => write!(f, "could not read {}: {}", path.display(), error),
+            ObjError::Image(path, error) => {
+                write!(f, "could not load texture {}: {}", path.display(), error)
+            }
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{} line {}: {}", file.display(), line, message),
+            ObjError::NoFaces(path) => write!(f, "{} doesn't contain any faces", path.display()),
+            ObjError::Mesh(path, error) => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+pub struct ObjModel {
+    // Every mesh in the file, ready to add to a `HittableList`
+    pub object: Arc<dyn Hittable + Sync + Send>,
+    // The meshes with an emissive material, to pass to the camera as lights
+    pub lights: Vec<Arc<dyn Hittable + Sync + Send>>,
+}
+
+// Loads an OBJ file, faces without a `usemtl` use `default_material`
+pub fn load_obj<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Material + Sync + Send>,
+) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    parse_obj(&source, path, base_dir, default_material)
+}
+
+// Parses OBJ source text, `file` is only used for error messages and `base_dir` to find the
+// material libraries
+pub fn parse_obj(
+    source: &str,
+    file: &Path,
+    base_dir: &Path,
+    default_material: Arc<dyn Material + Sync + Send>,
+) -> Result<ObjModel, ObjError> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut materials: HashMap<String, ObjMaterial> = HashMap::new();
+
+    let mut groups: Vec<FaceGroup> = Vec::new();
+    let mut group_name = String::new();
+    let mut material_name: Option<String> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let mut line = Line::new(file, index + 1, line);
+        let keyword = match line.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "v" => positions.push(line.vec3()?),
+            "vn" => normals.push(line.vec3()?),
+            "vt" => {
+                let u = line.f64()?;
+                let v = if line.has_more() { line.f64()? } else { 0.0 };
+                uvs.push((u, v));
+            }
+            "f" => {
+                let mut corners = Vec::new();
+                while let Some(token) = line.next() {
+                    corners.push(line.corner(token, positions.len(), uvs.len(), normals.len())?);
+                }
+                if corners.len() < 3 {
+                    return Err(line.error("a face needs at least 3 vertices".to_owned()));
+                }
+
+                let matches_current = groups.last().is_some_and(|group| {
+                    group.name == group_name && group.material == material_name
+                });
+                if !matches_current {
+                    groups.push(FaceGroup::new(&group_name, material_name.clone()));
+                }
+                let group = groups.last_mut().unwrap();
+
+                // Fan triangulation around the first corner
+                let first = group.vertex(corners[0]);
+                for pair in corners[1..].windows(2) {
+                    let b = group.vertex(pair[0]);
+                    let c = group.vertex(pair[1]);
+                    group.faces.push([first, b, c]);
+                }
+            }
+            "g" | "o" => {
+                group_name = line.rest();
+            }
+            "usemtl" => {
+                let name = line.rest();
+                if !materials.contains_key(&name) {
+                    return Err(line.error(format!("unknown material '{}'", name)));
+                }
+                material_name = Some(name);
+            }
+            "mtllib" => {
+                while let Some(library) = line.next() {
+                    let library_path = base_dir.join(library);
+                    let library_source = read_file(&library_path)?;
+                    let library_dir = library_path.parent().unwrap_or(Path::new(""));
+                    parse_mtl(&library_source, &library_path, library_dir, &mut materials)?;
+                }
+            }
+            // Smoothing groups, lines, points and anything else we can't render
+            _ => {}
+        }
+    }
+
+    let mut meshes = HittableList::new();
+    let mut lights = Vec::new();
+    for group in groups {
+        let (material, emissive) = match &group.material {
+            Some(name) => {
+                let material = &materials[name];
+                (Arc::clone(&material.material), material.emissive)
+            }
+            None => (Arc::clone(&default_material), false),
+        };
+        let mesh = group
+            .into_mesh(&positions, &uvs, &normals, material)
+            .map_err(|error| ObjError::Mesh(file.to_path_buf(), error))?;
+        let mesh: Arc<dyn Hittable + Sync + Send> = Arc::new(mesh);
+        if emissive {
+            lights.push(Arc::clone(&mesh));
+        }
+        meshes.add(mesh);
+    }
+
+    let object: Arc<dyn Hittable + Sync + Send> = match meshes.objects.len() {
+        0 => return Err(ObjError::NoFaces(file.to_path_buf())),
+        1 => Arc::clone(&meshes.objects[0]),
+        _ => Arc::new(BVHNode::from_list(&meshes)),
+    };
+    Ok(ObjModel { object, lights })
+}
+
+struct ObjMaterial {
+    material: Arc<dyn Material + Sync + Send>,
+    emissive: bool,
+}
+
+// The MTL statements we understand, collected until the next `newmtl`
+struct MtlDefinition {
+    name: String,
+    line: usize,
+    diffuse: Vec3,
+    specular: Option<Vec3>,
+    emission: Vec3,
+    shininess: f64,
+    ior: f64,
+    dissolve: f64,
+    illum: i32,
+    metallic: f64,
+    diffuse_map: Option<String>,
+}
+
+impl MtlDefinition {
+    fn new(name: String, line: usize) -> MtlDefinition {
+        MtlDefinition {
+            name,
+            line,
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            specular: None,
+            emission: Vec3::empty(),
+            shininess: 0.0,
+            ior: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+            metallic: 0.0,
+            diffuse_map: None,
+        }
+    }
+
+    fn build(self, file: &Path, base_dir: &Path) -> Result<ObjMaterial, ObjError> {
+        if self.name.is_empty() {
+            return Err(ObjError::Parse {
+                file: file.to_path_buf(),
+                line: self.line,
+                message: "newmtl needs a name".to_owned(),
+            });
+        }
+
+        let kind = self.kind();
+        let material: Arc<dyn Material + Sync + Send> = match kind {
+            MtlKind::Light(emission) => Arc::new(DiffuseLight::color(emission)),
+            MtlKind::Glass(ior) => Arc::new(Dielectric::new(ior)),
+            MtlKind::Metal { albedo, fuzz } => Arc::new(Metal::new(albedo, fuzz)),
+            MtlKind::Textured(ref map) => {
+                let path = base_dir.join(map);
+                let texture = ImageTexture::open(&path)
+                    .map_err(|error| ObjError::Image(path.clone(), error))?;
+                Arc::new(Lambertian::new(Arc::new(texture)))
+            }
+            MtlKind::Diffuse(color) => Arc::new(Lambertian::from_color(color)),
+        };
+        Ok(ObjMaterial {
+            material,
+            emissive: matches!(kind, MtlKind::Light(_)),
+        })
+    }
+
+    // Picks the material type as in the table at the top of the file
+    fn kind(&self) -> MtlKind {
+        if self.emission.length_squared() > 0.0 {
+            MtlKind::Light(self.emission)
+        } else if self.dissolve < 1.0 || (4..=7).contains(&self.illum) {
+            MtlKind::Glass(self.ior)
+        } else if self.illum == 3 || self.metallic >= 0.5 {
+            let albedo = match self.specular {
+                Some(specular) if self.metallic < 0.5 => specular,
+                _ => self.diffuse,
+            };
+            // Map the Phong exponent onto a roughness in [0, 1]
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            MtlKind::Metal { albedo, fuzz }
+        } else {
+            match &self.diffuse_map {
+                Some(map) => MtlKind::Textured(map.clone()),
+                None => MtlKind::Diffuse(self.diffuse),
+            }
+        }
+    }
+}
+
+// The material an MTL definition becomes, with the values it is made from
+#[derive(Debug)]
+enum MtlKind {
+    Light(Vec3),
+    // Index of refraction
+    Glass(f64),
+    Metal { albedo: Vec3, fuzz: f64 },
+    // Path of the diffuse texture, relative to the MTL file
+    Textured(String),
+    Diffuse(Vec3),
+}
+
+fn parse_mtl(
+    source: &str,
+    file: &Path,
+    base_dir: &Path,
+    materials: &mut HashMap<String, ObjMaterial>,
+) -> Result<(), ObjError> {
+    for definition in mtl_definitions(source, file)? {
+        let name = definition.name.clone();
+        materials.insert(name, definition.build(file, base_dir)?);
+    }
+    Ok(())
+}
+
+// The definitions of an MTL file in order, without loading their textures
+fn mtl_definitions(source: &str, file: &Path) -> Result<Vec<MtlDefinition>, ObjError> {
+    let mut definitions = Vec::new();
+    let mut current: Option<MtlDefinition> = None;
+    for (index, line) in source.lines().enumerate() {
+        let mut line = Line::new(file, index + 1, line);
+        let keyword = match line.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            definitions.extend(current.take());
+            current = Some(MtlDefinition::new(line.rest(), line.number));
+            continue;
+        }
+
+        let definition = match current.as_mut() {
+            Some(definition) => definition,
+            None => return Err(line.error(format!("'{}' before any newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => definition.diffuse = line.vec3()?,
+            "Ks" => definition.specular = Some(line.vec3()?),
+            "Ke" => definition.emission = line.vec3()?,
+            "Ns" => definition.shininess = line.f64()?,
+            "Ni" => definition.ior = line.f64()?,
+            "d" => definition.dissolve = line.f64()?,
+            "Tr" => definition.dissolve = 1.0 - line.f64()?,
+            "Pm" => definition.metallic = line.f64()?,
+            "illum" => definition.illum = line.f64()? as i32,
+            "map_Kd" => definition.diffuse_map = Some(line.last_token()?),
+            _ => {}
+        }
+    }
+    definitions.extend(current);
+    Ok(definitions)
+}
+
+// The faces sharing a group and material, with the OBJ position/uv/normal index triples
+// merged into single vertices
+struct FaceGroup {
+    name: String,
+    material: Option<String>,
+    corners: Vec<Corner>,
+    vertex_lookup: HashMap<Corner, usize>,
+    faces: Vec<[usize; 3]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+impl FaceGroup {
+    fn new(name: &str, material: Option<String>) -> FaceGroup {
+        FaceGroup {
+            name: name.to_owned(),
+            material,
+            corners: Vec::new(),
+            vertex_lookup: HashMap::new(),
+            faces: Vec::new(),
+        }
+    }
+
+    fn vertex(&mut self, corner: Corner) -> usize {
+        match self.vertex_lookup.get(&corner) {
+            Some(index) => *index,
+            None => {
+                let index = self.corners.len();
+                self.corners.push(corner);
+                self.vertex_lookup.insert(corner, index);
+                index
+            }
+        }
+    }
+
+    fn into_mesh(
+        self,
+        positions: &[Vec3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+        material: Arc<dyn Material + Sync + Send>,
+    ) -> Result<TriangleMesh, MeshError> {
+        // Normals and uvs are only used when every vertex of the mesh has one
+        let mesh_uvs = self
+            .corners
+            .iter()
+            .map(|corner| corner.uv.map(|index| uvs[index]))
+            .collect::<Option<Vec<(f64, f64)>>>();
+        let mesh_normals = self
+            .corners
+            .iter()
+            .map(|corner| corner.normal.map(|index| normals[index]))
+            .collect::<Option<Vec<Vec3>>>();
+        let buffers = MeshBuffers {
+            positions: self
+                .corners
+                .iter()
+                .map(|corner| positions[corner.position])
+                .collect(),
+            normals: mesh_normals,
+            uvs: mesh_uvs,
+        };
+        TriangleMesh::from_buffers(Arc::new(buffers), self.faces, material)
+    }
+}
+
+struct Line<'a> {
+    file: &'a Path,
+    number: usize,
+    tokens: std::iter::Peekable<std::str::SplitWhitespace<'a>>,
+}
+
+impl<'a> Line<'a> {
+    fn new(file: &'a Path, number: usize, text: &'a str) -> Line<'a> {
+        let content = match text.find('#') {
+            Some(comment_start) => &text[..comment_start],
+            None => text,
+        };
+        Line {
+            file,
+            number,
+            tokens: content.split_whitespace().peekable(),
+        }
+    }
+
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            file: self.file.to_path_buf(),
+            line: self.number,
+            message,
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    fn has_more(&mut self) -> bool {
+        self.tokens.peek().is_some()
+    }
+
+    // The remaining tokens joined by single spaces, as used for group and material names
+    fn rest(&mut self) -> String {
+        self.tokens.by_ref().collect::<Vec<&str>>().join(" ")
+    }
+
+    // Texture statements may have options before the file name, which is always last
+    fn last_token(&mut self) -> Result<String, ObjError> {
+        match self.tokens.by_ref().last() {
+            Some(token) => Ok(token.to_owned()),
+            None => Err(self.error("expected a file name".to_owned())),
+        }
+    }
+
+    fn f64(&mut self) -> Result<f64, ObjError> {
+        match self.tokens.next() {
+            Some(token) => token
+                .parse::<f64>()
+                .map_err(|_| self.error(format!("expected a number but found '{}'", token))),
+            None => Err(self.error("expected a number".to_owned())),
+        }
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ObjError> {
+        let x = self.f64()?;
+        let y = self.f64()?;
+        let z = self.f64()?;
+        Ok(Vec3::new(x, y, z))
+    }
+
+    // Parses a face corner (`v`, `v/vt`, `v//vn` or `v/vt/vn`) into zero based indices
+    fn corner(
+        &self,
+        token: &str,
+        position_count: usize,
+        uv_count: usize,
+        normal_count: usize,
+    ) -> Result<Corner, ObjError> {
+        let mut parts = token.split('/');
+        let position = match parts.next() {
+            Some(part) => self.index(part, position_count, token)?,
+            None => return Err(self.error(format!("bad face vertex '{}'", token))),
+        };
+        let uv = match parts.next() {
+            Some("") | None => None,
+            Some(part) => Some(self.index(part, uv_count, token)?),
+        };
+        let normal = match parts.next() {
+            Some("") | None => None,
+            Some(part) => Some(self.index(part, normal_count, token)?),
+        };
+        if parts.next().is_some() {
+            return Err(self.error(format!("bad face vertex '{}'", token)));
+        }
+        Ok(Corner {
+            position,
+            uv,
+            normal,
+        })
+    }
+
+    // OBJ indices start at 1, negative indices count back from the last element
+    fn index(&self, part: &str, count: usize, token: &str) -> Result<usize, ObjError> {
+        let index = part
+            .parse::<i64>()
+            .map_err(|_| self.error(format!("bad face vertex '{}'", token)))?;
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            Err(self.error(format!("face index {} is out of range", index)))
+        } else {
+            Ok(resolved as usize)
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|error| ObjError::Io(path.to_path_buf(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Interval;
+    use crate::ray::Ray3;
+
+    fn parse(source: &str) -> Result<ObjModel, ObjError> {
+        let grey = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        parse_obj(source, Path::new("test.obj"), Path::new(""), grey)
+    }
+
+    #[test]
+    fn quad_with_attributes() {
+        let source = "
+            # A unit square facing +z
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            vn 0 0 1
+            g square
+            f 1/1/1 2/2/1 3/3/1 -1/-1/-1
+        ";
+        let model = parse(source).unwrap();
+        assert!(model.lights.is_empty());
+
+        let ray = Ray3::new(Vec3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = crate::hittable::HitRecord::new();
+        assert!(model
+            .object
+            .hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.u - 0.75).abs() < 1e-9);
+        assert!((rec.v - 0.25).abs() < 1e-9);
+        assert!((rec.normal.z - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn malformed_faces() {
+        let cases = [
+            ("v 0 0 0\nv 1 0 0\nf 1 2\n", 3),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n", 4),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1/a 2 3\n", 5),
+            ("v 0 zero 0\n", 1),
+            ("v 0 0 0\nusemtl missing\n", 2),
+        ];
+        for (source, expected_line) in cases {
+            match parse(source) {
+                Err(ObjError::Parse { line, .. }) => assert_eq!(line, expected_line),
+                _ => panic!("{:?} should fail to parse", source),
+            }
+        }
+        assert!(matches!(parse("v 0 0 0\n"), Err(ObjError::NoFaces(_))));
+    }
+
+    #[test]
+    fn mtl_materials() {
+        let source = "
+            newmtl lamp
+            Kd 0.8 0.8 0.8
+            Ke 4 3 2
+            newmtl glass
+            Ni 1.33
+            d 0.5
+            newmtl tinted
+            Tr 0.25
+            newmtl lens
+            illum 6
+            Ni 1.7
+            newmtl chrome
+            illum 3
+            Ks 0.9 0.8 0.7
+            Ns 198
+            newmtl gold
+            illum 3
+            Kd 1 0.8 0.3
+            Ns 0
+            newmtl copper
+            Pm 1
+            Kd 0.95 0.64 0.54
+            Ks 0.1 0.1 0.1
+            newmtl wood
+            Kd 0.5 0.5 0.5
+            map_Kd textures/wood.png
+            newmtl plaster
+            Kd 0.7 0.6 0.5
+        ";
+        let definitions = mtl_definitions(source, Path::new("test.mtl")).unwrap();
+        let kinds: Vec<(String, MtlKind)> = definitions
+            .iter()
+            .map(|definition| (definition.name.clone(), definition.kind()))
+            .collect();
+        let close = |a: Vec3, b: [f64; 3]| (a - Vec3::new(b[0], b[1], b[2])).length() < 1e-9;
+        let kind = |name: &str| &kinds.iter().find(|(other, _)| other == name).unwrap().1;
+        assert_eq!(kinds.len(), 9);
+
+        assert!(
+            matches!(kind("lamp"), MtlKind::Light(emission) if close(*emission, [4.0, 3.0, 2.0]))
+        );
+        assert!(matches!(kind("glass"), MtlKind::Glass(ior) if *ior == 1.33));
+        assert!(matches!(kind("tinted"), MtlKind::Glass(ior) if *ior == 1.5));
+        assert!(matches!(kind("lens"), MtlKind::Glass(ior) if *ior == 1.7));
+        // Ns 198 is a tight highlight, Ns 0 is as rough as a metal gets
+        assert!(matches!(
+            kind("chrome"),
+            MtlKind::Metal { albedo, fuzz } if close(*albedo, [0.9, 0.8, 0.7]) && (fuzz - 0.1).abs() < 1e-9
+        ));
+        // Without Ks the metal takes its color from Kd rather than being black
+        assert!(matches!(
+            kind("gold"),
+            MtlKind::Metal { albedo, fuzz } if close(*albedo, [1.0, 0.8, 0.3]) && *fuzz == 1.0
+        ));
+        // PBR metals are colored by Kd, Ks is only a reflectance tweak there
+        assert!(matches!(
+            kind("copper"),
+            MtlKind::Metal { albedo, .. } if close(*albedo, [0.95, 0.64, 0.54])
+        ));
+        assert!(matches!(kind("wood"), MtlKind::Textured(map) if map == "textures/wood.png"));
+        assert!(
+            matches!(kind("plaster"), MtlKind::Diffuse(color) if close(*color, [0.7, 0.6, 0.5]))
+        );
+
+        // Only the light is emissive, a missing texture is an error when building
+        let (file, base) = (Path::new("test.mtl"), Path::new("/nonexistent"));
+        for definition in mtl_definitions(source, file).unwrap() {
+            let name = definition.name.clone();
+            match definition.build(file, base) {
+                Ok(material) => assert_eq!(material.emissive, name == "lamp"),
+                Err(error) => {
+                    assert_eq!(name, "wood");
+                    assert!(matches!(error, ObjError::Image(..)));
+                }
+            }
+        }
+    }
+}
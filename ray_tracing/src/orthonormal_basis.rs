@@ -19,6 +19,21 @@ impl OrthonormalBasis {
         OrthonormalBasis { axis }
     }
 
+    // A basis around `normal` with u along `tangent` as far as it is perpendicular to the
+    // normal, the frame of a surface with a parametrisation. Falls back to `new` when the
+    // tangent is zero or parallel to the normal.
+    pub fn from_tangent(normal: &Vec3, tangent: &Vec3) -> OrthonormalBasis {
+        let w = normal.unit_vector();
+        let u = *tangent - tangent.dot(&w) * w;
+        if u.near_zero() {
+            return OrthonormalBasis::new(normal);
+        }
+        let u = u.unit_vector();
+        OrthonormalBasis {
+            axis: [u, w.cross(&u), w],
+        }
+    }
+
     pub fn u(&self) -> Vec3 {
         self.axis[0]
     }
@@ -34,6 +49,15 @@ impl OrthonormalBasis {
     pub fn transform(&self, point: Vec3) -> Vec3 {
         point.x * self.axis[0] + point.y * self.axis[1] + point.z * self.axis[2]
     }
+
+    // The inverse of `transform`, a world space vector in terms of u, v and w
+    pub fn to_local(&self, vector: Vec3) -> Vec3 {
+        Vec3::new(
+            vector.dot(&self.axis[0]),
+            vector.dot(&self.axis[1]),
+            vector.dot(&self.axis[2]),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -48,4 +72,16 @@ mod tests {
         assert!((result.v().z - 1.0).abs() < f64::EPSILON);
         assert!((result.w().x - 1.0).abs() < f64::EPSILON);
     }
+
+    #[test]
+    fn tangent_frame() {
+        let normal = Vec3::new(0.0, 0.0, 2.0);
+        let result = OrthonormalBasis::from_tangent(&normal, &Vec3::new(3.0, 0.0, 1.0));
+        assert!((result.u() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((result.v() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        assert!((result.w() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        let fallback = OrthonormalBasis::from_tangent(&normal, &Vec3::new(0.0, 0.0, 1.0));
+        assert!((fallback.u() - OrthonormalBasis::new(&normal).u()).length() < 1e-12);
+    }
 }
@@ -1,7 +1,6 @@
+use nurbs::utility::random_u32_range;
 use nurbs::vector_3::Vec3;
 
-use rand::{seq::SliceRandom, thread_rng};
-
 const PERLIN_POINT_COUNT: i32 = 256;
 
 pub struct Perlin {
@@ -14,8 +13,11 @@ pub struct Perlin {
 impl Perlin {
     fn generate_perm() -> Vec<i32> {
         let mut vec: Vec<i32> = (0..PERLIN_POINT_COUNT).collect();
-        let mut rng = thread_rng();
-        vec.shuffle(&mut rng);
+        // Fisher-Yates shuffle
+        for i in (1..vec.len()).rev() {
+            let j = random_u32_range(0, i as u32) as usize;
+            vec.swap(i, j);
+        }
         vec
     }
 
@@ -0,0 +1,206 @@
+use crate::color::DisplayTransform;
+use crate::film::Film;
+use std::num::NonZeroU32;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use winit::dpi::{PhysicalSize, Size};
+use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder};
+use winit::keyboard::{Key, NamedKey};
+use winit::window::WindowBuilder;
+
+// Code modified from the example of https://github.com/rust-windowing/softbuffer/blob/master/README.md
+pub fn render_buffer(buffer: Vec<u32>, buffer_width: u32, buffer_height: u32) {
+    let event_loop = EventLoop::new().unwrap();
+    let window_size = Size::Physical(PhysicalSize {
+        width: buffer_width,
+        height: buffer_height,
+    });
+    let window = Rc::new(
+        WindowBuilder::new()
+            .with_inner_size(window_size)
+            .build(&event_loop)
+            .unwrap(),
+    );
+    let context = unsafe { softbuffer::Context::new(&window) }.unwrap();
+    let mut surface = unsafe { softbuffer::Surface::new(&context, &window) }.unwrap();
+
+    event_loop
+        .run(move |event, elwt| {
+            elwt.set_control_flow(ControlFlow::Wait);
+
+            match event {
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::RedrawRequested,
+                } if window_id == window.id() => {
+                    let (width, height) = {
+                        let size = window.inner_size();
+                        (size.width, size.height)
+                    };
+                    surface
+                        .resize(
+                            NonZeroU32::new(width).unwrap(),
+                            NonZeroU32::new(height).unwrap(),
+                        )
+                        .unwrap();
+
+                    let mut render_buffer = surface.buffer_mut().unwrap();
+                    for index in 0..(width * height) {
+                        render_buffer[index as usize] = buffer[index as usize];
+                    }
+
+                    render_buffer.present().unwrap();
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    window_id,
+                } if window_id == window.id() => {
+                    elwt.exit();
+                }
+                _ => {}
+            }
+        })
+        .unwrap();
+}
+
+// Messages from the render thread to the progressive preview window
+enum PreviewEvent {
+    // A finished pass as 0xFFRRGGBB pixels and the mean samples per pixel so far
+    Pass(Vec<u32>, f64),
+    Done,
+}
+
+// Shows a progressive render while it runs. `render` is called on a worker thread with a
+// callback to hand it every finished pass, which the window then displays with the samples
+// per pixel and elapsed time in its title. Escape stops the render and keeps the window open,
+// closing the window stops it too. The callback returns false once stopped, and the film
+// `render` returns, with every sample taken until then, is passed back.
+pub fn show_progressive<F>(
+    title: &str,
+    width: u32,
+    height: u32,
+    transform: DisplayTransform,
+    render: F,
+) -> Film
+where
+    F: FnOnce(&mut dyn FnMut(&Film) -> bool) -> Film + Send,
+{
+    let event_loop = EventLoopBuilder::<PreviewEvent>::with_user_event()
+        .build()
+        .unwrap();
+    let window = Rc::new(
+        WindowBuilder::new()
+            .with_inner_size(Size::Physical(PhysicalSize { width, height }))
+            .with_title(title)
+            .build(&event_loop)
+            .unwrap(),
+    );
+    let context = unsafe { softbuffer::Context::new(&window) }.unwrap();
+    let mut surface = unsafe { softbuffer::Surface::new(&context, &window) }.unwrap();
+
+    let stop = AtomicBool::new(false);
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        let proxy = event_loop.create_proxy();
+        let stop = &stop;
+        let worker = scope.spawn(move || {
+            let film = render(&mut |film: &Film| {
+                let counts = film.sample_counts();
+                let samples =
+                    counts.iter().map(|&count| count as f64).sum::<f64>() / counts.len() as f64;
+                // Nothing to do if the window is already gone
+                let _ = proxy.send_event(PreviewEvent::Pass(film.to_argb(&transform), samples));
+                !stop.load(Ordering::Relaxed)
+            });
+            let _ = proxy.send_event(PreviewEvent::Done);
+            film
+        });
+
+        let mut buffer = vec![0; (width * height) as usize];
+        let mut samples = 0.0;
+        event_loop
+            .run(|event, elwt| {
+                elwt.set_control_flow(ControlFlow::Wait);
+
+                match event {
+                    Event::UserEvent(PreviewEvent::Pass(pixels, mean_samples)) => {
+                        buffer = pixels;
+                        samples = mean_samples;
+                        window.set_title(&format!(
+                            "{} - {:.1} spp, {:.1} s",
+                            title,
+                            samples,
+                            start.elapsed().as_secs_f64()
+                        ));
+                        window.request_redraw();
+                    }
+                    Event::UserEvent(PreviewEvent::Done) => {
+                        let state = if stop.load(Ordering::Relaxed) {
+                            "stopped"
+                        } else {
+                            "done"
+                        };
+                        window.set_title(&format!(
+                            "{} - {:.1} spp, {} after {:.1} s",
+                            title,
+                            samples,
+                            state,
+                            start.elapsed().as_secs_f64()
+                        ));
+                    }
+                    Event::WindowEvent {
+                        window_id,
+                        event: WindowEvent::RedrawRequested,
+                    } if window_id == window.id() => {
+                        let size = window.inner_size();
+                        let (Some(surface_width), Some(surface_height)) =
+                            (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+                        else {
+                            return;
+                        };
+                        surface.resize(surface_width, surface_height).unwrap();
+
+                        // Copy the overlap, the window may have been resized
+                        let mut render_buffer = surface.buffer_mut().unwrap();
+                        render_buffer.fill(0);
+                        let rows = size.height.min(height) as usize;
+                        let columns = size.width.min(width) as usize;
+                        for y in 0..rows {
+                            let source = y * width as usize;
+                            let target = y * size.width as usize;
+                            render_buffer[target..target + columns]
+                                .copy_from_slice(&buffer[source..source + columns]);
+                        }
+                        render_buffer.present().unwrap();
+                    }
+                    Event::WindowEvent {
+                        event:
+                            WindowEvent::KeyboardInput {
+                                event:
+                                    KeyEvent {
+                                        logical_key: Key::Named(NamedKey::Escape),
+                                        state: ElementState::Pressed,
+                                        ..
+                                    },
+                                ..
+                            },
+                        window_id,
+                    } if window_id == window.id() => {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    Event::WindowEvent {
+                        event: WindowEvent::CloseRequested,
+                        window_id,
+                    } if window_id == window.id() => {
+                        stop.store(true, Ordering::Relaxed);
+                        elwt.exit();
+                    }
+                    _ => {}
+                }
+            })
+            .unwrap();
+        worker.join().unwrap()
+    })
+}
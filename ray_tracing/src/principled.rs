@@ -0,0 +1,385 @@
+use crate::color::luminance;
+use crate::cosine_pdf::CosinePDF;
+use crate::ggx::{refraction_half_vector, GGX};
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterPDF, ScatterRecord};
+use crate::microfacet_pdf::{MicrofacetPDF, MicrofacetTransmissionPDF};
+use crate::orthonormal_basis::OrthonormalBasis;
+use crate::pdf::PDF;
+use crate::ray::Ray3;
+use crate::rough_dielectric::fresnel_dielectric;
+use crate::sampler::sample_1d;
+use crate::solid_texture::SolidTexture;
+use crate::texture::Texture;
+use nurbs::vector_3::Vec3;
+use std::f64::consts::FRAC_1_PI;
+use std::sync::Arc;
+
+// Rougher than this for the lobes to stay something that can be sampled and evaluated
+const MIN_ROUGHNESS: f64 = 0.05;
+
+// One material for most surfaces, after Disney's principled BRDF (Burley 2012) with the
+// transmission of its 2015 BSDF. Every parameter is a texture, scalar parameters read its red
+// channel, and all of them except the base colour are in [0, 1].
+//
+// The surface is a mix of lobes: a diffuse base with sheen, a GGX specular reflection, a rough
+// glass that reflects and refracts, and a clearcoat on top. `metallic` turns the base into a
+// metal that reflects its colour, `transmission` turns the dielectric part into glass with index
+// of refraction `ior`, tinted by the base colour every time light crosses it.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture + Sync + Send>,
+    pub metallic: Arc<dyn Texture + Sync + Send>,
+    pub roughness: Arc<dyn Texture + Sync + Send>,
+    // Strength of the reflection of the dielectric part, 0.5 is the 4% of most materials
+    pub specular: Arc<dyn Texture + Sync + Send>,
+    // Tints that reflection towards the base colour
+    pub specular_tint: Arc<dyn Texture + Sync + Send>,
+    // Extra reflection at grazing angles for cloth
+    pub sheen: Arc<dyn Texture + Sync + Send>,
+    // Strength and roughness of a second, colourless specular layer like varnish
+    pub clearcoat: Arc<dyn Texture + Sync + Send>,
+    pub clearcoat_roughness: Arc<dyn Texture + Sync + Send>,
+    pub transmission: Arc<dyn Texture + Sync + Send>,
+    // Light given off by the front of the surface
+    pub emission: Arc<dyn Texture + Sync + Send>,
+    pub ior: f64,
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray3, hit_rec: &HitRecord, scatter_rec: &mut ScatterRecord) -> bool {
+        let lobes = self.lobes(ray_in, hit_rec);
+        scatter_rec.attenuation = lobes.base_color;
+        scatter_rec.pdf = ScatterPDF::PDF(Box::new(lobes.pdf()));
+        true
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray3, hit_rec: &HitRecord, scattered: &Ray3) -> f64 {
+        self.lobes(ray_in, hit_rec)
+            .pdf()
+            .value(&scattered.direction())
+    }
+
+    fn scattering(
+        &self,
+        ray_in: &Ray3,
+        hit_rec: &HitRecord,
+        _attenuation: Vec3,
+        scattered: &Ray3,
+    ) -> Vec3 {
+        self.lobes(ray_in, hit_rec)
+            .value(&scattered.direction().unit_vector())
+    }
+
+    fn emitted(&self, _ray_in: &Ray3, hit_record: &HitRecord, u: f64, v: f64, point: Vec3) -> Vec3 {
+        if hit_record.front_face {
+            self.emission.value(u, v, point)
+        } else {
+            Vec3::empty()
+        }
+    }
+}
+
+impl Principled {
+    // A rough dielectric of the given colour, change the public fields for anything else
+    pub fn new(base_color: Arc<dyn Texture + Sync + Send>) -> Principled {
+        let solid = |value: f64| -> Arc<dyn Texture + Sync + Send> {
+            Arc::new(SolidTexture::new(Vec3::new(value, value, value)))
+        };
+        Principled {
+            base_color,
+            metallic: solid(0.0),
+            roughness: solid(0.5),
+            specular: solid(0.5),
+            specular_tint: solid(0.0),
+            sheen: solid(0.0),
+            clearcoat: solid(0.0),
+            clearcoat_roughness: solid(0.1),
+            transmission: solid(0.0),
+            emission: solid(0.0),
+            ior: 1.5,
+        }
+    }
+
+    pub fn from_color(color: Vec3) -> Principled {
+        Principled::new(Arc::new(SolidTexture::new(color)))
+    }
+
+    // The parameters at the hit and the weights of the lobes they make
+    fn lobes(&self, ray_in: &Ray3, hit_rec: &HitRecord) -> Lobes {
+        let color = |texture: &Arc<dyn Texture + Sync + Send>| {
+            texture.value(hit_rec.u, hit_rec.v, hit_rec.point)
+        };
+        let scalar = |texture: &Arc<dyn Texture + Sync + Send>| color(texture).x.clamp(0.0, 1.0);
+
+        let base_color = color(&self.base_color);
+        let metallic = scalar(&self.metallic);
+        let transmission = scalar(&self.transmission);
+        let tint = if luminance(base_color) > 0.0 {
+            base_color / luminance(base_color)
+        } else {
+            Vec3::new(1.0, 1.0, 1.0)
+        };
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let dielectric_specular =
+            0.08 * scalar(&self.specular) * mix(white, tint, scalar(&self.specular_tint));
+
+        let uvw = OrthonormalBasis::new(&hit_rec.normal);
+        let direction = ray_in.direction().unit_vector();
+        let wo = uvw.to_local(-direction);
+        Lobes {
+            uvw,
+            wo,
+            direction,
+            normal: hit_rec.normal,
+            base_color,
+            diffuse: (1.0 - metallic) * (1.0 - transmission),
+            roughness: scalar(&self.roughness).max(MIN_ROUGHNESS),
+            sheen: scalar(&self.sheen) * mix(white, tint, 0.5),
+            specular_color: mix(dielectric_specular, base_color, metallic),
+            specular: 1.0 - transmission * (1.0 - metallic),
+            glass: transmission * (1.0 - metallic),
+            clearcoat: 0.25 * scalar(&self.clearcoat),
+            clearcoat_roughness: scalar(&self.clearcoat_roughness).max(MIN_ROUGHNESS),
+            eta: if hit_rec.front_face {
+                self.ior
+            } else {
+                1.0 / self.ior
+            },
+        }
+    }
+}
+
+// A principled surface at one hit, seen along one ray
+struct Lobes {
+    uvw: OrthonormalBasis,
+    wo: Vec3,
+    direction: Vec3,
+    normal: Vec3,
+    base_color: Vec3,
+    // Weights of the diffuse base, the specular reflection and the glass
+    diffuse: f64,
+    specular: f64,
+    glass: f64,
+    roughness: f64,
+    sheen: Vec3,
+    // Reflectance head on of the specular lobe
+    specular_color: Vec3,
+    clearcoat: f64,
+    clearcoat_roughness: f64,
+    eta: f64,
+}
+
+impl Lobes {
+    fn specular_distribution(&self) -> GGX {
+        GGX::from_roughness(self.roughness, 0.0)
+    }
+
+    fn clearcoat_distribution(&self) -> GGX {
+        GGX::from_roughness(self.clearcoat_roughness, 0.0)
+    }
+
+    // The BSDF times the cosine towards `direction`, a unit vector
+    fn value(&self, direction: &Vec3) -> Vec3 {
+        let (wo, wi) = (self.wo, self.uvw.to_local(*direction));
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Vec3::empty();
+        }
+
+        if wi.z < 0.0 {
+            // Only the glass lets light through
+            if self.glass <= 0.0 {
+                return Vec3::empty();
+            }
+            let Some(m) = refraction_half_vector(&wo, &wi, self.eta) else {
+                return Vec3::empty();
+            };
+            let distribution = self.specular_distribution();
+            let fresnel = fresnel_dielectric(wo.dot(&m), self.eta);
+            let denominator = wo.dot(&m) + self.eta * wi.dot(&m);
+            let transmitted = -wi.dot(&m)
+                * wo.dot(&m)
+                * self.eta
+                * self.eta
+                * (1.0 - fresnel)
+                * distribution.d(&m)
+                * distribution.g2(&wo, &wi)
+                / (wo.z * denominator * denominator);
+            return self.glass * transmitted * self.base_color;
+        }
+
+        let m = (wo + wi).unit_vector();
+        let cos_d = wi.dot(&m);
+        let schlick = (1.0 - cos_d).clamp(0.0, 1.0).powi(5);
+        let mut value = Vec3::empty();
+
+        if self.diffuse > 0.0 {
+            // Burley's diffuse darkens smooth surfaces and brightens rough ones at grazing angles
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro = |cos: f64| 1.0 + (fd90 - 1.0) * (1.0 - cos).powi(5);
+            let diffuse = self.base_color * (FRAC_1_PI * retro(wi.z) * retro(wo.z) * wi.z);
+            value += self.diffuse * (diffuse + self.sheen * (schlick * wi.z));
+        }
+
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        if self.specular > 0.0 {
+            let distribution = self.specular_distribution();
+            let fresnel = mix(self.specular_color, white, schlick);
+            let microfacets = distribution.d(&m) * distribution.g2(&wo, &wi) / (4.0 * wo.z);
+            value += self.specular * microfacets * fresnel;
+        }
+        if self.glass > 0.0 {
+            let distribution = self.specular_distribution();
+            let fresnel = fresnel_dielectric(wo.dot(&m), self.eta);
+            let reflected = fresnel * distribution.d(&m) * distribution.g2(&wo, &wi) / (4.0 * wo.z);
+            value += self.glass * reflected * white;
+        }
+        if self.clearcoat > 0.0 {
+            let distribution = self.clearcoat_distribution();
+            let fresnel = 0.04 + 0.96 * schlick;
+            let microfacets = distribution.d(&m) * distribution.g2(&wo, &wi) / (4.0 * wo.z);
+            value += self.clearcoat * fresnel * microfacets * white;
+        }
+        value
+    }
+
+    // Samples one lobe at a time, each about as often as it reflects light
+    fn pdf(&self) -> LobePDF {
+        let head_on = (1.0 - self.wo.z.clamp(0.0, 1.0)).powi(5);
+        let specular = mix(self.specular_color, Vec3::new(1.0, 1.0, 1.0), head_on);
+        let weights = [
+            self.diffuse * luminance(self.base_color).max(0.01),
+            self.specular * luminance(specular),
+            self.glass,
+            self.clearcoat * (0.04 + 0.96 * head_on),
+        ];
+        let total: f64 = weights.iter().sum();
+        let mut lobes: Vec<(f64, Box<dyn PDF>)> = Vec::new();
+        if total <= 0.0 {
+            lobes.push((1.0, Box::new(CosinePDF::new(&self.normal))));
+            return LobePDF { lobes };
+        }
+        for (index, weight) in weights.into_iter().enumerate() {
+            if weight <= 0.0 {
+                continue;
+            }
+            let pdf: Box<dyn PDF> = match index {
+                0 => Box::new(CosinePDF::new(&self.normal)),
+                1 => Box::new(MicrofacetPDF::new(
+                    &self.normal,
+                    &self.direction,
+                    self.specular_distribution(),
+                )),
+                2 => Box::new(MicrofacetTransmissionPDF::new(
+                    &self.normal,
+                    &self.direction,
+                    self.eta,
+                    self.specular_distribution(),
+                )),
+                _ => Box::new(MicrofacetPDF::new(
+                    &self.normal,
+                    &self.direction,
+                    self.clearcoat_distribution(),
+                )),
+            };
+            lobes.push((weight / total, pdf));
+        }
+        LobePDF { lobes }
+    }
+}
+
+// Picks one of its PDFs with the probability it is paired with
+struct LobePDF {
+    lobes: Vec<(f64, Box<dyn PDF>)>,
+}
+
+impl PDF for LobePDF {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.lobes
+            .iter()
+            .map(|(probability, pdf)| probability * pdf.value(direction))
+            .sum()
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut choice = sample_1d();
+        for (probability, pdf) in &self.lobes {
+            if choice < *probability {
+                return pdf.generate();
+            }
+            choice -= probability;
+        }
+        self.lobes[self.lobes.len() - 1].1.generate()
+    }
+}
+
+fn mix(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ggx::sphere_grid;
+
+    // Integrates the BSDF times the cosine and the sampling density over the sphere of
+    // directions around a hit seen at 60 degrees
+    fn integrate(material: &Principled) -> (Vec3, f64) {
+        let ray_in = Ray3::new(Vec3::empty(), Vec3::new(0.5, 0.3, -0.81), 0.0);
+        let mut hit_rec = HitRecord::new();
+        hit_rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        hit_rec.front_face = true;
+
+        let mut reflected = Vec3::empty();
+        let mut density = 0.0;
+        for (direction, solid_angle) in sphere_grid(500, false) {
+            let scattered = Ray3::new(Vec3::empty(), direction, 0.0);
+            reflected +=
+                material.scattering(&ray_in, &hit_rec, Vec3::empty(), &scattered) * solid_angle;
+            density += material.scattering_pdf(&ray_in, &hit_rec, &scattered) * solid_angle;
+        }
+        (reflected, density)
+    }
+
+    #[test]
+    fn lobes_stay_in_balance() {
+        let solid = |value: f64| -> Arc<dyn Texture + Sync + Send> {
+            Arc::new(SolidTexture::new(Vec3::new(value, value, value)))
+        };
+        let plastic = Principled::from_color(Vec3::new(0.8, 0.2, 0.1));
+        let mut gold = Principled::from_color(Vec3::new(1.0, 0.78, 0.34));
+        gold.metallic = solid(1.0);
+        gold.roughness = solid(0.3);
+        let mut glass = Principled::from_color(Vec3::new(1.0, 1.0, 1.0));
+        glass.transmission = solid(1.0);
+        glass.roughness = solid(0.2);
+        let mut varnished = Principled::from_color(Vec3::new(0.3, 0.5, 0.2));
+        varnished.clearcoat = solid(1.0);
+        varnished.sheen = solid(1.0);
+
+        for material in [plastic, gold, glass, varnished] {
+            let (reflected, density) = integrate(&material);
+            // Every lobe's density integrates to one and so do their weights
+            assert!((density - 1.0).abs() < 0.03, "{}", density);
+            assert!(reflected.x < 1.05 && reflected.y < 1.05 && reflected.z < 1.05);
+            assert!(reflected.x > 0.2, "{:?}", reflected);
+        }
+    }
+
+    #[test]
+    fn emits_from_the_front() {
+        let mut lamp = Principled::from_color(Vec3::new(0.5, 0.5, 0.5));
+        lamp.emission = Arc::new(SolidTexture::new(Vec3::new(4.0, 4.0, 4.0)));
+        let ray_in = Ray3::new(Vec3::empty(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut hit_rec = HitRecord::new();
+        hit_rec.front_face = true;
+        assert_eq!(
+            lamp.emitted(&ray_in, &hit_rec, 0.0, 0.0, Vec3::empty()).x,
+            4.0
+        );
+        hit_rec.front_face = false;
+        assert_eq!(
+            lamp.emitted(&ray_in, &hit_rec, 0.0, 0.0, Vec3::empty()).x,
+            0.0
+        );
+    }
+}
@@ -4,6 +4,7 @@ use crate::hittable_list::HittableList;
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::ray::Ray3;
+use crate::sampler::sample_2d;
 use nurbs::vector_3::Vec3;
 use core::f64;
 use std::sync::Arc;
@@ -39,7 +40,10 @@ impl Hittable for Quad {
                     hit_record.time = hit_time;
                     hit_record.point = intersection;
                     hit_record.mat = Arc::clone(&self.mat);
+                    hit_record.object = HitRecord::object_id(self);
                     hit_record.set_face_normal(ray_in, self.normal);
+                    hit_record.dpdu = self.u;
+                    hit_record.dpdv = self.v;
 
                     quad_hit = true;
                 }
@@ -66,8 +70,8 @@ impl Hittable for Quad {
     }
 
     fn random(&self, origin: &Vec3) -> Vec3 {
-        let point =
-            self.origin + (self.u * rand::random::<f64>()) + (self.v * rand::random::<f64>());
+        let (r1, r2) = sample_2d();
+        let point = self.origin + (self.u * r1) + (self.v * r2);
         point - origin.clone()
     }
 }
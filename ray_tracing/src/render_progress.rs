@@ -0,0 +1,127 @@
+use crate::film::Film;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// A snapshot of a running render, handed to the progress callback of
+// `Camera::render_with_progress` every time a tile finishes
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderProgress {
+    // Pass number, counting from 1. Renders take a single pass unless they are progressive
+    // or use adaptive sampling.
+    pub pass: u32,
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+    // Camera samples taken so far and the budget of samples_per_pixel for every pixel. An
+    // adaptive render may stop before it has spent the whole budget.
+    pub samples_done: u64,
+    pub samples_total: u64,
+    // Rays traced so far, counting every bounce
+    pub rays: u64,
+    pub elapsed: Duration,
+}
+
+impl RenderProgress {
+    // Share of the sample budget that has been taken, in [0, 1]
+    pub fn fraction(&self) -> f64 {
+        if self.samples_total == 0 {
+            1.0
+        } else {
+            (self.samples_done as f64 / self.samples_total as f64).min(1.0)
+        }
+    }
+
+    // Time left if the rest of the budget renders as fast as what has been taken so far
+    pub fn eta(&self) -> Option<Duration> {
+        if self.samples_done == 0 {
+            return None;
+        }
+        let left = self.samples_total.saturating_sub(self.samples_done);
+        Some(self.elapsed.mul_f64(left as f64 / self.samples_done as f64))
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.rays as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+// Stops a render from another thread. Clones share the same flag, so one can be handed to the
+// render and the other kept to cancel it. The workers check it before every pixel.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderStatus {
+    // The render ran to completion, it was not cancelled
+    Complete,
+    // The render was cancelled, or a progressive render's callback asked it to stop, the film
+    // holds the samples taken until then
+    Cancelled,
+}
+
+// What `Camera::render_with_progress` returns: the film, which is partial when the render was
+// cancelled, how it ended and the final progress
+pub struct RenderOutcome {
+    pub film: Film,
+    pub status: RenderStatus,
+    pub progress: RenderProgress,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_from_progress() {
+        let progress = RenderProgress {
+            pass: 1,
+            tiles_done: 10,
+            tiles_total: 40,
+            samples_done: 250,
+            samples_total: 1000,
+            rays: 3000,
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(progress.fraction(), 0.25);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(6)));
+        assert_eq!(progress.rays_per_second(), 1500.0);
+
+        let starting = RenderProgress {
+            samples_done: 0,
+            elapsed: Duration::ZERO,
+            ..progress
+        };
+        assert_eq!(starting.eta(), None);
+        assert_eq!(starting.rays_per_second(), 0.0);
+    }
+
+    #[test]
+    fn clones_share_cancellation() {
+        let token = CancelToken::new();
+        let handed_out = token.clone();
+        assert!(!handed_out.is_cancelled());
+        token.cancel();
+        assert!(handed_out.is_cancelled());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::dielectric::{Absorption, Dielectric};
+use crate::ggx::{refraction_half_vector, GGX};
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterPDF, ScatterRecord};
+use crate::microfacet_pdf::MicrofacetTransmissionPDF;
+use crate::orthonormal_basis::OrthonormalBasis;
+use crate::pdf::PDF;
+use crate::ray::Ray3;
+use nurbs::vector_3::Vec3;
+
+// Frosted glass: a dielectric with a rough GGX microfacet surface that both reflects and
+// transmits (Walter et al. 2007), optionally absorbing the light that travels through it. Like
+// `Dielectric` it leaves out the change in radiance when light moves between the media, which
+// cancels out for light that leaves closed objects again.
+pub struct RoughDielectric {
+    ir: f64,
+    distribution: GGX,
+    absorption: Option<Absorption>,
+    // What the surface is when it is too smooth for the microfacets
+    smooth: Dielectric,
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray3, hit_rec: &HitRecord, scatter_rec: &mut ScatterRecord) -> bool {
+        if self.distribution.is_smooth() {
+            return self.smooth.scatter(ray_in, hit_rec, scatter_rec);
+        }
+        scatter_rec.attenuation = match &self.absorption {
+            Some(absorption) => absorption.along(ray_in, hit_rec),
+            None => Vec3::new(1.0, 1.0, 1.0),
+        };
+        scatter_rec.pdf = ScatterPDF::PDF(Box::new(MicrofacetTransmissionPDF::new(
+            &hit_rec.normal,
+            &ray_in.direction(),
+            self.eta(hit_rec),
+            self.distribution,
+        )));
+        true
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray3, hit_rec: &HitRecord, scattered: &Ray3) -> f64 {
+        MicrofacetTransmissionPDF::new(
+            &hit_rec.normal,
+            &ray_in.direction(),
+            self.eta(hit_rec),
+            self.distribution,
+        )
+        .value(&scattered.direction())
+    }
+
+    fn scattering(
+        &self,
+        ray_in: &Ray3,
+        hit_rec: &HitRecord,
+        attenuation: Vec3,
+        scattered: &Ray3,
+    ) -> Vec3 {
+        let uvw = OrthonormalBasis::new(&hit_rec.normal);
+        let wo = uvw.to_local(-ray_in.direction().unit_vector());
+        let wi = uvw.to_local(scattered.direction().unit_vector());
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Vec3::empty();
+        }
+        let eta = self.eta(hit_rec);
+        let distribution = &self.distribution;
+        let value = if wi.z > 0.0 {
+            // F D G / (4 cos_o cos_i) times cos_i
+            let m = (wo + wi).unit_vector();
+            let fresnel = fresnel_dielectric(wo.dot(&m), eta);
+            fresnel * distribution.d(&m) * distribution.g2(&wo, &wi) / (4.0 * wo.z)
+        } else {
+            match refraction_half_vector(&wo, &wi, eta) {
+                // |wi.m| |wo.m| eta^2 (1 - F) D G / (cos_o cos_i (wo.m + eta wi.m)^2) times cos_i
+                Some(m) => {
+                    let fresnel = fresnel_dielectric(wo.dot(&m), eta);
+                    let denominator = wo.dot(&m) + eta * wi.dot(&m);
+                    -wi.dot(&m)
+                        * wo.dot(&m)
+                        * eta
+                        * eta
+                        * (1.0 - fresnel)
+                        * distribution.d(&m)
+                        * distribution.g2(&wo, &wi)
+                        / (wo.z * denominator * denominator)
+                }
+                None => 0.0,
+            }
+        };
+        attenuation * value
+    }
+}
+
+impl RoughDielectric {
+    // `roughness` in [0, 1], 0 is polished glass
+    pub fn new(ir: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric {
+            ir,
+            distribution: GGX::from_roughness(roughness, 0.0),
+            absorption: None,
+            smooth: Dielectric::new(ir),
+        }
+    }
+
+    // Keeps `color` of the light for every `distance` it travels inside
+    pub fn new_absorbing(ir: f64, roughness: f64, color: Vec3, distance: f64) -> RoughDielectric {
+        RoughDielectric {
+            ir,
+            distribution: GGX::from_roughness(roughness, 0.0),
+            absorption: Some(Absorption::new(color, distance)),
+            smooth: Dielectric::new_absorbing(ir, color, distance),
+        }
+    }
+
+    // Index of refraction beyond the surface over the one in front of it
+    fn eta(&self, hit_rec: &HitRecord) -> f64 {
+        if hit_rec.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        }
+    }
+}
+
+// Unpolarised Fresnel reflectance of light arriving at `cos_theta` to the normal of an
+// interface, `eta` being the index of refraction beyond it over the one in front of it. 1 for
+// total internal reflection.
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_theta * cos_theta) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_theta - cos_t) / (eta * cos_theta + cos_t);
+    let perpendicular = (cos_theta - eta * cos_t) / (cos_theta + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ggx::sphere_grid;
+
+    #[test]
+    fn fresnel_of_glass() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.0, 1.5), 1.0);
+        // Past the critical angle of about 42 degrees inside glass
+        assert_eq!(fresnel_dielectric(0.7, 1.0 / 1.5), 1.0);
+        assert!(fresnel_dielectric(0.8, 1.0 / 1.5) < 1.0);
+    }
+
+    #[test]
+    fn conserves_energy() {
+        // Everything that arrives is either reflected or transmitted, less what bounces
+        // between the microfacets. The sampling density integrates to one over the sphere.
+        let ray_in = Ray3::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.5, 0.0, -0.866), 0.0);
+        for front_face in [true, false] {
+            let mut hit_rec = HitRecord::new();
+            hit_rec.normal = Vec3::new(0.0, 0.0, 1.0);
+            hit_rec.front_face = front_face;
+            let glass = RoughDielectric::new(1.5, 0.3);
+            let pdf = MicrofacetTransmissionPDF::new(
+                &hit_rec.normal,
+                &ray_in.direction(),
+                glass.eta(&hit_rec),
+                glass.distribution,
+            );
+
+            let white = Vec3::new(1.0, 1.0, 1.0);
+            let mut total_pdf = 0.0;
+            let mut albedo = 0.0;
+            for (direction, solid_angle) in sphere_grid(600, false) {
+                total_pdf += pdf.value(&direction) * solid_angle;
+                let scattered = Ray3::new(hit_rec.point, direction, 0.0);
+                albedo += glass.scattering(&ray_in, &hit_rec, white, &scattered).x * solid_angle;
+            }
+            assert!((total_pdf - 1.0).abs() < 0.02, "{}", total_pdf);
+            assert!(albedo < 1.0 && albedo > 0.9, "{} {}", front_face, albedo);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::halton_sampler::HaltonSampler;
+use crate::independent_sampler::IndependentSampler;
+use crate::sobol_sampler::SobolSampler;
+use crate::stratified_sampler::StratifiedSampler;
+use nurbs::random::random_f64;
+use std::cell::RefCell;
+
+// Source of the numbers that place a camera sample: the position in the pixel, the point on
+// the lens, the time and the choices made at every bounce. Every sample of a pixel is a point
+// in a unit hypercube and the samples of a pixel are drawn together, so they can cover it more
+// evenly than independent random numbers.
+//
+// Consumers take one or two dimensions at a time, always in the same order, so dimension n of
+// every sample is used for the same decision.
+pub trait Sampler {
+    // Starts the samples of pixel number `pixel`
+    fn start_pixel(&mut self, pixel: u64);
+    // Starts sample `index` of the current pixel at the first dimension
+    fn start_sample(&mut self, index: u32);
+    // Skips ahead to `dimension`, or does nothing if the sample is already past it
+    fn advance_to(&mut self, dimension: u32);
+    fn next_1d(&mut self) -> f64;
+    fn next_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+    // Independent uniform random numbers
+    Independent,
+    // One jittered sample per cell of a sqrt(spp) by sqrt(spp) grid
+    Stratified,
+    // The Halton sequence, rotated differently in every pixel
+    Halton,
+    // Owen-scrambled Sobol points, shuffled independently for every pair of dimensions
+    Sobol,
+}
+
+impl SamplerKind {
+    // Looks a sampler up by the name used in scene files and on the command line
+    pub fn from_name(name: &str) -> Option<SamplerKind> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+
+    // A sampler for `samples_per_pixel` samples, scrambled differently for every seed
+    pub fn create(&self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler {}),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Box<dyn Sampler>>> = const { RefCell::new(None) };
+}
+
+// Makes `sampler` the source of `sample_1d` and `sample_2d` on the calling thread. Without
+// one they fall back to the thread's random number generator.
+pub fn install(sampler: Option<Box<dyn Sampler>>) {
+    ACTIVE.with(|active| *active.borrow_mut() = sampler);
+}
+
+pub fn start_pixel(pixel: u64) {
+    with_active(|sampler| sampler.start_pixel(pixel));
+}
+
+pub fn start_sample(index: u32) {
+    with_active(|sampler| sampler.start_sample(index));
+}
+
+pub fn advance_to(dimension: u32) {
+    with_active(|sampler| sampler.advance_to(dimension));
+}
+
+// The next dimension of the current sample, in [0, 1)
+pub fn sample_1d() -> f64 {
+    with_active(|sampler| sampler.next_1d()).unwrap_or_else(random_f64)
+}
+
+// The next two dimensions of the current sample
+pub fn sample_2d() -> (f64, f64) {
+    with_active(|sampler| sampler.next_2d()).unwrap_or_else(|| (random_f64(), random_f64()))
+}
+
+fn with_active<T>(f: impl FnOnce(&mut dyn Sampler) -> T) -> Option<T> {
+    ACTIVE.with(|active| {
+        let mut active = active.borrow_mut();
+        let sampler: &mut dyn Sampler = active.as_mut()?.as_mut();
+        Some(f(sampler))
+    })
+}
+
+// Hashes two values into one, used to derive the scrambling of a pixel and dimension
+pub(crate) fn mix(a: u64, b: u64) -> u64 {
+    let mut z = (a ^ b.wrapping_mul(0x9E37_79B9_7F4A_7C15)).wrapping_add(0x632B_E59B_D9B4_E019);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Reads 32 bits as a binary fraction in [0, 1)
+pub(crate) fn to_unit(bits: u32) -> f64 {
+    bits as f64 * (1.0 / 4294967296.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    // The first `count` samples of `dimension` in `pixel`
+    fn samples_2d(
+        sampler: &mut dyn Sampler,
+        pixel: u64,
+        dimension: u32,
+        count: u32,
+    ) -> Vec<(f64, f64)> {
+        sampler.start_pixel(pixel);
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(index);
+                sampler.advance_to(dimension);
+                sampler.next_2d()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn samples_in_range_and_reproducible() {
+        for kind in KINDS {
+            let mut sampler = kind.create(3, 16);
+            for dimension in [0, 1, 7, 40, 62] {
+                let first = samples_2d(sampler.as_mut(), 5, dimension, 16);
+                assert!(first
+                    .iter()
+                    .all(|&(x, y)| (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)));
+                if kind != SamplerKind::Independent {
+                    assert_eq!(first, samples_2d(sampler.as_mut(), 5, dimension, 16));
+                    assert_ne!(first, samples_2d(sampler.as_mut(), 6, dimension, 16));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_fills_every_cell() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut sampler = kind.create(1, 16);
+            for pixel in 0..8 {
+                for dimension in [0, 2, 9] {
+                    let mut cells = [0; 16];
+                    for (x, y) in samples_2d(sampler.as_mut(), pixel, dimension, 16) {
+                        cells[(x * 4.0) as usize * 4 + (y * 4.0) as usize] += 1;
+                    }
+                    assert!(cells.iter().all(|&count| count == 1), "{:?}", kind);
+                }
+            }
+        }
+
+        // Sobol points are (0, 2)-nets, every 1/16 wide strip holds exactly one point
+        let mut sampler = SamplerKind::Sobol.create(1, 16);
+        let points = samples_2d(sampler.as_mut(), 3, 4, 16);
+        for strip in 0..16 {
+            let inside = |value: f64| (value * 16.0) as usize == strip;
+            assert_eq!(points.iter().filter(|(x, _)| inside(*x)).count(), 1);
+            assert_eq!(points.iter().filter(|(_, y)| inside(*y)).count(), 1);
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_converges_faster() {
+        // Mean squared error of a 16 sample estimate of a smooth integral over many pixels
+        let f = |x: f64, y: f64| (x * y * 3.0).sin() + x * x;
+        let exact = 0.852_066_06;
+        let error = |kind: SamplerKind| {
+            let mut sampler = kind.create(9, 16);
+            let mut sum = 0.0;
+            for pixel in 0..256 {
+                let points = samples_2d(sampler.as_mut(), pixel, 0, 16);
+                let estimate = points.iter().map(|&(x, y)| f(x, y)).sum::<f64>() / 16.0;
+                sum += (estimate - exact) * (estimate - exact);
+            }
+            sum / 256.0
+        };
+        let independent = error(SamplerKind::Independent);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let kind_error = error(kind);
+            assert!(
+                kind_error * 4.0 < independent,
+                "{:?} {} {}",
+                kind,
+                kind_error,
+                independent
+            );
+        }
+    }
+
+    #[test]
+    fn falls_back_without_a_sampler() {
+        install(Some(SamplerKind::Sobol.create(0, 4)));
+        start_pixel(0);
+        start_sample(0);
+        let first = sample_2d();
+        start_sample(0);
+        assert_eq!(first, sample_2d());
+        install(None);
+        assert!((0.0..1.0).contains(&sample_1d()));
+    }
+}
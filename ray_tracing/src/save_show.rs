@@ -1,97 +1,733 @@
-use image::RgbImage;
-use softbuffer;
-use std::num::NonZeroU32;
-use std::path::Path;
-use std::rc::Rc;
-use winit::dpi::{PhysicalSize, Size};
-use winit::event::{Event, WindowEvent};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
-
-// Code modified from the example of https://github.com/rust-windowing/softbuffer/blob/master/README.md
-pub fn render_buffer(buffer: Vec<u32>, buffer_width: u32, buffer_height: u32) {
-    let event_loop = EventLoop::new().unwrap();
-    let window_size = Size::Physical(PhysicalSize {
-        width: buffer_width,
-        height: buffer_height,
+use crate::camera::Camera;
+use crate::color::DisplayTransform;
+use crate::film::Film;
+use flate2::write::ZlibEncoder;
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::codecs::tga::TgaEncoder;
+use image::error::{EncodingError, ImageFormatHint};
+use image::{ColorType, ImageEncoder, ImageError, ImageFormat, ImageResult};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Showing images needs the `window` feature
+#[cfg(feature = "window")]
+pub use crate::preview_window::{render_buffer, show_progressive};
+
+// Where renders go when no output is given
+pub const DEFAULT_OUTPUT_TEMPLATE: &str = "renders/{name}_w{width}_s{samples}_d{depth}.png";
+
+// What was rendered and how, for output path templates and the text chunks of PNG files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderInfo {
+    pub scene: String,
+    pub width: u32,
+    pub height: u32,
+    pub samples: i32,
+    pub depth: i32,
+    pub seed: u64,
+    pub render_time: Duration,
+}
+
+impl RenderInfo {
+    pub fn new(scene: &str, camera: &Camera, render_time: Duration) -> RenderInfo {
+        let (width, height) = camera.output_size();
+        RenderInfo {
+            scene: scene.to_owned(),
+            width,
+            height,
+            samples: camera.samples_per_pixel,
+            depth: camera.max_depth,
+            seed: camera.seed,
+            render_time,
+        }
+    }
+
+    // Keyword and text pairs for PNG tEXt chunks
+    pub fn text_chunks(&self) -> Vec<(String, String)> {
+        [
+            ("Title", self.scene.clone()),
+            ("Software", "rust-ray-tracer".to_owned()),
+            ("Samples per pixel", self.samples.to_string()),
+            ("Max depth", self.depth.to_string()),
+            ("Seed", self.seed.to_string()),
+            (
+                "Render time",
+                format!("{:.3} s", self.render_time.as_secs_f64()),
+            ),
+        ]
+        .into_iter()
+        .map(|(keyword, text)| (keyword.to_owned(), text))
+        .collect()
+    }
+}
+
+// Fills in an output path template. {name}, {width}, {height}, {samples}, {depth}, {seed} and
+// {timestamp}, the seconds since the Unix epoch, are replaced by the render's values.
+pub fn output_path(template: &str, info: &RenderInfo) -> Result<PathBuf, String> {
+    let mut path = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        path.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in '{}'", template))?;
+        let value = match &rest[open + 1..open + close] {
+            "name" => info.scene.clone(),
+            "width" => info.width.to_string(),
+            "height" => info.height.to_string(),
+            "samples" => info.samples.to_string(),
+            "depth" => info.depth.to_string(),
+            "seed" => info.seed.to_string(),
+            "timestamp" => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string(),
+            other => return Err(format!("unknown placeholder '{{{}}}'", other)),
+        };
+        path.push_str(&value);
+        rest = &rest[open + close + 1..];
+    }
+    path.push_str(rest);
+    Ok(PathBuf::from(path))
+}
+
+// Saves the buffer to the default path under renders/ and returns that path
+pub fn save_image(
+    name: &str,
+    buffer: &[u32],
+    width: u32,
+    height: u32,
+    samples: i32,
+    depth: i32,
+) -> ImageResult<PathBuf> {
+    let full_path = default_image_path(name, width, samples, depth);
+    create_parent(&full_path)?;
+    save_image_to(&full_path, buffer, width, height)?;
+    Ok(full_path)
+}
+
+// The path used when no output is given: renders/<name>_w<width>_s<samples>_d<depth>.png
+pub fn default_image_path(name: &str, width: u32, samples: i32, depth: i32) -> PathBuf {
+    let info = RenderInfo {
+        scene: name.to_owned(),
+        width,
+        samples,
+        depth,
+        ..RenderInfo::default()
+    };
+    output_path(DEFAULT_OUTPUT_TEMPLATE, &info).unwrap()
+}
+
+// Saves a buffer of 0xFFRRGGBB pixels to `path` with the default settings, the image format
+// is picked from the file extension
+pub fn save_image_to(path: &Path, buffer: &[u32], width: u32, height: u32) -> ImageResult<()> {
+    let rgb: Vec<u8> = buffer
+        .iter()
+        .flat_map(|&value| [(value >> 16) as u8, (value >> 8) as u8, value as u8])
+        .collect();
+    save_ldr(path, &rgb, width, height, &SaveOptions::new())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExrCompression {
+    None,
+    // Deflate over blocks of 16 scanlines
+    Zip,
+}
+
+// How images are written
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveOptions {
+    pub exr_compression: ExrCompression,
+    // 16 instead of 8 bits per channel in PNG files, other formats don't have a choice
+    pub png_16_bit: bool,
+    // 1 to 100
+    pub jpeg_quality: u8,
+    // Written into PNG files as text chunks when set
+    pub info: Option<RenderInfo>,
+}
+
+impl SaveOptions {
+    pub fn new() -> SaveOptions {
+        SaveOptions {
+            exr_compression: ExrCompression::Zip,
+            png_16_bit: false,
+            jpeg_quality: 90,
+            info: None,
+        }
+    }
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        SaveOptions::new()
+    }
+}
+
+// Saves the linear film to `path`, creating its directory if needed. `.hdr`, `.pfm` and `.exr`
+// keep the float data. `.png`, `.jpg`, `.bmp`, `.tga` and `.ppm` go through the output stage
+// with `transform`, PNG at 8 or 16 bits.
+pub fn save_film(
+    path: &Path,
+    film: &Film,
+    transform: &DisplayTransform,
+    options: &SaveOptions,
+) -> ImageResult<()> {
+    if !is_output_path(path) {
+        return Err(unsupported(path));
+    }
+    create_parent(path)?;
+    let write = |write_film: &dyn Fn(&mut BufWriter<File>) -> io::Result<()>| {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_film(&mut writer)?;
+        writer.flush()
+    };
+    match extension(path).as_deref() {
+        Some("hdr") => write(&|writer| write_hdr(writer, film))?,
+        Some("pfm") => write(&|writer| write_pfm(writer, film))?,
+        Some("exr") => write(&|writer| write_exr(writer, film, options.exr_compression))?,
+        Some("png") if options.png_16_bit => {
+            let samples = film.to_rgb16(transform);
+            let bytes: Vec<u8> = samples
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect();
+            write_png(path, &bytes, film.width(), film.height(), true, options)?
+        }
+        _ => {
+            let rgb: Vec<u8> = film
+                .to_argb(transform)
+                .iter()
+                .flat_map(|&value| [(value >> 16) as u8, (value >> 8) as u8, value as u8])
+                .collect();
+            save_ldr(path, &rgb, film.width(), film.height(), options)?
+        }
+    }
+    Ok(())
+}
+
+// The file extensions `save_film` can write
+pub const OUTPUT_EXTENSIONS: [&str; 9] = [
+    "png", "jpg", "jpeg", "bmp", "tga", "ppm", "hdr", "pfm", "exr",
+];
+
+// Whether `save_film` can write to `path`, going by its extension
+pub fn is_output_path(path: &Path) -> bool {
+    extension(path).is_some_and(|extension| OUTPUT_EXTENSIONS.contains(&extension.as_str()))
+}
+
+fn unsupported(path: &Path) -> ImageError {
+    let hint = match path.extension() {
+        Some(extension) => ImageFormatHint::PathExtension(PathBuf::from(extension)),
+        None => ImageFormatHint::Unknown,
+    };
+    ImageError::Unsupported(hint.into())
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => std::fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+// Writes 8-bit RGB in the format of the file extension
+fn save_ldr(
+    path: &Path,
+    rgb: &[u8],
+    width: u32,
+    height: u32,
+    options: &SaveOptions,
+) -> ImageResult<()> {
+    let color = ColorType::Rgb8;
+    // Only called for known formats, so nothing is created for the others
+    let write = |encode: &dyn Fn(&mut BufWriter<File>) -> ImageResult<()>| -> ImageResult<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        encode(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    };
+    match extension(path).as_deref() {
+        Some("png") => write_png(path, rgb, width, height, false, options),
+        Some("jpg" | "jpeg") => write(&|writer| {
+            JpegEncoder::new_with_quality(writer, options.jpeg_quality)
+                .write_image(rgb, width, height, color)
+        }),
+        Some("bmp") => {
+            write(&|writer| BmpEncoder::new(writer).write_image(rgb, width, height, color))
+        }
+        Some("tga") => {
+            write(&|writer| TgaEncoder::new(writer).write_image(rgb, width, height, color))
+        }
+        Some("ppm") => write(&|writer| {
+            PnmEncoder::new(writer)
+                .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                .write_image(rgb, width, height, color)
+        }),
+        _ => Err(unsupported(path)),
+    }
+}
+
+// PNG through the png crate, which unlike the image crate can add text chunks
+fn write_png(
+    path: &Path,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    sixteen_bit: bool,
+    options: &SaveOptions,
+) -> ImageResult<()> {
+    let encoding_error = |error: png::EncodingError| match error {
+        png::EncodingError::IoError(error) => ImageError::IoError(error),
+        error => ImageError::Encoding(EncodingError::new(ImageFormat::Png.into(), error)),
+    };
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(if sixteen_bit {
+        png::BitDepth::Sixteen
+    } else {
+        png::BitDepth::Eight
     });
-    let window = Rc::new(
-        WindowBuilder::new()
-            .with_inner_size(window_size)
-            .build(&event_loop)
-            .unwrap(),
+    encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+    if let Some(info) = &options.info {
+        for (keyword, text) in info.text_chunks() {
+            encoder
+                .add_text_chunk(keyword, text)
+                .map_err(encoding_error)?;
+        }
+    }
+    let mut png_writer = encoder.write_header().map_err(encoding_error)?;
+    png_writer.write_image_data(data).map_err(encoding_error)?;
+    png_writer.finish().map_err(encoding_error)
+}
+
+// Radiance RGBE, with run length encoded scanlines where the format allows it
+pub fn write_hdr<W: Write>(writer: &mut W, film: &Film) -> io::Result<()> {
+    let width = film.width() as usize;
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        film.height(),
+        width
+    )?;
+
+    let mut channels: [Vec<u8>; 4] = Default::default();
+    for row in film.pixels().chunks(width) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(|&pixel| to_rgbe(pixel)).collect();
+        if !(8..=0x7fff).contains(&width) {
+            for pixel in rgbe {
+                writer.write_all(&pixel)?;
+            }
+            continue;
+        }
+
+        writer.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+        for (index, channel) in channels.iter_mut().enumerate() {
+            channel.clear();
+            channel.extend(rgbe.iter().map(|pixel| pixel[index]));
+            write_hdr_rle(writer, channel)?;
+        }
+    }
+    Ok(())
+}
+
+fn to_rgbe([r, g, b]: [f32; 3]) -> [u8; 4] {
+    let v = r.max(g).max(b);
+    if v <= 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // v = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let mut exponent = v.log2().floor() as i32 + 1;
+    let mut mantissa = v / 2f32.powi(exponent);
+    if mantissa >= 1.0 {
+        mantissa *= 0.5;
+        exponent += 1;
+    } else if mantissa < 0.5 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+    let scale = mantissa * 256.0 / v;
+    let channel = |value: f32| (value.max(0.0) * scale) as u8;
+    [channel(r), channel(g), channel(b), (exponent + 128) as u8]
+}
+
+// Runs of at least 3 equal bytes are stored as (128 + length, value), everything else as
+// (length, bytes...), with at most 127 bytes per packet
+fn write_hdr_rle<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    let mut index = 0;
+    while index < data.len() {
+        let mut run = 1;
+        while index + run < data.len() && run < 127 && data[index + run] == data[index] {
+            run += 1;
+        }
+        if run >= 3 {
+            writer.write_all(&[128 + run as u8, data[index]])?;
+            index += run;
+            continue;
+        }
+
+        // Literal bytes up to the start of the next run
+        let start = index;
+        while index < data.len() && index - start < 127 {
+            if index + 2 < data.len()
+                && data[index] == data[index + 1]
+                && data[index] == data[index + 2]
+            {
+                break;
+            }
+            index += 1;
+        }
+        writer.write_all(&[(index - start) as u8])?;
+        writer.write_all(&data[start..index])?;
+    }
+    Ok(())
+}
+
+// Portable float map, little endian with the rows stored bottom to top
+pub fn write_pfm<W: Write>(writer: &mut W, film: &Film) -> io::Result<()> {
+    write!(writer, "PF\n{} {}\n-1.0\n", film.width(), film.height())?;
+    for row in film.pixels().chunks(film.width() as usize).rev() {
+        for pixel in row {
+            for value in pixel {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Single part scanline OpenEXR with 32-bit float R, G and B channels
+pub fn write_exr<W: Write>(
+    writer: &mut W,
+    film: &Film,
+    compression: ExrCompression,
+) -> io::Result<()> {
+    let width = film.width() as usize;
+    let height = film.height() as usize;
+    let lines_per_block = match compression {
+        ExrCompression::None => 1,
+        ExrCompression::Zip => 16,
+    };
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&20000630u32.to_le_bytes());
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    // Channels are listed alphabetically: name, pixel type (2 = float), linear, reserved,
+    // x and y sampling
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    exr_attribute(&mut header, "channels", "chlist", &channels);
+
+    let compression_id = match compression {
+        ExrCompression::None => 0u8,
+        ExrCompression::Zip => 3u8,
+    };
+    exr_attribute(&mut header, "compression", "compression", &[compression_id]);
+
+    let mut window = Vec::new();
+    for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
     );
-    let context = unsafe { softbuffer::Context::new(&window) }.unwrap();
-    let mut surface = unsafe { softbuffer::Surface::new(&context, &window) }.unwrap();
-
-    event_loop
-        .run(move |event, elwt| {
-            elwt.set_control_flow(ControlFlow::Wait);
-
-            match event {
-                Event::WindowEvent {
-                    window_id,
-                    event: WindowEvent::RedrawRequested,
-                } if window_id == window.id() => {
-                    let (width, height) = {
-                        let size = window.inner_size();
-                        (size.width, size.height)
-                    };
-                    surface
-                        .resize(
-                            NonZeroU32::new(width).unwrap(),
-                            NonZeroU32::new(height).unwrap(),
-                        )
-                        .unwrap();
-
-                    let mut render_buffer = surface.buffer_mut().unwrap();
-                    for index in 0..(width * height) {
-                        render_buffer[index as usize] = buffer[index as usize];
-                    }
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
 
-                    render_buffer.present().unwrap();
-                }
-                Event::WindowEvent {
-                    event: WindowEvent::CloseRequested,
-                    window_id,
-                } if window_id == window.id() => {
-                    elwt.exit();
+    // Each block holds its scanlines one after the other, with every channel of a scanline
+    // stored contiguously
+    let blocks: Vec<Vec<u8>> = film
+        .pixels()
+        .chunks(width * lines_per_block)
+        .map(|block| {
+            let mut data = Vec::with_capacity(block.len() * 12);
+            for row in block.chunks(width) {
+                for channel in [2, 1, 0] {
+                    for pixel in row {
+                        data.extend_from_slice(&pixel[channel].to_le_bytes());
+                    }
                 }
-                _ => {}
+            }
+            match compression {
+                ExrCompression::None => data,
+                ExrCompression::Zip => exr_zip(data),
             }
         })
-        .unwrap();
-}
-
-pub fn save_image(name: &str, buffer: &Vec<u32>, width: u32, height: u32, samples: i32, depth: i32) {
-    // Load the buffer into an image
-    let image = RgbImage::from_fn(width, height, |x, y| {
-        let index = x + (y * width);
-        let value = buffer[index as usize];
-        let red = ((value >> 16) & 0x000000FF) as u8;
-        let green = ((value >> 8) & 0x000000FF) as u8;
-        let blue = (value & 0x000000FF) as u8;
-        image::Rgb([red, green, blue])
-    });
+        .collect();
+
+    writer.write_all(&header)?;
+    let mut offset = (header.len() + 8 * blocks.len()) as u64;
+    for block in &blocks {
+        writer.write_all(&offset.to_le_bytes())?;
+        offset += 8 + block.len() as u64;
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        writer.write_all(&((index * lines_per_block) as i32).to_le_bytes())?;
+        writer.write_all(&(block.len() as i32).to_le_bytes())?;
+        writer.write_all(block)?;
+    }
+    Ok(())
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// EXR's ZIP compression: the bytes are split into even and odd halves, delta encoded and then
+// zlib compressed. Blocks that don't shrink are stored as is.
+fn exr_zip(data: Vec<u8>) -> Vec<u8> {
+    let half = data.len().div_ceil(2);
+    let mut split = vec![0u8; data.len()];
+    for (index, &byte) in data.iter().enumerate() {
+        let target = if index % 2 == 0 {
+            index / 2
+        } else {
+            half + index / 2
+        };
+        split[target] = byte;
+    }
+    let mut previous = split.first().copied().unwrap_or(0);
+    for byte in split.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    let compressed = encoder
+        .write_all(&split)
+        .and_then(|_| encoder.finish())
+        .unwrap_or_default();
+    if !compressed.is_empty() && compressed.len() < data.len() {
+        compressed
+    } else {
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nurbs::vector_3::Vec3;
 
-    let _ = std::fs::create_dir("renders");  
+    fn gradient_film() -> Film {
+        let mut film = Film::new(37, 21);
+        for y in 0..21 {
+            for x in 0..37 {
+                let color = Vec3::new(
+                    x as f64 * 0.25,
+                    y as f64 * 3.0,
+                    if x < 20 { 0.5 } else { 12.0 },
+                );
+                film.set_pixel(x, y, color);
+            }
+        }
+        film
+    }
+
+    fn decode(bytes: Vec<u8>, format: image::ImageFormat) -> image::Rgb32FImage {
+        image::load_from_memory_with_format(&bytes, format)
+            .unwrap()
+            .to_rgb32f()
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        let film = gradient_film();
+        let mut bytes = Vec::new();
+        write_hdr(&mut bytes, &film).unwrap();
+        // The generic loader maps HDR to 8-bit, so read the floats directly
+        let decoder = image::codecs::hdr::HdrDecoder::new(bytes.as_slice()).unwrap();
+        let pixels = decoder.read_image_hdr().unwrap();
+        assert_eq!(pixels.len(), 37 * 21);
+        for (index, pixel) in pixels.iter().enumerate() {
+            let expected = film.pixel(index as u32 % 37, index as u32 / 37);
+            // RGBE keeps 8 bits of mantissa relative to the brightest channel
+            let tolerance = expected.x.max(expected.y).max(expected.z) as f32 / 64.0;
+            assert!((pixel[0] - expected.x as f32).abs() <= tolerance);
+            assert!((pixel[1] - expected.y as f32).abs() <= tolerance);
+            assert!((pixel[2] - expected.z as f32).abs() <= tolerance);
+        }
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let film = gradient_film();
+        for compression in [ExrCompression::None, ExrCompression::Zip] {
+            let mut bytes = Vec::new();
+            write_exr(&mut bytes, &film, compression).unwrap();
+            let image = decode(bytes, image::ImageFormat::OpenExr);
+            assert_eq!(image.dimensions(), (37, 21));
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let expected = film.pixel(x, y);
+                assert_eq!(
+                    pixel.0,
+                    [expected.x as f32, expected.y as f32, expected.z as f32]
+                );
+            }
+        }
+    }
 
-    let mut full_path = "renders/".to_owned();
-    let path_end = ".png";
+    #[test]
+    fn pfm_layout() {
+        let film = gradient_film();
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, &film).unwrap();
+        let header = b"PF\n37 21\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 37 * 21 * 12);
+        // The first stored pixel is the bottom left one
+        let green = f32::from_le_bytes(
+            bytes[header.len() + 4..header.len() + 8]
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(green, 60.0);
+    }
 
-    // Add the file name
-    full_path.push_str(name);
+    #[test]
+    fn output_path_templates() {
+        let info = RenderInfo {
+            scene: "Quads".to_owned(),
+            width: 400,
+            height: 225,
+            samples: 32,
+            depth: 8,
+            seed: 5,
+            render_time: Duration::from_secs(3),
+        };
+        assert_eq!(
+            output_path(
+                "out/{name}/{width}x{height}_{samples}_{depth}_{seed}.jpg",
+                &info
+            ),
+            Ok(PathBuf::from("out/Quads/400x225_32_8_5.jpg"))
+        );
+        assert_eq!(
+            default_image_path("Quads", 400, 32, 8),
+            PathBuf::from("renders/Quads_w400_s32_d8.png")
+        );
+        assert!(
+            output_path("{timestamp}.png", &info)
+                .unwrap()
+                .to_string_lossy()
+                .len()
+                > 4
+        );
+        assert!(output_path("{scene}.png", &info).is_err());
+        assert!(output_path("{name.png", &info).is_err());
+    }
 
-    // width, samples, depth
-    full_path.push_str("_w");
-    full_path.push_str(width.to_string().as_str());
-    full_path.push_str("_s");
-    full_path.push_str(samples.to_string().as_str());
-    full_path.push_str("_d");
-    full_path.push_str(depth.to_string().as_str());
+    #[test]
+    fn ldr_formats() {
+        let directory =
+            std::env::temp_dir().join(format!("ray_tracing_ldr_{}", std::process::id()));
+        let film = gradient_film();
+        let transform = DisplayTransform::clamp();
+        let options = SaveOptions {
+            png_16_bit: true,
+            info: Some(RenderInfo {
+                scene: "Gradient".to_owned(),
+                samples: 64,
+                ..RenderInfo::default()
+            }),
+            ..SaveOptions::new()
+        };
 
-    full_path.push_str(path_end);
-    println!("Full Path: {}", &full_path);
-    let path = Path::new(&full_path);
-    let _ = image.save(path);
-}
\ No newline at end of file
+        // 16-bit PNG with the render settings as text
+        let path = directory.join("nested/gradient.png");
+        save_film(&path, &film, &transform, &options).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let png_info = reader.info();
+        assert_eq!(png_info.bit_depth, png::BitDepth::Sixteen);
+        let texts: Vec<(&str, &str)> = png_info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.as_str(), chunk.text.as_str()))
+            .collect();
+        assert!(texts.contains(&("Title", "Gradient")));
+        assert!(texts.contains(&("Samples per pixel", "64")));
+        let image = image::open(&path).unwrap().to_rgb16();
+        let expected = transform.to_display(&film.pixel(3, 0));
+        assert_eq!(
+            image.get_pixel(3, 0)[0],
+            (expected.x * 65535.0 + 0.5) as u16
+        );
+
+        let argb = film.to_argb(&transform);
+        for name in [
+            "gradient.png",
+            "gradient.bmp",
+            "gradient.tga",
+            "gradient.ppm",
+            "gradient.jpg",
+        ] {
+            let path = directory.join(name);
+            save_film(&path, &film, &transform, &SaveOptions::new()).unwrap();
+            let image = image::open(&path).unwrap().to_rgb8();
+            assert_eq!(image.dimensions(), (37, 21));
+            // JPEG is lossy, the others keep every value
+            let tolerance = if name.ends_with("jpg") { 24 } else { 0 };
+            for (index, pixel) in image.pixels().enumerate() {
+                let value = argb[index];
+                let expected = [(value >> 16) as u8, (value >> 8) as u8, value as u8];
+                for channel in 0..3 {
+                    assert!(
+                        pixel[channel].abs_diff(expected[channel]) <= tolerance,
+                        "{}",
+                        name
+                    );
+                }
+            }
+        }
+
+        // Refused before anything is written, naming the extension
+        let path = directory.join("gradient.xyz");
+        let error = save_film(&path, &film, &transform, &options).unwrap_err();
+        assert!(matches!(error, ImageError::Unsupported(_)));
+        assert!(error.to_string().contains("xyz") && !error.to_string().contains("gradient"));
+        let error = save_image_to(&path, &argb, film.width(), film.height()).unwrap_err();
+        assert!(matches!(error, ImageError::Unsupported(_)));
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::hittable::Hittable;
+use crate::render_progress::{CancelToken, RenderOutcome, RenderProgress};
+use std::sync::Arc;
+
+// Everything needed to render an image: the geometry, the objects to sample as lights and a
+// configured camera
+pub struct Scene {
+    pub name: String,
+    pub world: Arc<dyn Hittable + Sync + Send>,
+    pub lights: Option<Arc<dyn Hittable + Sync + Send>>,
+    pub camera: Camera,
+}
+
+impl Scene {
+    pub fn new(
+        name: &str,
+        world: Arc<dyn Hittable + Sync + Send>,
+        lights: Option<Arc<dyn Hittable + Sync + Send>>,
+        camera: Camera,
+    ) -> Scene {
+        Scene {
+            name: name.to_owned(),
+            world,
+            lights,
+            camera,
+        }
+    }
+
+    // Renders the scene, running on a single thread when `threads` is 1
+    pub fn render(&mut self, threads: u32) -> Film {
+        let world = Arc::clone(&self.world);
+        let lights = self.lights.as_ref().map(Arc::clone);
+        self.camera.render(world, lights, threads > 1, threads)
+    }
+
+    // Renders with progress reports and cancellation, see `Camera::render_with_progress`
+    pub fn render_with_progress(
+        &mut self,
+        threads: u32,
+        on_progress: &mut dyn FnMut(&RenderProgress),
+        cancel: &CancelToken,
+    ) -> RenderOutcome {
+        let world = Arc::clone(&self.world);
+        let lights = self.lights.as_ref().map(Arc::clone);
+        self.camera
+            .render_with_progress(world, lights, threads, on_progress, cancel)
+    }
+
+    // Renders in passes and calls `on_pass` with the film after each one, see
+    // `Camera::render_progressive`
+    pub fn render_progressive(
+        &mut self,
+        threads: u32,
+        on_pass: &mut dyn FnMut(&Film) -> bool,
+    ) -> Film {
+        let world = Arc::clone(&self.world);
+        let lights = self.lights.as_ref().map(Arc::clone);
+        self.camera
+            .render_progressive(world, lights, threads, on_pass)
+    }
+}
@@ -0,0 +1,941 @@
+// Loader for the text scene format used by the files in `ray_tracing/scenes`.
+//
+// A scene file is a list of statements, one per line. Tokens are separated by whitespace,
+// everything after a `#` is a comment and a vector or color is written as three numbers.
+// Names have to be defined before they are used and may be redefined, which makes it easy to
+// wrap an object in place (e.g. `object box_1 rotate_y box_1 15`).
+//
+//   name <scene name>
+//   camera <field> <value>
+//   texture <name> solid <color>
+//   texture <name> checker <scale> <even color> <odd color>
+//   texture <name> image <path relative to the scene file>
+//   texture <name> noise <scale>
+//   material <name> lambertian <color | texture>
+//   material <name> metal <color> <fuzz>
+//   material <name> conductor <preset | eta k> <roughness> [anisotropy]
+//   material <name> dielectric <index of refraction | dispersion> [<absorption color> <distance>]
+//   material <name> rough_dielectric <index of refraction> <roughness> [<color> <distance>]
+//   material <name> principled <color | texture> [<parameter> <value>] ...
+//   material <name> normal_map <material> <texture>
+//   material <name> bump <material> <texture> <height>
+//   material <name> diffuse_light <color | texture>
+//   material <name> isotropic <color | texture>
+//   object <name> sphere <center> <radius> <material>
+//   object <name> moving_sphere <center start> <center end> <radius> <material>
+//   object <name> quad <origin> <u> <v> <material>
+//   object <name> box <corner a> <corner b> <material>
+//   object <name> triangle <vertex a> <vertex b> <vertex c> <material>
+//   object <name> prism <vertex a> <vertex b> <vertex c> <extrusion> <material>
+//   object <name> obj <path relative to the scene file> <default material>
+//   object <name> constant_medium <boundary object> <density> <color | texture>
+//   object <name> translate <object> <offset>
+//   object <name> rotate_x <object> <degrees>
+//   object <name> rotate_y <object> <degrees>
+//   object <name> rotate_z <object> <degrees>
+//   object <name> rotate <object> <axis> <degrees>
+//   object <name> scale <object> <factors>
+//   object <name> look_at <object> <from> <to> <up>
+//   object <name> list <object> ...
+//   object <name> bvh <object> ...
+//   object <name> linear_bvh <object> ...
+//   world <object> ...
+//   light <object> ...
+//
+// An `obj` object with emissive materials also defines `<name>.lights`, the emissive meshes on
+// their own, to be passed to `light` and wrapped the same way as the model.
+//
+// The camera fields are the public fields of `Camera`. `aspect_ratio` also accepts a ratio
+// such as `16/9`, `tone_map` is one of clamp, reinhard, extended_reinhard [white point] or
+// aces, `exposure` is in EV and `sampler` is one of independent, stratified, halton or sobol.
+// `adaptive <relative error> [min samples]` turns on adaptive sampling. `tile_size` is in pixels
+// and `tile_order` is one of scanline, spiral or hilbert. `aovs` lists the AOVs to make, any
+// of normal, albedo, depth, position, material_id, object_id, direct and indirect, or `all`.
+// `denoise [iterations]` filters the noise out of the finished image, in 1 to 16 passes.
+// `spectral` traces wavelengths instead of RGB.
+//
+// The parameters of `principled` are metallic, roughness, specular, specular_tint, sheen,
+// clearcoat, clearcoat_roughness and transmission, each a number or a texture, emission, a
+// color or a texture, and ior, a number. The dispersion of a `dielectric` is one of
+// `cauchy <a> <b>`, `sellmeier <b1 b2 b3> <c1 c2 c3>` with wavelengths in micrometres, or the
+// glasses bk7 and sf11. `normal_map` and `bump` wrap another material, tilting its normal with
+// a tangent space normal map or the slope of a height texture.
+
+use crate::adaptive_sampling::AdaptiveSampling;
+use crate::affine_transforms::{RotateY, Transform, Translate};
+use crate::aov::Aov;
+use crate::bvh_node::{BVHNode, SplitMethod};
+use crate::camera::Camera;
+use crate::checker_texture::CheckerTexture;
+use crate::color::ToneMap;
+use crate::conductor::Conductor;
+use crate::constant_medium::ConstantMedium;
+use crate::denoise::Denoiser;
+use crate::dielectric::{Absorption, Dielectric, Dispersion};
+use crate::diffuse_light::DiffuseLight;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::image_texture::ImageTexture;
+use crate::isotropic::Isotropic;
+use crate::lambertian::Lambertian;
+use crate::linear_bvh::LinearBVH;
+use crate::mat4::Mat4;
+use crate::material::Material;
+use crate::metal::Metal;
+use crate::noise_texture::NoiseTexture;
+use crate::normal_map::NormalMapped;
+use crate::obj_loader::load_obj;
+use crate::principled::Principled;
+use crate::quad::{quad_box, Quad};
+use crate::rough_dielectric::RoughDielectric;
+use crate::sampler::SamplerKind;
+use crate::scene::Scene;
+use crate::solid_texture::SolidTexture;
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::tile_scheduler::TileOrder;
+use crate::triangle::{prism, Triangle};
+use nurbs::vector_3::Vec3;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(path, error) => {
+                write!(f, "could not read scene file {}: {}", path.display(), error)
+            }
+            SceneError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+// Loads a scene file, naming the scene after the file unless it contains a `name` statement
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+    load_scene_with(path, SplitMethod::default())
+}
+
+// Like `load_scene`, building the `bvh` objects with `split_method`
+pub fn load_scene_with<P: AsRef<Path>>(
+    path: P,
+    split_method: SplitMethod,
+) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)
+        .map_err(|error| SceneError::Io(path.to_path_buf(), error))?;
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Scene".to_owned());
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    parse_scene_with(&source, &name, base_dir, split_method)
+}
+
+// Parses scene source text, resolving image paths against `base_dir`
+pub fn parse_scene(source: &str, name: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    parse_scene_with(source, name, base_dir, SplitMethod::default())
+}
+
+pub fn parse_scene_with(
+    source: &str,
+    name: &str,
+    base_dir: &Path,
+    split_method: SplitMethod,
+) -> Result<Scene, SceneError> {
+    let mut parser = SceneParser::new(name, base_dir, split_method);
+    for (index, line) in source.lines().enumerate() {
+        let content = match line.find('#') {
+            Some(comment_start) => &line[..comment_start],
+            None => line,
+        };
+        let mut statement = Statement {
+            line: index + 1,
+            tokens: content.split_whitespace().peekable(),
+        };
+        if statement.tokens.peek().is_some() {
+            parser.statement(&mut statement)?;
+            statement.finish()?;
+        }
+    }
+    Ok(parser.build())
+}
+
+struct Statement<'a> {
+    line: usize,
+    tokens: Peekable<SplitWhitespace<'a>>,
+}
+
+impl<'a> Statement<'a> {
+    fn error(&self, message: String) -> SceneError {
+        SceneError::Parse {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn has_more(&mut self) -> bool {
+        self.tokens.peek().is_some()
+    }
+
+    fn next_token(&mut self, what: &str) -> Result<&'a str, SceneError> {
+        match self.tokens.next() {
+            Some(token) => Ok(token),
+            None => Err(self.error(format!("expected {}", what))),
+        }
+    }
+
+    fn next_f64(&mut self, what: &str) -> Result<f64, SceneError> {
+        let token = self.next_token(what)?;
+        token
+            .parse::<f64>()
+            .map_err(|_| self.error(format!("expected {} but found '{}'", what, token)))
+    }
+
+    fn next_i32(&mut self, what: &str) -> Result<i32, SceneError> {
+        let token = self.next_token(what)?;
+        token
+            .parse::<i32>()
+            .map_err(|_| self.error(format!("expected {} but found '{}'", what, token)))
+    }
+
+    fn next_ratio(&mut self, what: &str) -> Result<f64, SceneError> {
+        let token = self.next_token(what)?;
+        let value = match token.split_once('/') {
+            Some((num, den)) => match (num.parse::<f64>(), den.parse::<f64>()) {
+                (Ok(num), Ok(den)) if den != 0.0 => Some(num / den),
+                _ => None,
+            },
+            None => token.parse::<f64>().ok(),
+        };
+        value.ok_or_else(|| self.error(format!("expected {} but found '{}'", what, token)))
+    }
+
+    fn next_vec3(&mut self, what: &str) -> Result<Vec3, SceneError> {
+        let x = self.next_f64(what)?;
+        let y = self.next_f64(what)?;
+        let z = self.next_f64(what)?;
+        Ok(Vec3::new(x, y, z))
+    }
+
+    fn next_is_number(&mut self) -> bool {
+        match self.tokens.peek() {
+            Some(token) => token.parse::<f64>().is_ok(),
+            None => false,
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), SceneError> {
+        match self.tokens.next() {
+            Some(token) => Err(self.error(format!("unexpected '{}'", token))),
+            None => Ok(()),
+        }
+    }
+}
+
+struct SceneParser {
+    name: String,
+    base_dir: PathBuf,
+    split_method: SplitMethod,
+    camera: Camera,
+    textures: HashMap<String, Arc<dyn Texture + Sync + Send>>,
+    materials: HashMap<String, Arc<dyn Material + Sync + Send>>,
+    objects: HashMap<String, Arc<dyn Hittable + Sync + Send>>,
+    world: HittableList,
+    lights: HittableList,
+}
+
+impl SceneParser {
+    fn new(name: &str, base_dir: &Path, split_method: SplitMethod) -> SceneParser {
+        SceneParser {
+            name: name.to_owned(),
+            base_dir: base_dir.to_path_buf(),
+            split_method,
+            camera: Camera::new(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            objects: HashMap::new(),
+            world: HittableList::new(),
+            lights: HittableList::new(),
+        }
+    }
+
+    fn build(self) -> Scene {
+        let lights: Option<Arc<dyn Hittable + Sync + Send>> = match self.lights.objects.len() {
+            0 => None,
+            1 => Some(Arc::clone(&self.lights.objects[0])),
+            _ => Some(Arc::new(self.lights)),
+        };
+        Scene::new(&self.name, Arc::new(self.world), lights, self.camera)
+    }
+
+    fn statement(&mut self, statement: &mut Statement) -> Result<(), SceneError> {
+        let keyword = statement.next_token("a statement")?;
+        match keyword {
+            "name" => {
+                self.name = statement.next_token("a scene name")?.to_owned();
+            }
+            "camera" => self.camera_field(statement)?,
+            "texture" => {
+                let name = statement.next_token("a texture name")?;
+                let texture = self.texture(statement)?;
+                self.textures.insert(name.to_owned(), texture);
+            }
+            "material" => {
+                let name = statement.next_token("a material name")?;
+                let material = self.material(statement)?;
+                self.materials.insert(name.to_owned(), material);
+            }
+            "object" => {
+                let name = statement.next_token("an object name")?;
+                let object = self.object(name, statement)?;
+                self.objects.insert(name.to_owned(), object);
+            }
+            "world" => {
+                for object in self.object_list(statement)? {
+                    self.world.add(object);
+                }
+            }
+            "light" => {
+                for object in self.object_list(statement)? {
+                    self.lights.add(object);
+                }
+            }
+            _ => return Err(statement.error(format!("unknown statement '{}'", keyword))),
+        }
+        Ok(())
+    }
+
+    fn camera_field(&mut self, statement: &mut Statement) -> Result<(), SceneError> {
+        let field = statement.next_token("a camera field")?;
+        let cam = &mut self.camera;
+        match field {
+            "samples_per_pixel" => cam.samples_per_pixel = statement.next_i32("a sample count")?,
+            "image_width" => cam.image_width = statement.next_i32("an image width")?,
+            "aspect_ratio" => cam.aspect_ratio = statement.next_ratio("an aspect ratio")?,
+            "max_depth" => cam.max_depth = statement.next_i32("a depth")?,
+            "vfov" => cam.vfov = statement.next_f64("a field of view")?,
+            "look_from" => cam.look_from = statement.next_vec3("a position")?,
+            "look_at" => cam.look_at = statement.next_vec3("a position")?,
+            "v_up" => cam.v_up = statement.next_vec3("a direction")?,
+            "defocus_angle" => cam.defocus_angle = statement.next_f64("an angle")?,
+            "focus_dist" => cam.focus_dist = statement.next_f64("a distance")?,
+            "background" => cam.background = statement.next_vec3("a color")?,
+            "seed" => {
+                let token = statement.next_token("a seed")?;
+                cam.seed = token.parse::<u64>().map_err(|_| {
+                    statement.error(format!("expected a seed but found '{}'", token))
+                })?;
+            }
+            "sampler" => {
+                let name = statement.next_token("a sampler")?;
+                cam.sampler = SamplerKind::from_name(name)
+                    .ok_or_else(|| statement.error(format!("unknown sampler '{}'", name)))?;
+            }
+            "tile_size" => cam.tile_size = statement.next_i32("a tile size")?.max(1) as u32,
+            "tile_order" => {
+                let name = statement.next_token("a tile order")?;
+                cam.tile_order = TileOrder::from_name(name)
+                    .ok_or_else(|| statement.error(format!("unknown tile order '{}'", name)))?;
+            }
+            "aovs" => {
+                cam.aovs.clear();
+                while statement.has_more() {
+                    let name = statement.next_token("an AOV")?;
+                    match name {
+                        "all" => cam.aovs.extend(Aov::ALL),
+                        _ => {
+                            cam.aovs.push(Aov::from_name(name).ok_or_else(|| {
+                                statement.error(format!("unknown AOV '{}'", name))
+                            })?)
+                        }
+                    }
+                }
+            }
+            "denoise" => {
+                let mut denoiser = Denoiser::new();
+                if statement.next_is_number() {
+                    let iterations = statement.next_i32("an iteration count")?;
+                    let range = Denoiser::ITERATIONS;
+                    if iterations < *range.start() as i32 || iterations > *range.end() as i32 {
+                        return Err(statement.error(format!(
+                            "the denoiser takes {} to {} iterations",
+                            range.start(),
+                            range.end()
+                        )));
+                    }
+                    denoiser.iterations = iterations as u32;
+                }
+                cam.denoise = Some(denoiser);
+            }
+            "spectral" => cam.spectral = true,
+            "adaptive" => {
+                let mut adaptive = AdaptiveSampling::new(statement.next_f64("a relative error")?);
+                if statement.next_is_number() {
+                    adaptive.min_samples = statement.next_i32("a sample count")?.max(1) as u32;
+                }
+                cam.adaptive = Some(adaptive);
+            }
+            "exposure" => cam.exposure = statement.next_f64("an exposure in EV")?,
+            "tone_map" => {
+                let name = statement.next_token("a tone map")?;
+                let white = if statement.next_is_number() {
+                    Some(statement.next_f64("a white point")?)
+                } else {
+                    None
+                };
+                cam.tone_map = ToneMap::from_name(name, white)
+                    .ok_or_else(|| statement.error(format!("unknown tone map '{}'", name)))?;
+            }
+            _ => return Err(statement.error(format!("unknown camera field '{}'", field))),
+        }
+        Ok(())
+    }
+
+    fn texture(
+        &mut self,
+        statement: &mut Statement,
+    ) -> Result<Arc<dyn Texture + Sync + Send>, SceneError> {
+        let kind = statement.next_token("a texture type")?;
+        let texture: Arc<dyn Texture + Sync + Send> = match kind {
+            "solid" => Arc::new(SolidTexture::new(statement.next_vec3("a color")?)),
+            "checker" => {
+                let scale = statement.next_f64("a checker scale")?;
+                let even = statement.next_vec3("a color")?;
+                let odd = statement.next_vec3("a color")?;
+                Arc::new(CheckerTexture::new(scale, even, odd))
+            }
+            "image" => {
+                let path = self.base_dir.join(statement.next_token("an image path")?);
+                let texture = ImageTexture::open(&path).map_err(|error| {
+                    statement.error(format!("could not load image {}: {}", path.display(), error))
+                })?;
+                Arc::new(texture)
+            }
+            "noise" => Arc::new(NoiseTexture::new(statement.next_f64("a noise scale")?)),
+            _ => return Err(statement.error(format!("unknown texture type '{}'", kind))),
+        };
+        Ok(texture)
+    }
+
+    fn material(
+        &mut self,
+        statement: &mut Statement,
+    ) -> Result<Arc<dyn Material + Sync + Send>, SceneError> {
+        let kind = statement.next_token("a material type")?;
+        let material: Arc<dyn Material + Sync + Send> = match kind {
+            "lambertian" => Arc::new(Lambertian::new(self.color_or_texture(statement)?)),
+            "metal" => {
+                let albedo = statement.next_vec3("a color")?;
+                let fuzz = statement.next_f64("a fuzz factor")?;
+                Arc::new(Metal::new(albedo, fuzz))
+            }
+            "conductor" => {
+                // A preset name or the complex index of refraction
+                let (preset, eta, k) = if statement.next_is_number() {
+                    let eta = statement.next_vec3("a refractive index")?;
+                    (None, eta, statement.next_vec3("an extinction coefficient")?)
+                } else {
+                    let name = statement.next_token("a metal")?;
+                    (Some(name), Vec3::empty(), Vec3::empty())
+                };
+                let roughness = statement.next_f64("a roughness")?;
+                let anisotropy = if statement.next_is_number() {
+                    statement.next_f64("an anisotropy")?
+                } else {
+                    0.0
+                };
+                match preset {
+                    Some(name) => Arc::new(
+                        Conductor::preset(name, roughness, anisotropy)
+                            .ok_or_else(|| statement.error(format!("unknown metal '{}'", name)))?,
+                    ),
+                    None => Arc::new(Conductor::new(eta, k, roughness, anisotropy)),
+                }
+            }
+            "dielectric" if statement.next_is_number() => {
+                let ir = statement.next_f64("an index of refraction")?;
+                match self.absorption(statement)? {
+                    Some((color, distance)) => {
+                        Arc::new(Dielectric::new_absorbing(ir, color, distance))
+                    }
+                    None => Arc::new(Dielectric::new(ir)),
+                }
+            }
+            "dielectric" => {
+                let dispersion = self.dispersion(statement)?;
+                let absorption = self.absorption(statement)?;
+                Arc::new(Dielectric::new_dispersive(
+                    dispersion,
+                    absorption.map(|(color, distance)| Absorption::new(color, distance)),
+                ))
+            }
+            "rough_dielectric" => {
+                let ir = statement.next_f64("an index of refraction")?;
+                let roughness = statement.next_f64("a roughness")?;
+                match self.absorption(statement)? {
+                    Some((color, distance)) => Arc::new(RoughDielectric::new_absorbing(
+                        ir, roughness, color, distance,
+                    )),
+                    None => Arc::new(RoughDielectric::new(ir, roughness)),
+                }
+            }
+            "principled" => {
+                let mut principled = Principled::new(self.color_or_texture(statement)?);
+                while statement.has_more() {
+                    let parameter = statement.next_token("a parameter")?;
+                    let texture = match parameter {
+                        "metallic" => &mut principled.metallic,
+                        "roughness" => &mut principled.roughness,
+                        "specular" => &mut principled.specular,
+                        "specular_tint" => &mut principled.specular_tint,
+                        "sheen" => &mut principled.sheen,
+                        "clearcoat" => &mut principled.clearcoat,
+                        "clearcoat_roughness" => &mut principled.clearcoat_roughness,
+                        "transmission" => &mut principled.transmission,
+                        "emission" => {
+                            principled.emission = self.color_or_texture(statement)?;
+                            continue;
+                        }
+                        "ior" => {
+                            principled.ior = statement.next_f64("an index of refraction")?;
+                            continue;
+                        }
+                        _ => {
+                            return Err(statement
+                                .error(format!("unknown principled parameter '{}'", parameter)))
+                        }
+                    };
+                    *texture = self.value_or_texture(statement)?;
+                }
+                Arc::new(principled)
+            }
+            "normal_map" => {
+                let material = self.material_ref(statement)?;
+                let texture = self.color_or_texture(statement)?;
+                Arc::new(NormalMapped::new_normal_map(material, texture))
+            }
+            "bump" => {
+                let material = self.material_ref(statement)?;
+                let texture = self.color_or_texture(statement)?;
+                let scale = statement.next_f64("a bump height")?;
+                Arc::new(NormalMapped::new_bump(material, texture, scale))
+            }
+            "diffuse_light" => Arc::new(DiffuseLight::new(self.color_or_texture(statement)?)),
+            "isotropic" => Arc::new(Isotropic::new(self.color_or_texture(statement)?)),
+            _ => return Err(statement.error(format!("unknown material type '{}'", kind))),
+        };
+        Ok(material)
+    }
+
+    fn object(
+        &mut self,
+        name: &str,
+        statement: &mut Statement,
+    ) -> Result<Arc<dyn Hittable + Sync + Send>, SceneError> {
+        let kind = statement.next_token("an object type")?;
+        let object: Arc<dyn Hittable + Sync + Send> = match kind {
+            "sphere" => {
+                let center = statement.next_vec3("a center")?;
+                let radius = statement.next_f64("a radius")?;
+                Arc::new(Sphere::new(center, radius, self.material_ref(statement)?))
+            }
+            "moving_sphere" => {
+                let center_start = statement.next_vec3("a center")?;
+                let center_end = statement.next_vec3("a center")?;
+                let radius = statement.next_f64("a radius")?;
+                let mat = self.material_ref(statement)?;
+                Arc::new(Sphere::new_moving(center_start, radius, mat, center_end))
+            }
+            "quad" => {
+                let origin = statement.next_vec3("an origin")?;
+                let u = statement.next_vec3("an edge vector")?;
+                let v = statement.next_vec3("an edge vector")?;
+                Arc::new(Quad::new(origin, u, v, self.material_ref(statement)?))
+            }
+            "triangle" => {
+                let a = statement.next_vec3("a vertex")?;
+                let b = statement.next_vec3("a vertex")?;
+                let c = statement.next_vec3("a vertex")?;
+                Arc::new(Triangle::new(a, b, c, self.material_ref(statement)?))
+            }
+            "prism" => {
+                let a = statement.next_vec3("a vertex")?;
+                let b = statement.next_vec3("a vertex")?;
+                let c = statement.next_vec3("a vertex")?;
+                let extrusion = statement.next_vec3("an extrusion")?;
+                prism(a, b, c, extrusion, self.material_ref(statement)?)
+            }
+            "obj" => {
+                let path = self.base_dir.join(statement.next_token("an OBJ path")?);
+                let default_material = self.material_ref(statement)?;
+                let model = load_obj(&path, default_material)
+                    .map_err(|error| statement.error(error.to_string()))?;
+                // The emissive meshes, for `light <name>.lights`
+                let lights_name = format!("{}.lights", name);
+                if model.lights.is_empty() {
+                    self.objects.remove(&lights_name);
+                } else {
+                    let mut lights = HittableList::new();
+                    for light in model.lights {
+                        lights.add(light);
+                    }
+                    self.objects.insert(lights_name, Arc::new(lights));
+                }
+                model.object
+            }
+            "box" => {
+                let point_a = statement.next_vec3("a corner")?;
+                let point_b = statement.next_vec3("a corner")?;
+                quad_box(point_a, point_b, self.material_ref(statement)?)
+            }
+            "constant_medium" => {
+                let boundary = self.object_ref(statement)?;
+                let density = statement.next_f64("a density")?;
+                let phase = self.color_or_texture(statement)?;
+                Arc::new(ConstantMedium::new(boundary, density, phase))
+            }
+            "translate" => {
+                let object = self.object_ref(statement)?;
+                let offset = statement.next_vec3("an offset")?;
+                Arc::new(Translate::new(object, offset))
+            }
+            "rotate_y" => {
+                let object = self.object_ref(statement)?;
+                let angle = statement.next_f64("an angle")?;
+                Arc::new(RotateY::new(object, angle))
+            }
+            "rotate_x" | "rotate_z" => {
+                let object = self.object_ref(statement)?;
+                let angle = statement.next_f64("an angle")?;
+                let matrix = if kind == "rotate_x" {
+                    Mat4::rotate_x(angle)
+                } else {
+                    Mat4::rotate_z(angle)
+                };
+                Arc::new(Transform::new(object, matrix).expect("rotations are invertible"))
+            }
+            "rotate" => {
+                let object = self.object_ref(statement)?;
+                let axis = statement.next_vec3("an axis")?;
+                let angle = statement.next_f64("an angle")?;
+                let transform = Transform::rotate(object, axis, angle).ok_or_else(|| {
+                    statement.error("the rotation axis must not be zero".to_string())
+                })?;
+                Arc::new(transform)
+            }
+            "scale" => {
+                let object = self.object_ref(statement)?;
+                let factors = statement.next_vec3("scale factors")?;
+                let transform = Transform::scale(object, factors)
+                    .ok_or_else(|| statement.error("scale factors must not be zero".to_string()))?;
+                Arc::new(transform)
+            }
+            "look_at" => {
+                let object = self.object_ref(statement)?;
+                let from = statement.next_vec3("a position")?;
+                let to = statement.next_vec3("a target")?;
+                let up = statement.next_vec3("an up vector")?;
+                let transform = Transform::look_at(object, from, to, up).ok_or_else(|| {
+                    statement.error(
+                        "look_at needs two points apart and an up vector off the line through them"
+                            .to_string(),
+                    )
+                })?;
+                Arc::new(transform)
+            }
+            "list" => {
+                let mut list = HittableList::new();
+                for object in self.object_list(statement)? {
+                    list.add(object);
+                }
+                Arc::new(list)
+            }
+            "bvh" => {
+                let mut list = HittableList::new();
+                for object in self.object_list(statement)? {
+                    list.add(object);
+                }
+                Arc::new(BVHNode::from_list_with(&list, self.split_method))
+            }
+            "linear_bvh" => {
+                let mut list = HittableList::new();
+                for object in self.object_list(statement)? {
+                    list.add(object);
+                }
+                Arc::new(LinearBVH::from_list(&list))
+            }
+            _ => return Err(statement.error(format!("unknown object type '{}'", kind))),
+        };
+        Ok(object)
+    }
+
+    fn color_or_texture(
+        &self,
+        statement: &mut Statement,
+    ) -> Result<Arc<dyn Texture + Sync + Send>, SceneError> {
+        if statement.next_is_number() {
+            Ok(Arc::new(SolidTexture::new(statement.next_vec3("a color")?)))
+        } else {
+            let name = statement.next_token("a color or texture name")?;
+            match self.textures.get(name) {
+                Some(texture) => Ok(Arc::clone(texture)),
+                None => Err(statement.error(format!("unknown texture '{}'", name))),
+            }
+        }
+    }
+
+    // A single number, for all three channels, or a texture
+    fn value_or_texture(
+        &self,
+        statement: &mut Statement,
+    ) -> Result<Arc<dyn Texture + Sync + Send>, SceneError> {
+        if statement.next_is_number() {
+            let value = statement.next_f64("a value")?;
+            Ok(Arc::new(SolidTexture::new(Vec3::new(value, value, value))))
+        } else {
+            self.color_or_texture(statement)
+        }
+    }
+
+    // How the index of refraction of a dielectric changes with the wavelength
+    fn dispersion(&self, statement: &mut Statement) -> Result<Dispersion, SceneError> {
+        let kind = statement.next_token("an index of refraction or a dispersion")?;
+        match kind {
+            "cauchy" => Ok(Dispersion::Cauchy {
+                a: statement.next_f64("a Cauchy coefficient")?,
+                b: statement.next_f64("a Cauchy coefficient")?,
+            }),
+            "sellmeier" => {
+                let b = statement.next_vec3("Sellmeier coefficients")?;
+                let c = statement.next_vec3("Sellmeier coefficients")?;
+                Ok(Dispersion::Sellmeier {
+                    b: [b.x, b.y, b.z],
+                    c: [c.x, c.y, c.z],
+                })
+            }
+            "bk7" => Ok(Dispersion::BK7),
+            "sf11" => Ok(Dispersion::SF11),
+            _ => Err(statement.error(format!("unknown dispersion '{}'", kind))),
+        }
+    }
+
+    // The optional absorption color and distance at the end of a dielectric
+    fn absorption(&self, statement: &mut Statement) -> Result<Option<(Vec3, f64)>, SceneError> {
+        if !statement.next_is_number() {
+            return Ok(None);
+        }
+        let color = statement.next_vec3("an absorption color")?;
+        Ok(Some((color, statement.next_f64("a distance")?)))
+    }
+
+    fn material_ref(
+        &self,
+        statement: &mut Statement,
+    ) -> Result<Arc<dyn Material + Sync + Send>, SceneError> {
+        let name = statement.next_token("a material name")?;
+        match self.materials.get(name) {
+            Some(material) => Ok(Arc::clone(material)),
+            None => Err(statement.error(format!("unknown material '{}'", name))),
+        }
+    }
+
+    fn object_ref(
+        &self,
+        statement: &mut Statement,
+    ) -> Result<Arc<dyn Hittable + Sync + Send>, SceneError> {
+        let name = statement.next_token("an object name")?;
+        match self.objects.get(name) {
+            Some(object) => Ok(Arc::clone(object)),
+            None => Err(statement.error(format!("unknown object '{}'", name))),
+        }
+    }
+
+    fn object_list(
+        &self,
+        statement: &mut Statement,
+    ) -> Result<Vec<Arc<dyn Hittable + Sync + Send>>, SceneError> {
+        let mut objects = vec![self.object_ref(statement)?];
+        while statement.has_more() {
+            objects.push(self.object_ref(statement)?);
+        }
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse_scene(source, "Test", Path::new(""))
+    }
+
+    fn error_line(source: &str) -> usize {
+        match parse(source) {
+            Err(SceneError::Parse { line, .. }) => line,
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn camera_and_lights() {
+        let source = "
+            # A light above a sphere
+            name Lit
+            camera aspect_ratio 16/9
+            camera image_width 320
+            camera look_from 0 1 -5
+            camera sampler halton
+            camera adaptive 0.02 32
+            camera tile_size 32
+            camera tile_order spiral
+            camera aovs depth normal
+            camera denoise 3
+            material white lambertian 0.73 0.73 0.73
+            material lamp diffuse_light 15 15 15
+            object ball sphere 0 0 0 1 white
+            object panel quad -1 3 -1  2 0 0  0 0 2 lamp  # facing down
+            world ball panel
+            light panel
+        ";
+        let scene = parse(source).unwrap();
+        assert_eq!(scene.name, "Lit");
+        assert!((scene.camera.aspect_ratio - 16.0 / 9.0).abs() < f64::EPSILON);
+        assert_eq!(scene.camera.image_width, 320);
+        assert!((scene.camera.look_from.z + 5.0).abs() < f64::EPSILON);
+        assert_eq!(scene.camera.sampler, SamplerKind::Halton);
+        let adaptive = scene.camera.adaptive.unwrap();
+        assert_eq!((adaptive.threshold, adaptive.min_samples), (0.02, 32));
+        assert_eq!(scene.camera.tile_size, 32);
+        assert_eq!(scene.camera.tile_order, TileOrder::Spiral);
+        assert_eq!(scene.camera.aovs, [Aov::Depth, Aov::Normal]);
+        assert_eq!(scene.camera.denoise.unwrap().iterations, 3);
+        assert!(scene.lights.is_some());
+        let bbox = scene.world.bounding_box();
+        assert!((bbox.axis(1).max() - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn wrapped_objects() {
+        let source = "
+            material white lambertian 0.73 0.73 0.73
+            object box_1 box 0 0 0 165 330 165 white
+            object box_1 rotate_y box_1 15
+            object box_1 translate box_1 265 0 295
+            object smoke constant_medium box_1 0.01 0 0 0
+            object group bvh box_1 smoke
+            world group
+        ";
+        let scene = parse(source).unwrap();
+        assert!(scene.lights.is_none());
+        assert!(scene.world.bounding_box().axis(0).min() > 250.0);
+    }
+
+    #[test]
+    fn bvh_split_method() {
+        // Spheres on top of each other, which only the median split breaks up
+        let source = "
+            material white lambertian 0.73 0.73 0.73
+            object a sphere 0 0 0 1 white
+            object group bvh a a a a
+            world group
+        ";
+        let leaves = |split_method: SplitMethod| {
+            let scene = parse_scene_with(source, "Stack", Path::new(""), split_method).unwrap();
+            scene.world.bvh_stats().unwrap().leaves
+        };
+        assert_eq!(leaves(SplitMethod::Median), 4);
+        assert!(leaves(SplitMethod::SurfaceAreaHeuristic) < 4);
+    }
+
+    #[test]
+    fn obj_lights() {
+        let directory =
+            std::env::temp_dir().join(format!("ray_tracing_obj_lights_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("lamp.mtl"),
+            "newmtl shade\nKd 0.5 0.5 0.5\nnewmtl bulb\nKe 4 4 4\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("lamp.obj"),
+            "mtllib lamp.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\n\
+             usemtl shade\nf 1 2 3\nusemtl bulb\nf 1 3 4\n",
+        )
+        .unwrap();
+        let source = "
+            material white lambertian 0.73 0.73 0.73
+            object lamp obj lamp.obj white
+            object lamp.lights translate lamp.lights 0 2 0
+            object lamp translate lamp 0 2 0
+            world lamp
+            light lamp.lights
+        ";
+        let scene = parse_scene(source, "Lamp", &directory).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        // Only the emissive face, which lies in the x = 0 plane
+        let light = scene.lights.unwrap();
+        let bbox = light.bounding_box();
+        assert!(bbox.axis(0).max() - bbox.axis(0).min() < 1e-3);
+        assert!((bbox.axis(1).min() - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn shipped_scenes_load() {
+        let scene_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in std::fs::read_dir(scene_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if let Err(error) = load_scene(&path) {
+                panic!("{}: {}", path.display(), error);
+            }
+        }
+    }
+
+    #[test]
+    fn errors_report_line() {
+        assert_eq!(error_line("\ncamera vfov wide\n"), 2);
+        assert_eq!(error_line("material m metal 1 1 1 0\nobject s sphere 0 0 0 1 gold\n"), 2);
+        assert_eq!(error_line("\n\n\nworld missing\n"), 4);
+        assert_eq!(error_line("texture t solid 1 1\n"), 1);
+        assert_eq!(error_line("# ok\nmaterial m dielectric 1.5 extra\n"), 2);
+        assert_eq!(error_line("camera zoom 2\n"), 1);
+        assert_eq!(error_line("\ncamera sampler random\n"), 2);
+        assert_eq!(error_line("\nmaterial m conductor tin 0.2\n"), 2);
+        assert_eq!(error_line("material m principled 1 1 1 gloss 1\n"), 1);
+        assert_eq!(error_line("\nmaterial m dielectric crystal\n"), 2);
+        assert_eq!(error_line("camera denoise 70\n"), 1);
+        assert_eq!(error_line("camera denoise 0\n"), 1);
+        let ball = "material m lambertian 1 1 1\nobject ball sphere 0 0 0 1 m\n";
+        assert_eq!(
+            error_line(&format!(
+                "{}object ball look_at ball 0 0 0  0 1 0  0 1 0\n",
+                ball
+            )),
+            3
+        );
+        assert_eq!(
+            error_line(&format!(
+                "{}object ball look_at ball 1 1 1  1 1 1  0 1 0\n",
+                ball
+            )),
+            3
+        );
+        assert_eq!(
+            error_line(&format!("{}object ball scale ball 1 0 1\n", ball)),
+            3
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::sampler::{mix, to_unit, Sampler};
+
+// The first two dimensions of the Sobol sequence, Owen-scrambled with the hash based nested
+// uniform scrambling from Burley's "Practical Hash-based Owen Scrambling". Every pair of
+// dimensions gets its own scrambling and its own shuffle of the sample order, which pads the
+// 2D points out to any number of dimensions. The first 2^k samples of a pixel are a (0, k, 2)-
+// net in every pair of dimensions, so power of two sample counts work best.
+pub struct SobolSampler {
+    seed: u64,
+    pixel_key: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel(&mut self, pixel: u64) {
+        self.pixel_key = mix(self.seed, pixel);
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn advance_to(&mut self, dimension: u32) {
+        self.dimension = self.dimension.max(dimension);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let key = self.next_key(1);
+        let shuffled = nested_uniform_scramble(self.index, key as u32);
+        to_unit(nested_uniform_scramble(
+            shuffled.reverse_bits(),
+            (key >> 32) as u32,
+        ))
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let key = self.next_key(2);
+        let shuffled = nested_uniform_scramble(self.index, key as u32);
+        let (x, y) = sobol_2d(shuffled);
+        (
+            to_unit(nested_uniform_scramble(x, (key >> 32) as u32)),
+            to_unit(nested_uniform_scramble(y, mix(key, 1) as u32)),
+        )
+    }
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel_key: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Key of the current dimension, then moves on by `count` dimensions
+    fn next_key(&mut self, count: u32) -> u64 {
+        let key = mix(self.pixel_key, self.dimension as u64);
+        self.dimension += count;
+        key
+    }
+}
+
+// Point `index` of the first two Sobol dimensions as 32 bit fractions. The first is the van
+// der Corput sequence, the second uses the direction numbers of the polynomial x + 1.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut direction = 1u32 << 31;
+    let mut bits = index;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            y ^= direction;
+        }
+        bits >>= 1;
+        direction ^= direction >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+// Flips every bit depending only on the bits below it, i.e. on the more significant bits of the
+// reversed value
+fn laine_karras_permutation(value: u32, seed: u32) -> u32 {
+    let mut x = value.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6C50_B47C);
+    x ^= x.wrapping_mul(0xB82F_1E52);
+    x ^= x.wrapping_mul(0xC7AF_E638);
+    x ^= x.wrapping_mul(0x8D22_F6E6);
+    x
+}
+
+// Owen scrambling of a base 2 fraction
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
@@ -0,0 +1,254 @@
+use nurbs::vector_3::Vec3;
+use std::cell::Cell;
+use std::sync::OnceLock;
+
+// Range of the wavelengths a spectral render samples, in nanometres
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+// Smits' (1999) spectra for turning RGB into reflectances, in ten bins over the sampled range.
+// Any color is white plus one secondary and one primary color.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+thread_local! {
+    // Wavelengths of the camera sample being traced on this thread, None outside spectral
+    // renders
+    static ACTIVE: Cell<Option<Wavelengths>> = const { Cell::new(None) };
+}
+
+// The wavelengths one camera sample carries, a hero wavelength and two more spaced evenly
+// around the sampled range (Wilkie et al. 2014). Colors along the path hold the radiance at
+// these three wavelengths in x, y and z.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wavelengths {
+    pub lambdas: [f64; 3],
+    // Set once something dispersive split the wavelengths up, after which only the hero
+    // wavelength is followed
+    pub secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    // The wavelengths for a sample `u` in [0, 1)
+    pub fn sample(u: f64) -> Wavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let lambda = |offset: f64| LAMBDA_MIN + ((u + offset) % 1.0) * range;
+        Wavelengths {
+            lambdas: [lambda(0.0), lambda(1.0 / 3.0), lambda(2.0 / 3.0)],
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambdas[0]
+    }
+
+    // The values of the spectrum of an RGB color at the wavelengths
+    pub fn uplift(&self, rgb: Vec3) -> Vec3 {
+        let [a, b, c] = self.lambdas.map(|lambda| uplift(rgb, lambda));
+        Vec3::new(a, b, c)
+    }
+
+    // Linear sRGB of the radiance `values` at the wavelengths, an estimate of the color of the
+    // whole spectrum
+    pub fn to_rgb(&self, values: Vec3) -> Vec3 {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let xyz = if self.secondary_terminated {
+            range * values.x * cie_xyz(self.lambdas[0])
+        } else {
+            range / 3.0
+                * (values.x * cie_xyz(self.lambdas[0])
+                    + values.y * cie_xyz(self.lambdas[1])
+                    + values.z * cie_xyz(self.lambdas[2]))
+        };
+        xyz_to_rgb(xyz) * white_balance()
+    }
+}
+
+// Makes `wavelengths` the ones traced on the calling thread, None for RGB
+pub fn install(wavelengths: Option<Wavelengths>) {
+    ACTIVE.with(|active| active.set(wavelengths));
+}
+
+// The wavelengths of the camera sample being traced, None in an RGB render
+pub fn wavelengths() -> Option<Wavelengths> {
+    ACTIVE.with(|active| active.get())
+}
+
+// Drops the secondary wavelengths of the sample being traced, for materials that send each
+// wavelength a different way
+pub fn terminate_secondary() {
+    ACTIVE.with(|active| {
+        if let Some(mut wavelengths) = active.get() {
+            wavelengths.secondary_terminated = true;
+            active.set(Some(wavelengths));
+        }
+    });
+}
+
+// A smooth spectrum with the color `rgb`, at `lambda`
+pub fn uplift(rgb: Vec3, lambda: f64) -> f64 {
+    let at = |spectrum: &[f64; 10]| smits(spectrum, lambda);
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    if r <= g && r <= b {
+        let base = r * at(&SMITS_WHITE);
+        if g <= b {
+            base + (g - r) * at(&SMITS_CYAN) + (b - g) * at(&SMITS_BLUE)
+        } else {
+            base + (b - r) * at(&SMITS_CYAN) + (g - b) * at(&SMITS_GREEN)
+        }
+    } else if g <= r && g <= b {
+        let base = g * at(&SMITS_WHITE);
+        if r <= b {
+            base + (r - g) * at(&SMITS_MAGENTA) + (b - r) * at(&SMITS_BLUE)
+        } else {
+            base + (b - g) * at(&SMITS_MAGENTA) + (r - b) * at(&SMITS_RED)
+        }
+    } else {
+        let base = b * at(&SMITS_WHITE);
+        if r <= g {
+            base + (r - b) * at(&SMITS_YELLOW) + (g - r) * at(&SMITS_GREEN)
+        } else {
+            base + (g - b) * at(&SMITS_YELLOW) + (r - g) * at(&SMITS_RED)
+        }
+    }
+}
+
+// Linear interpolation between the centres of the bins
+fn smits(spectrum: &[f64; 10], lambda: f64) -> f64 {
+    let position = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * 10.0 - 0.5;
+    let position = position.clamp(0.0, 9.0);
+    let bin = (position as usize).min(8);
+    let t = position - bin as f64;
+    (1.0 - t) * spectrum[bin] + t * spectrum[bin + 1]
+}
+
+// The CIE 1931 colour matching functions, the multi-lobe fit of Wyman, Sloan and Shirley (2013)
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let lobe = |mean: f64, below: f64, above: f64| {
+        let t = (lambda - mean) / if lambda < mean { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    Vec3::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+// Scales the channels so a spectrum of constant 1 comes out as white, 1 in every channel,
+// which keeps grey surfaces grey and bright as they are in RGB
+fn white_balance() -> Vec3 {
+    static WHITE: OnceLock<Vec3> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let steps = 3400;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let mut xyz = Vec3::empty();
+        for i in 0..steps {
+            xyz += step * cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step);
+        }
+        let white = xyz_to_rgb(xyz);
+        Vec3::new(1.0 / white.x, 1.0 / white.y, 1.0 / white.z)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The color of the spectrum uplifted from `rgb`, averaged over evenly spread samples
+    fn round_trip(rgb: Vec3) -> Vec3 {
+        let samples = 1000;
+        let mut sum = Vec3::empty();
+        for i in 0..samples {
+            let wavelengths = Wavelengths::sample((i as f64 + 0.5) / samples as f64);
+            sum += wavelengths.to_rgb(wavelengths.uplift(rgb));
+        }
+        sum / samples as f64
+    }
+
+    #[test]
+    fn colors_survive_the_round_trip() {
+        let white = round_trip(Vec3::new(1.0, 1.0, 1.0));
+        assert!(
+            (white - Vec3::new(1.0, 1.0, 1.0)).length() < 0.01,
+            "{:?}",
+            white
+        );
+        let grey = round_trip(Vec3::new(0.18, 0.18, 0.18));
+        assert!(
+            (grey - Vec3::new(0.18, 0.18, 0.18)).length() < 0.01,
+            "{:?}",
+            grey
+        );
+        // Smits' spectra aren't exact, saturated colors come back a little off
+        for rgb in [
+            Vec3::new(0.65, 0.05, 0.05),
+            Vec3::new(0.12, 0.45, 0.15),
+            Vec3::new(0.2, 0.3, 0.8),
+        ] {
+            let back = round_trip(rgb);
+            assert!((back - rgb).length() < 0.1, "{:?} {:?}", rgb, back);
+        }
+    }
+
+    #[test]
+    fn hero_wavelength_alone_is_unbiased() {
+        // Following only the hero wavelength gives the same color on average
+        let rgb = Vec3::new(0.3, 0.6, 0.2);
+        let samples = 1000;
+        let mut sum = Vec3::empty();
+        for i in 0..samples {
+            let mut wavelengths = Wavelengths::sample((i as f64 + 0.5) / samples as f64);
+            let values = wavelengths.uplift(rgb);
+            wavelengths.secondary_terminated = true;
+            sum += wavelengths.to_rgb(values);
+        }
+        let hero = sum / samples as f64;
+        assert!((hero - round_trip(rgb)).length() < 1e-3, "{:?}", hero);
+    }
+
+    #[test]
+    fn wavelengths_stay_in_range() {
+        for u in [0.0, 0.3, 0.999] {
+            let wavelengths = Wavelengths::sample(u);
+            for lambda in wavelengths.lambdas {
+                assert!((LAMBDA_MIN..LAMBDA_MAX).contains(&lambda));
+            }
+        }
+        install(Some(Wavelengths::sample(0.5)));
+        terminate_secondary();
+        assert!(wavelengths().unwrap().secondary_terminated);
+        install(None);
+        terminate_secondary();
+        assert_eq!(wavelengths(), None);
+    }
+}
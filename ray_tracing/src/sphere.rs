@@ -9,6 +9,7 @@ use crate::interval::Interval;
 use crate::material::Material;
 use crate::orthonormal_basis::OrthonormalBasis;
 use crate::ray::Ray3;
+use crate::sampler::sample_2d;
 use nurbs::vector_3::Vec3;
 
 pub struct Sphere {
@@ -52,7 +53,9 @@ impl Hittable for Sphere {
                 let outward_normal = (hit_record.point - center) / self.radius;
                 hit_record.set_face_normal(r, outward_normal);
                 hit_record.mat = Arc::clone(&self.mat);
+                hit_record.object = HitRecord::object_id(self);
                 Sphere::sphere_uv(outward_normal, &mut hit_record.u, &mut hit_record.v);
+                (hit_record.dpdu, hit_record.dpdv) = self.tangents(outward_normal);
                 true
             }
         }
@@ -129,9 +132,28 @@ impl Sphere {
         *v = theta / PI;
     }
 
+    // The derivatives of the point at `normal` by the u and v of `sphere_uv`, u running east
+    // around the y axis and v from the south pole to the north pole
+    fn tangents(&self, normal: Vec3) -> (Vec3, Vec3) {
+        let radius = self.radius;
+        let sin_theta = (normal.x * normal.x + normal.z * normal.z).sqrt();
+        let dpdu = 2.0 * PI * radius * Vec3::new(normal.z, 0.0, -normal.x);
+        // The poles have no direction of their own, any tangent will do there
+        let dpdv = if sin_theta > 1e-12 {
+            PI * radius
+                * Vec3::new(
+                    -normal.y * normal.x / sin_theta,
+                    sin_theta,
+                    -normal.y * normal.z / sin_theta,
+                )
+        } else {
+            Vec3::empty()
+        };
+        (dpdu, dpdv)
+    }
+
     pub fn random_to_sphere(radius: f64, dist_sq: f64) -> Vec3 {
-        let r1 = rand::random::<f64>();
-        let r2 = rand::random::<f64>();
+        let (r1, r2) = sample_2d();
         let z = 1.0 + r2 * (f64::sqrt(1.0 - ((radius * radius) / dist_sq)) - 1.0);
 
         let z_sqrt = f64::sqrt(1.0 - (z * z));
@@ -142,3 +164,53 @@ impl Sphere {
         Vec3::new(x, y, z)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+
+    #[test]
+    fn tangents_follow_the_uvs() {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let sphere = Sphere::new(center, 2.0, mat);
+        // The point of the sphere at u, v, the inverse of `sphere_uv`
+        let at = |u: f64, v: f64| {
+            let (theta, phi) = (v * PI, u * 2.0 * PI);
+            center
+                + 2.0
+                    * Vec3::new(
+                        -theta.sin() * phi.cos(),
+                        -theta.cos(),
+                        theta.sin() * phi.sin(),
+                    )
+        };
+        for direction in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.3, -0.5, 1.0),
+            Vec3::new(-0.4, 0.6, 1.0),
+        ] {
+            let origin = center - 10.0 * direction;
+            let mut rec = HitRecord::new();
+            let ray = Ray3::new(origin, direction, 0.0);
+            assert!(sphere.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+            let h = 1e-6;
+            let dpdu = (at(rec.u + h, rec.v) - at(rec.u - h, rec.v)) / (2.0 * h);
+            let dpdv = (at(rec.u, rec.v + h) - at(rec.u, rec.v - h)) / (2.0 * h);
+            assert!((at(rec.u, rec.v) - rec.point).length() < 1e-9);
+            assert!(
+                (dpdu - rec.dpdu).length() < 1e-5,
+                "{:?} {:?}",
+                dpdu,
+                rec.dpdu
+            );
+            assert!(
+                (dpdv - rec.dpdv).length() < 1e-5,
+                "{:?} {:?}",
+                dpdv,
+                rec.dpdv
+            );
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::pdf::PDF;
+use crate::sampler::sample_2d;
 use nurbs::vector_3::Vec3;
 use std::f64::consts::FRAC_1_PI;
 
@@ -10,6 +11,7 @@ impl PDF for SpherePDF {
     }
 
     fn generate(&self) -> Vec3 {
-        Vec3::random_unit_vector()
+        let (r1, r2) = sample_2d();
+        Vec3::on_unit_sphere(r1, r2)
     }
 }
@@ -0,0 +1,116 @@
+use crate::sampler::{mix, to_unit, Sampler};
+
+// Jittered stratification. The square of every pair of dimensions is divided into a grid of
+// at least samples_per_pixel cells and every sample takes a different cell, single dimensions
+// are divided into samples_per_pixel strata. Which sample gets which cell is shuffled per
+// pixel and dimension so the dimensions don't correlate.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    x_strata: u32,
+    y_strata: u32,
+    seed: u64,
+    pixel_key: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel(&mut self, pixel: u64) {
+        self.pixel_key = mix(self.seed, pixel);
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn advance_to(&mut self, dimension: u32) {
+        self.dimension = self.dimension.max(dimension);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let key = self.next_key();
+        let stratum = permute(
+            self.index % self.samples_per_pixel,
+            self.samples_per_pixel,
+            key,
+        );
+        (stratum as f64 + self.jitter(key, 0)) / self.samples_per_pixel as f64
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let key = self.next_key();
+        self.dimension += 1;
+        let cells = self.x_strata * self.y_strata;
+        let cell = permute(self.index % cells, cells, key);
+        (
+            ((cell % self.x_strata) as f64 + self.jitter(key, 1)) / self.x_strata as f64,
+            ((cell / self.x_strata) as f64 + self.jitter(key, 2)) / self.y_strata as f64,
+        )
+    }
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> StratifiedSampler {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x_strata = (samples_per_pixel as f64).sqrt().ceil() as u32;
+        StratifiedSampler {
+            samples_per_pixel,
+            x_strata,
+            y_strata: samples_per_pixel.div_ceil(x_strata),
+            seed,
+            pixel_key: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Key of the current dimension, then moves on to the next one
+    fn next_key(&mut self) -> u32 {
+        let key = mix(self.pixel_key, self.dimension as u64) as u32;
+        self.dimension += 1;
+        key
+    }
+
+    // Position of the current sample inside its cell
+    fn jitter(&self, key: u32, axis: u64) -> f64 {
+        to_unit((mix(key as u64 | axis << 32, self.index as u64) >> 32) as u32)
+    }
+}
+
+// Kensler's hashed permutation of 0..length, returns where `index` goes
+fn permute(index: u32, length: u32, key: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // Permute within the next power of two until the result lands inside the range
+    let mut i = index;
+    loop {
+        i ^= key;
+        i = i.wrapping_mul(0xE170_893D);
+        i ^= key >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= key >> 8;
+        i = i.wrapping_mul(0x0929_EB3F);
+        i ^= key >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | key >> 27);
+        i = i.wrapping_mul(0x6935_FA69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74DC_B303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9E50_1CC3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xC860_A3DF);
+        i &= mask;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    (i + key) % length
+}
@@ -0,0 +1,308 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+// Order the tiles of an image are rendered in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileOrder {
+    // Row by row from the top left
+    Scanline,
+    // Outwards from the middle of the image, where the subject usually is
+    Spiral,
+    // Along a Hilbert curve, consecutive tiles are always neighbours which keeps the geometry
+    // they hit in the caches
+    Hilbert,
+}
+
+impl TileOrder {
+    // Looks an order up by the name used in scene files and on the command line
+    pub fn from_name(name: &str) -> Option<TileOrder> {
+        match name {
+            "scanline" => Some(TileOrder::Scanline),
+            "spiral" => Some(TileOrder::Spiral),
+            "hilbert" => Some(TileOrder::Hilbert),
+            _ => None,
+        }
+    }
+}
+
+// A rectangle of pixels, the unit of work handed to a thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+}
+
+// The tiles of an image in the order they are rendered. Per pixel data is stored tile by tile
+// in this order, row by row inside a tile, so the pixels of every tile are one contiguous slice
+// that a thread can own while it renders.
+pub struct TileLayout {
+    tiles: Vec<Tile>,
+}
+
+impl TileLayout {
+    pub fn new(width: u32, height: u32, tile_size: u32, order: TileOrder) -> TileLayout {
+        let size = tile_size.max(1);
+        let columns = width.div_ceil(size);
+        let rows = height.div_ceil(size);
+        let mut grid: Vec<(u32, u32)> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect();
+
+        match order {
+            TileOrder::Scanline => {}
+            TileOrder::Spiral => {
+                // By the ring around the middle tile, then by angle within the ring
+                let middle = ((columns - 1) as f64 / 2.0, (rows - 1) as f64 / 2.0);
+                let key = |&(column, row): &(u32, u32)| {
+                    let dx = column as f64 - middle.0;
+                    let dy = row as f64 - middle.1;
+                    (dx.abs().max(dy.abs()), dy.atan2(dx))
+                };
+                grid.sort_by(|a, b| {
+                    let (a, b) = (key(a), key(b));
+                    a.0.partial_cmp(&b.0)
+                        .unwrap_or(Ordering::Equal)
+                        .then(a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+                });
+            }
+            TileOrder::Hilbert => {
+                let side = columns.max(rows).next_power_of_two();
+                grid.sort_by_key(|&(column, row)| hilbert_index(side, column, row));
+            }
+        }
+
+        let tiles = grid
+            .into_iter()
+            .map(|(column, row)| {
+                let (x, y) = (column * size, row * size);
+                Tile {
+                    x,
+                    y,
+                    width: size.min(width - x),
+                    height: size.min(height - y),
+                }
+            })
+            .collect();
+        TileLayout { tiles }
+    }
+
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    // Splits per pixel data stored in tile order into one slice per tile
+    pub fn split<'a, T>(&self, mut data: &'a [T]) -> Vec<&'a [T]> {
+        self.tiles
+            .iter()
+            .map(|tile| {
+                let (tile_data, rest) = data.split_at(tile.pixel_count());
+                data = rest;
+                tile_data
+            })
+            .collect()
+    }
+
+    pub fn split_mut<'a, T>(&self, mut data: &'a mut [T]) -> Vec<&'a mut [T]> {
+        self.tiles
+            .iter()
+            .map(|tile| {
+                let (tile_data, rest) = std::mem::take(&mut data).split_at_mut(tile.pixel_count());
+                data = rest;
+                tile_data
+            })
+            .collect()
+    }
+
+    // Image coordinates of every pixel, in storage order
+    pub fn positions(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.tiles.iter().flat_map(|tile| {
+            (tile.y..tile.y + tile.height)
+                .flat_map(move |y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+        })
+    }
+}
+
+// Position of cell x, y along the Hilbert curve that fills a `side` by `side` grid, `side`
+// being a power of two
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so the curve inside it starts where the last one ended
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+// Calls `work` once for every item on `threads` threads. The items are dealt out to the threads
+// in turn, so the threads work through them together in the order they come in, and a thread
+// that runs out steals from the end of another thread's share, so no thread sits idle while
+// there is work left however uneven the items are. `progress` is called on the calling thread
+// with the number of finished items after each one. With a single thread everything runs on
+// the calling thread.
+pub fn run<T: Send>(
+    items: Vec<T>,
+    threads: u32,
+    work: impl Fn(T) + Sync,
+    progress: &mut dyn FnMut(usize),
+) {
+    let total = items.len();
+    let threads = (threads.max(1) as usize).min(total.max(1));
+    if threads == 1 {
+        for (index, item) in items.into_iter().enumerate() {
+            work(item);
+            progress(index + 1);
+        }
+        return;
+    }
+
+    let queues: Vec<Mutex<VecDeque<T>>> = deal(items, threads)
+        .into_iter()
+        .map(Mutex::new)
+        .collect();
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for own in 0..threads {
+            let sender = sender.clone();
+            let (queues, work) = (&queues, &work);
+            scope.spawn(move || {
+                while let Some(item) = next_item(queues, own) {
+                    work(item);
+                    let _ = sender.send(());
+                }
+            });
+        }
+        drop(sender);
+
+        // Ends early if a worker panics, the scope then passes the panic on
+        for done in 1..=total {
+            if receiver.recv().is_err() {
+                break;
+            }
+            progress(done);
+        }
+    });
+}
+
+// Item i goes to thread i % threads, keeping the order within every share
+fn deal<T>(items: Vec<T>, threads: usize) -> Vec<VecDeque<T>> {
+    let mut shares: Vec<VecDeque<T>> = (0..threads).map(|_| VecDeque::new()).collect();
+    for (index, item) in items.into_iter().enumerate() {
+        shares[index % threads].push_back(item);
+    }
+    shares
+}
+
+// The next item of thread `own`'s share, or one stolen from the end of another thread's
+fn next_item<T>(queues: &[Mutex<VecDeque<T>>], own: usize) -> Option<T> {
+    if let Some(item) = queues[own].lock().unwrap().pop_front() {
+        return Some(item);
+    }
+    (1..queues.len())
+        .map(|offset| (own + offset) % queues.len())
+        .find_map(|victim| queues[victim].lock().unwrap().pop_back())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let layout = TileLayout::new(37, 21, 8, order);
+            assert_eq!(layout.tiles().len(), 5 * 3);
+            let mut covered = vec![0; 37 * 21];
+            for (x, y) in layout.positions() {
+                covered[(y * 37 + x) as usize] += 1;
+            }
+            assert!(covered.iter().all(|&count| count == 1), "{:?}", order);
+
+            let data: Vec<usize> = (0..37 * 21).collect();
+            let slices = layout.split(&data);
+            for (tile, slice) in layout.tiles().iter().zip(slices) {
+                assert_eq!(slice.len(), tile.pixel_count());
+            }
+        }
+
+        // The spiral starts in the middle, the Hilbert curve only ever steps to a neighbour
+        let spiral = TileLayout::new(5, 5, 1, TileOrder::Spiral);
+        assert_eq!((spiral.tiles()[0].x, spiral.tiles()[0].y), (2, 2));
+        let hilbert = TileLayout::new(64, 64, 8, TileOrder::Hilbert);
+        for pair in hilbert.tiles().windows(2) {
+            let step = pair[0].x.abs_diff(pair[1].x) + pair[0].y.abs_diff(pair[1].y);
+            assert_eq!(step, 8);
+        }
+    }
+
+    #[test]
+    fn runs_every_item_once() {
+        for threads in [1, 3, 8] {
+            let mut counts = [0; 50];
+            let items: Vec<&mut u32> = counts.iter_mut().collect();
+            let mut reported = Vec::new();
+            run(items, threads, |count| *count += 1, &mut |done| {
+                reported.push(done)
+            });
+            assert!(counts.iter().all(|&count| count == 1));
+            assert_eq!(reported, (1..=50).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn threads_start_at_the_front() {
+        // Every thread starts at the front of the order, so a spiral starts in the middle on
+        // all of them
+        let shares = deal((0..10).collect(), 4);
+        let fronts: Vec<u32> = shares.iter().map(|share| share[0]).collect();
+        assert_eq!(fronts, [0, 1, 2, 3]);
+        assert_eq!(shares[1], [1, 5, 9]);
+        assert_eq!(shares[3], [3, 7]);
+    }
+
+    #[test]
+    fn idle_threads_steal_work() {
+        // The first item blocks until every other one is done, which can only happen if the
+        // rest of the first thread's share is stolen
+        let finished = AtomicUsize::new(0);
+        run(
+            (0..16).collect(),
+            2,
+            |item: u32| {
+                if item == 0 {
+                    let start = Instant::now();
+                    while finished.load(Ordering::SeqCst) < 15 {
+                        assert!(start.elapsed() < Duration::from_secs(10), "nothing stolen");
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                finished.fetch_add(1, Ordering::SeqCst);
+            },
+            &mut |_| {},
+        );
+        assert_eq!(finished.load(Ordering::SeqCst), 16);
+    }
+}
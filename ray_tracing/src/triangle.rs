@@ -0,0 +1,324 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray3;
+use crate::sampler::sample_2d;
+use nurbs::vector_3::Vec3;
+use std::sync::Arc;
+
+// Vertex data that can be shared between the triangles of a mesh. The normals and uvs, when
+// present, are indexed the same way as the positions.
+pub struct MeshBuffers {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<(f64, f64)>>,
+}
+
+pub struct Triangle {
+    buffers: Arc<MeshBuffers>,
+    indices: [usize; 3],
+    mat: Arc<dyn Material + Sync + Send>,
+    bbox: AABB,
+    normal: Vec3,
+    area: f64,
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        let [p0, p1, p2] = self.vertices();
+
+        // Moller-Trumbore intersection
+        let edge_1 = p1 - p0;
+        let edge_2 = p2 - p0;
+        let p_vec = ray_in.direction().cross(&edge_2);
+        let det = edge_1.dot(&p_vec);
+        if det.abs() < 1e-12 {
+            return false;
+        }
+        let inv_det = 1.0 / det;
+
+        let t_vec = ray_in.origin() - p0;
+        let b1 = t_vec.dot(&p_vec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return false;
+        }
+
+        let q_vec = t_vec.cross(&edge_1);
+        let b2 = ray_in.direction().dot(&q_vec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return false;
+        }
+
+        let hit_time = edge_2.dot(&q_vec) * inv_det;
+        if !time.surrounds(hit_time) {
+            return false;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        hit_record.time = hit_time;
+        hit_record.point = ray_in.at(hit_time);
+        hit_record.mat = Arc::clone(&self.mat);
+        hit_record.object = HitRecord::object_id(self);
+        let [uv0, uv1, uv2] = match &self.buffers.uvs {
+            Some(uvs) => self.indices.map(|index| uvs[index]),
+            None => [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+        };
+        hit_record.u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+        hit_record.v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+
+        // Solve p0 - p2 and p1 - p2 for the derivatives by u and v
+        let (du02, dv02) = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+        let (du12, dv12) = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+        let uv_det = du02 * dv12 - dv02 * du12;
+        (hit_record.dpdu, hit_record.dpdv) = if uv_det.abs() > 1e-12 {
+            let (dp02, dp12) = (p0 - p2, p1 - p2);
+            (
+                (dv12 * dp02 - dv02 * dp12) / uv_det,
+                (du02 * dp12 - du12 * dp02) / uv_det,
+            )
+        } else {
+            (Vec3::empty(), Vec3::empty())
+        };
+
+        // The face is decided by the geometric normal, the interpolated normal is only used
+        // for shading
+        hit_record.set_face_normal(ray_in, self.normal);
+        if let Some(normals) = &self.buffers.normals {
+            let [n0, n1, n2] = self.indices.map(|index| normals[index]);
+            let shading_normal = (b0 * n0 + b1 * n1 + b2 * n2).unit_vector();
+            hit_record.normal = if hit_record.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::copy(&self.bbox)
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        let ray = Ray3::new(*origin, *direction, 0.0);
+        if self.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec) {
+            let dist_sq = rec.time * rec.time * direction.length_squared();
+            let cosine = f64::abs(direction.dot(&self.normal)) / direction.length();
+
+            dist_sq / (cosine * self.area)
+        } else {
+            0.0
+        }
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.random_point() - *origin
+    }
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, mat: Arc<dyn Material + Sync + Send>) -> Triangle {
+        Triangle::new_shaded([a, b, c], None, None, mat)
+    }
+
+    // A single triangle with optional per-vertex normals and texture coordinates
+    pub fn new_shaded(
+        vertices: [Vec3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f64, f64); 3]>,
+        mat: Arc<dyn Material + Sync + Send>,
+    ) -> Triangle {
+        let buffers = MeshBuffers {
+            positions: vertices.to_vec(),
+            normals: normals.map(|normals| normals.to_vec()),
+            uvs: uvs.map(|uvs| uvs.to_vec()),
+        };
+        Triangle::from_mesh(Arc::new(buffers), [0, 1, 2], mat)
+    }
+
+    // A triangle referencing three vertices of a shared buffer
+    pub fn from_mesh(
+        buffers: Arc<MeshBuffers>,
+        indices: [usize; 3],
+        mat: Arc<dyn Material + Sync + Send>,
+    ) -> Triangle {
+        let [p0, p1, p2] = indices.map(|index| buffers.positions[index]);
+        let bbox = AABB::from_aabbs(&AABB::from_vec3s(p0, p1), &AABB::from_vec3s(p0, p2)).pad();
+        let n = (p1 - p0).cross(&(p2 - p0));
+        let area = 0.5 * n.length();
+        let normal = n.unit_vector();
+        Triangle {
+            buffers,
+            indices,
+            mat,
+            bbox,
+            normal,
+            area,
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    pub fn vertices(&self) -> [Vec3; 3] {
+        self.indices.map(|index| self.buffers.positions[index])
+    }
+
+    // A uniformly distributed point on the triangle
+    pub fn random_point(&self) -> Vec3 {
+        let [p0, p1, p2] = self.vertices();
+        let (r1, r2) = sample_2d();
+        let su = r1.sqrt();
+        let b1 = su * (1.0 - r2);
+        let b2 = su - b1;
+        p0 + b1 * (p1 - p0) + b2 * (p2 - p0)
+    }
+}
+
+// A closed prism made of the triangle a, b, c swept along `extrusion`, with the normals facing
+// out so it can hold a dielectric
+pub fn prism(
+    a: Vec3,
+    b: Vec3,
+    c: Vec3,
+    extrusion: Vec3,
+    mat: Arc<dyn Material + Sync + Send>,
+) -> Arc<HittableList> {
+    let [d, e, f] = [a, b, c].map(|vertex| vertex + extrusion);
+    let centroid = (a + b + c) / 3.0 + extrusion / 2.0;
+    let faces = [
+        [a, b, c],
+        [d, e, f],
+        [a, b, e],
+        [a, e, d],
+        [b, c, f],
+        [b, f, e],
+        [c, a, d],
+        [c, d, f],
+    ];
+    let mut triangles = HittableList::new();
+    for [p0, p1, p2] in faces {
+        let outward = (p1 - p0)
+            .cross(&(p2 - p0))
+            .dot(&((p0 + p1 + p2) / 3.0 - centroid))
+            > 0.0;
+        let vertices = if outward { [p0, p1, p2] } else { [p0, p2, p1] };
+        triangles.add(Arc::new(Triangle::new_shaded(
+            vertices,
+            None,
+            None,
+            Arc::clone(&mat),
+        )));
+    }
+    Arc::new(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+
+    fn unit_triangle() -> Triangle {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        Triangle::new_shaded(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            None,
+            Some([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]),
+            mat,
+        )
+    }
+
+    #[test]
+    fn hit_barycentrics() {
+        let triangle = unit_triangle();
+        let ray = Ray3::new(Vec3::new(0.25, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(triangle.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.time - 2.0).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12);
+        assert!((rec.v - 0.5).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal.z - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn tangents_follow_the_uvs() {
+        // uvs rotated a quarter turn and stretched to half size, so u runs along y
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let triangle = Triangle::new_shaded(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            None,
+            Some([(0.0, 0.0), (0.0, -0.5), (0.5, 0.0)]),
+            mat,
+        );
+        let ray = Ray3::new(Vec3::new(0.25, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(triangle.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.dpdu - Vec3::new(0.0, 2.0, 0.0)).length() < 1e-12);
+        assert!((rec.dpdv - Vec3::new(-2.0, 0.0, 0.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn miss_outside() {
+        let triangle = unit_triangle();
+        let ray = Ray3::new(Vec3::new(0.75, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(!triangle.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+    }
+
+    #[test]
+    fn pdf_matches_solid_angle() {
+        // Seen from far away the solid angle is roughly area / distance^2
+        let triangle = unit_triangle();
+        let origin = Vec3::new(0.2, 0.2, 100.0);
+        let pdf = triangle.pdf_value(&origin, &Vec3::new(0.0, 0.0, -1.0));
+        assert!((pdf - 100.0 * 100.0 / 0.5).abs() < 1e-6);
+        for _ in 0..100 {
+            let direction = triangle.random(&origin);
+            assert!(triangle.pdf_value(&origin, &direction) > 0.0);
+        }
+    }
+
+    #[test]
+    fn prism_faces_out() {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let prism = prism(
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.5, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            mat,
+        );
+        let inside = Vec3::new(0.0, 0.5, 1.0);
+        for direction in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.2, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.1, 0.0, 1.0),
+            Vec3::new(0.0, 0.1, -1.0),
+        ] {
+            // Rays leaving from the inside hit back faces, rays coming back from outside front
+            // faces
+            let mut rec = HitRecord::new();
+            let ray = Ray3::new(inside, direction, 0.0);
+            assert!(prism.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+            assert!(!rec.front_face);
+            let ray = Ray3::new(inside + 10.0 * direction, -direction, 0.0);
+            assert!(prism.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+            assert!(rec.front_face);
+        }
+    }
+}
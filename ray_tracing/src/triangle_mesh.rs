@@ -0,0 +1,210 @@
+use crate::aabb::AABB;
+use crate::bvh_node::BVHStats;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::linear_bvh::LinearBVH;
+use crate::material::Material;
+use crate::ray::Ray3;
+use crate::sampler::sample_1d;
+use crate::triangle::{MeshBuffers, Triangle};
+use nurbs::vector_3::Vec3;
+use std::fmt;
+use std::sync::Arc;
+
+// Why a mesh couldn't be built from its buffers and indices
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeshError {
+    NoTriangles,
+    // A face refers to a vertex that isn't in every buffer
+    IndexOutOfRange { face: usize, index: usize },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::NoTriangles => write!(f, "a triangle mesh needs at least one triangle"),
+            MeshError::IndexOutOfRange { face, index } => {
+                write!(f, "face {} refers to the missing vertex {}", face, index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+pub struct TriangleMesh {
+    triangles: Vec<Arc<Triangle>>,
+    bvh: LinearBVH,
+    // Running total of the triangle areas, used to pick triangles proportional to their area
+    area_cdf: Vec<f64>,
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        if self.bvh.hit(ray_in, time, hit_record) {
+            hit_record.object = HitRecord::object_id(self);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bvh.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        let ray = Ray3::new(*origin, *direction, 0.0);
+        match self
+            .bvh
+            .hit_object(&ray, Interval::new(0.001, f64::INFINITY), &mut rec)
+        {
+            // The density of the triangle itself, which uses its geometric normal rather than
+            // the shading normal of the hit, times the chance of picking it
+            Some(index) => {
+                let triangle = &self.triangles[index];
+                triangle.pdf_value(origin, direction) * triangle.area() / self.area()
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        let target = sample_1d() * self.area();
+        let index = self
+            .area_cdf
+            .partition_point(|&area| area < target)
+            .min(self.triangles.len() - 1);
+        self.triangles[index].random(origin)
+    }
+
+    fn bvh_stats(&self) -> Option<BVHStats> {
+        self.bvh.bvh_stats()
+    }
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material + Sync + Send>,
+    ) -> Result<TriangleMesh, MeshError> {
+        let buffers = MeshBuffers {
+            positions,
+            normals: None,
+            uvs: None,
+        };
+        TriangleMesh::from_buffers(Arc::new(buffers), indices, mat)
+    }
+
+    // Every triangle shares `buffers`, only the indices are stored per triangle. Fails
+    // without any triangles or when an index is past the end of one of the buffers.
+    pub fn from_buffers(
+        buffers: Arc<MeshBuffers>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material + Sync + Send>,
+    ) -> Result<TriangleMesh, MeshError> {
+        if indices.is_empty() {
+            return Err(MeshError::NoTriangles);
+        }
+        let vertices = [
+            Some(buffers.positions.len()),
+            buffers.normals.as_ref().map(Vec::len),
+            buffers.uvs.as_ref().map(Vec::len),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap();
+        for (face, corners) in indices.iter().enumerate() {
+            if let Some(&index) = corners.iter().find(|&&index| index >= vertices) {
+                return Err(MeshError::IndexOutOfRange { face, index });
+            }
+        }
+
+        let mut triangles = Vec::with_capacity(indices.len());
+        let mut area_cdf = Vec::with_capacity(indices.len());
+        let mut list = HittableList::new();
+        let mut total_area = 0.0;
+        for face in indices {
+            let triangle = Arc::new(Triangle::from_mesh(
+                Arc::clone(&buffers),
+                face,
+                Arc::clone(&mat),
+            ));
+            total_area += triangle.area();
+            area_cdf.push(total_area);
+            list.add(Arc::clone(&triangle) as Arc<dyn Hittable + Sync + Send>);
+            triangles.push(triangle);
+        }
+
+        Ok(TriangleMesh {
+            triangles,
+            bvh: LinearBVH::from_list(&list),
+            area_cdf,
+        })
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn area(&self) -> f64 {
+        *self.area_cdf.last().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+
+    fn material() -> Arc<dyn Material + Sync + Send> {
+        Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn pdf_ignores_shading_normals() {
+        // A unit square at z = 1 whose vertex normals lean far away from its face
+        let positions = vec![
+            Vec3::new(-0.5, -0.5, 1.0),
+            Vec3::new(0.5, -0.5, 1.0),
+            Vec3::new(0.5, 0.5, 1.0),
+            Vec3::new(-0.5, 0.5, 1.0),
+        ];
+        let indices = vec![[0, 1, 2], [0, 2, 3]];
+        let flat = TriangleMesh::new(positions.clone(), indices.clone(), material()).unwrap();
+        let buffers = MeshBuffers {
+            positions,
+            normals: Some(vec![Vec3::new(1.0, 0.0, 0.2).unit_vector(); 4]),
+            uvs: None,
+        };
+        let smooth = TriangleMesh::from_buffers(Arc::new(buffers), indices, material()).unwrap();
+
+        let (origin, direction) = (Vec3::empty(), Vec3::new(0.1, 0.2, 1.0));
+        let expected = direction.length_squared() / (1.0 / direction.length());
+        for mesh in [flat, smooth] {
+            let pdf = mesh.pdf_value(&origin, &direction);
+            assert!((pdf - expected).abs() < 1e-9, "{} {}", pdf, expected);
+        }
+    }
+
+    #[test]
+    fn bad_indices_are_errors() {
+        let positions = vec![
+            Vec3::empty(),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        assert_eq!(
+            TriangleMesh::new(positions.clone(), Vec::new(), material()).err(),
+            Some(MeshError::NoTriangles)
+        );
+        assert_eq!(
+            TriangleMesh::new(positions, vec![[0, 1, 2], [0, 3, 2]], material()).err(),
+            Some(MeshError::IndexOutOfRange { face: 1, index: 3 })
+        );
+    }
+}